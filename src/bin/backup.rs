@@ -0,0 +1,96 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use data_parser::database::*;
+
+const USAGE: &str = "Usage: backup <create | restore | show> <archive> \
+    [--tables users,hotels,reviews,user_reviews] [--batch-size N]";
+
+enum Command {
+    Create,
+    Restore,
+    Show,
+}
+
+struct Args {
+    command: Command,
+    archive: PathBuf,
+    opts: RestoreOptions,
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let (command, archive, flags) = match args {
+        [command, archive, flags @ ..] => (command, PathBuf::from(archive), flags),
+        _ => return Err("Didn't get a command and an archive".to_string()),
+    };
+    let command = match command.as_str() {
+        "create" => Command::Create,
+        "restore" => Command::Restore,
+        "show" => Command::Show,
+        _ => return Err(format!("Unknown command '{command}'")),
+    };
+    if flags.len() % 2 != 0 {
+        return Err(format!("Missing value for '{}'", flags[flags.len() - 1]));
+    }
+
+    let mut opts = RestoreOptions::default();
+    for pair in flags.chunks(2) {
+        let (flag, value) = (pair[0].as_str(), pair[1].as_str());
+        match flag {
+            "--tables" => opts.tables = value.split(',')
+                .map(str::parse).collect::<Result<_, _>>()?,
+            "--batch-size" => opts.batch_size = value.parse()
+                .map_err(|_| format!("Invalid number '{value}' for '{flag}'"))?,
+            _ => return Err(format!("Unknown option '{flag}'")),
+        }
+    }
+
+    Ok(Args { command, archive, opts })
+}
+
+#[actix_web::main]
+async fn main() {
+    // cargo r --bin backup -- create hotels.tar.gz
+    // DATABASE_URL=sqlite:copy.db cargo r --features sqlite --bin backup -- restore hotels.tar.gz
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Args { command, archive, opts } = parse(&args).unwrap_or_else(|err| {
+        println!("\n{err}\n{USAGE}\n");
+        process::exit(1);
+    });
+
+    if let Command::Show = command {
+        match read_manifest(&archive) {
+            Ok(manifest) => println!("{manifest}"),
+            Err(e) => {
+                println!("\n{e}\n");
+                process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let url = env::var("DATABASE_URL").unwrap_or_else(|_| {
+        println!("\nNo database, set DATABASE_URL\n");
+        process::exit(1);
+    });
+    let pool = DbPool::connect(&url, 1)
+        .await
+        .unwrap_or_else(|err| {
+            println!("\n{err}\n");
+            process::exit(1);
+        });
+
+    let result = match command {
+        Command::Create => create_backup(&pool, &archive, &opts.tables).await
+            .map(|manifest| println!("Wrote '{}'\n{manifest}", archive.display())),
+        Command::Restore => restore_backup(&pool, &archive, &opts).await
+            .map(|report| println!("{report}")),
+        Command::Show => Ok(()),
+    };
+
+    if let Err(e) = result {
+        println!("\n{e}\n");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,155 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use data_parser::{database::*, generator::*};
+
+const USAGE: &str = "Usage: generate <out_dir> [--seed N] [--hotels N] \
+    [--reviews N | MIN-MAX | ~MEAN] [--words MIN-MAX] [--from DATE] \
+    [--to DATE] [--users N] [--user-reviews MIN-MAX] [--database URL]";
+
+const HOTELS_PER_FILE: usize = 1000;
+const REVIEWS_PER_PAGE: usize = 50;
+
+struct Args {
+    out_dir: PathBuf,
+    opts: GeneratorOptions,
+    database: Option<String>,
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let (out_dir, flags) = match args.split_first() {
+        Some((dir, flags)) if !dir.starts_with("--") => (PathBuf::from(dir), flags),
+        _ => return Err("Didn't get an output directory".to_string()),
+    };
+    if flags.len() % 2 != 0 {
+        return Err(format!("Missing value for '{}'", flags[flags.len() - 1]));
+    }
+
+    let mut opts = GeneratorOptions::default();
+    let mut database = None;
+    for pair in flags.chunks(2) {
+        let (flag, value) = (pair[0].as_str(), pair[1].as_str());
+        match flag {
+            "--seed" => opts.seed = number(flag, value)?,
+            "--hotels" => opts.hotels = number(flag, value)?,
+            "--reviews" => opts.reviews_per_hotel = review_count(value)?,
+            "--words" => opts.text_words = range(flag, value)?,
+            "--from" => opts.from = date(flag, value)?,
+            "--to" => opts.to = date(flag, value)?,
+            "--users" => opts.users = number(flag, value)?,
+            "--user-reviews" => opts.user_reviews_per_user = range(flag, value)?,
+            "--database" => database = Some(value.to_string()),
+            _ => return Err(format!("Unknown option '{flag}'")),
+        }
+    }
+    if opts.from >= opts.to {
+        return Err("'--from' must be before '--to'".to_string());
+    }
+
+    Ok(Args { out_dir, opts, database })
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number '{value}' for '{flag}'"))
+}
+
+fn range(flag: &str, value: &str) -> Result<(usize, usize), String> {
+    match value.split_once('-') {
+        Some((min, max)) => Ok((number(flag, min)?, number(flag, max)?)),
+        None => number(flag, value).map(|n| (n, n)),
+    }
+}
+
+fn review_count(value: &str) -> Result<ReviewCount, String> {
+    if let Some(mean) = value.strip_prefix('~') {
+        return number("--reviews", mean).map(|mean| ReviewCount::Exponential { mean });
+    }
+    match range("--reviews", value)? {
+        (min, max) if min == max => Ok(ReviewCount::Fixed(min)),
+        (min, max) => Ok(ReviewCount::Uniform { min, max }),
+    }
+}
+
+/// Accepts '2015-06-01' as well as full RFC 3339 times.
+fn date(flag: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    value.parse().map_err(|_| format!("Invalid date '{value}' for '{flag}'"))
+}
+
+#[actix_web::main]
+async fn main() {
+    // cargo r --bin generate -- data/generated --seed 7 --hotels 10000 --reviews ~40
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Args { out_dir, opts, database } = parse(&args).unwrap_or_else(|err| {
+        println!("\n{err}\n{USAGE}\n");
+        process::exit(1);
+    });
+
+    let mut generator = Generator::new(opts);
+    let hotels = generator.hotels();
+    let reviews = generator.reviews(&hotels);
+
+    let written = write_hotels(&out_dir.join("hotels"), &hotels, HOTELS_PER_FILE)
+        .and_then(|h| {
+            write_reviews(&out_dir.join("reviews"), &reviews, REVIEWS_PER_PAGE)
+                .map(|r| (h, r))
+        });
+    match written {
+        Ok((h, r)) => println!("Wrote {} hotels in {h} files and {} reviews \
+            in {r} files to '{}'", hotels.len(),
+            reviews.values().map(Vec::len).sum::<usize>(), out_dir.display()),
+        Err(e) => {
+            println!("\nError writing files: {e}\n");
+            process::exit(1);
+        },
+    }
+
+    let url = match database {
+        Some(url) => url,
+        None => return,
+    };
+    if let Err(e) = seed_database(&url, generator, hotels, reviews).await {
+        println!("\n{e}\n");
+        process::exit(1);
+    }
+}
+
+/// Upserts the generated hotels and reviews, then seeds users on top.
+async fn seed_database(
+    url: &str, mut generator: Generator, hotels: Vec<Hotel>,
+    reviews: std::collections::HashMap<i32, Vec<Review>>,
+) -> DbResult<()> {
+    let pool = DbPool::connect_with_retry(url, 4, 5, Duration::from_secs(1)).await?;
+    match check_schema(&pool).await? {
+        SchemaCheck::UpToDate => {},
+        check => {
+            println!("\n{check}, run the 'migrate' binary first\n");
+            process::exit(1);
+        },
+    }
+
+    let opts = LoadOptions { mode: LoadMode::Upsert, ..Default::default() };
+    let hotel_ids: Vec<i32> = hotels.iter().map(|h| h.hotel_id).collect();
+    let hotels = hotels.into_iter().map(|h| (h.hotel_id, h)).collect();
+    for report in [
+        pool.add_hotels_data(hotels, &opts).await?,
+        pool.add_reviews_data(reviews, &opts).await?,
+    ] {
+        println!("{report}");
+        for e in &report.errors {
+            eprintln!("Error loading '{}': {e}", report.table);
+        }
+    }
+
+    let seeded = seed_users(
+        &mut generator, &pool.app_state(), &hotel_ids
+    ).await?;
+    println!("{seeded}");
+
+    Ok(())
+}
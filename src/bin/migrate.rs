@@ -0,0 +1,73 @@
+use std::env;
+use std::process;
+
+use data_parser::database::*;
+
+const USAGE: &str = "Usage: migrate <up [version] | down [steps] | status>";
+
+enum Command {
+    Up(Option<i64>),
+    Down(usize),
+    Status,
+}
+
+fn parse(args: &[String]) -> Option<Command> {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["up"] => Some(Command::Up(None)),
+        ["up", version] => version.parse().ok().map(|v| Command::Up(Some(v))),
+        ["down"] => Some(Command::Down(1)),
+        ["down", steps] => steps.parse().ok().map(Command::Down),
+        ["status"] => Some(Command::Status),
+        _ => None,
+    }
+}
+
+#[actix_web::main]
+async fn main() {
+    // cargo r --bin migrate -- up
+    // DATABASE_URL=sqlite:hotels.db cargo r --features sqlite --bin migrate -- up
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = parse(&args).unwrap_or_else(|| {
+        println!("\n{USAGE}\n");
+        process::exit(1);
+    });
+
+    let url = env::var("DATABASE_URL").unwrap_or_else(|_| {
+        println!("\nNo database, set DATABASE_URL\n");
+        process::exit(1);
+    });
+    let pool = DbPool::connect(&url, 1)
+        .await
+        .unwrap_or_else(|err| {
+            println!("\n{err}\n");
+            process::exit(1);
+        });
+
+    let result = match command {
+        Command::Up(target) => migrate_up(&pool, target).await
+            .map(|vs| report("Applied", vs)),
+        Command::Down(steps) => migrate_down(&pool, steps).await
+            .map(|vs| report("Reverted", vs)),
+        Command::Status => migration_status(&pool).await.map(|status| {
+            for s in status {
+                println!("{s}");
+            }
+        }),
+    };
+
+    if let Err(e) = result {
+        println!("\nError running migrations: {e}\n");
+        process::exit(1);
+    }
+}
+
+fn report(action: &str, versions: Vec<i64>) {
+    match versions.is_empty() {
+        true => println!("Nothing to do"),
+        false => {
+            for v in versions {
+                println!("{action} migration {v}");
+            }
+        }
+    }
+}
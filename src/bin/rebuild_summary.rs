@@ -0,0 +1,49 @@
+use std::env;
+use std::process;
+
+use data_parser::database::*;
+
+#[actix_web::main]
+async fn main() {
+    // cargo r --bin rebuild_summary
+    // DATABASE_URL=sqlite:hotels.db cargo r --features sqlite --bin rebuild_summary
+    let url = env::var("DATABASE_URL").unwrap_or_else(|_| {
+        println!("\nNo database, set DATABASE_URL\n");
+        process::exit(1);
+    });
+    let pool = DbPool::connect(&url, 1)
+        .await
+        .unwrap_or_else(|err| {
+            println!("\n{err}\n");
+            process::exit(1);
+        });
+
+    match check_schema(&pool).await {
+        Ok(SchemaCheck::UpToDate) => {},
+        Ok(check) => {
+            println!("\n{check}, run the 'migrate' binary first\n");
+            process::exit(1);
+        },
+        Err(e) => {
+            println!("\nError checking the schema: {e}\n");
+            process::exit(1);
+        },
+    }
+
+    let drift = pool.app_state()
+        .ratings.rebuild_rating_summaries().await
+        .unwrap_or_else(|err| {
+            println!("\nError rebuilding rating summaries: {err}\n");
+            process::exit(1);
+        });
+
+    match drift.is_empty() {
+        true => println!("Rating summaries were up to date"),
+        false => {
+            for d in &drift {
+                println!("{d}");
+            }
+            println!("Fixed the rating summaries of {} hotels", drift.len());
+        }
+    }
+}
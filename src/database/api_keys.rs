@@ -0,0 +1,168 @@
+use std::fmt;
+use std::str::FromStr;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::database::error::DbResult;
+
+/**
+ * What an API key may do. Reading is public for users, keys need a scope for
+ * it too; the others open the routes a role would for a user.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Scope {
+    /// Reading hotels, their reviews and searching them.
+    HotelsRead,
+    /// Reading user reviews.
+    ReviewsRead,
+    /// Adding user reviews as the key's creator, and deleting any, like
+    /// moderators.
+    ReviewsWrite,
+    /// Listing users, like admins.
+    UsersRead,
+    /// Updating and deleting any user, like admins.
+    UsersWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [
+        Scope::HotelsRead, Scope::ReviewsRead, Scope::ReviewsWrite, Scope::UsersRead,
+        Scope::UsersWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::HotelsRead => "hotels:read",
+            Scope::ReviewsRead => "reviews:read",
+            Scope::ReviewsWrite => "reviews:write",
+            Scope::UsersRead => "users:read",
+            Scope::UsersWrite => "users:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == s).ok_or_else(|| {
+            let names: Vec<&str> = Scope::ALL.iter().map(Scope::as_str).collect();
+            format!("Unknown scope '{s}', expected one of {}", names.join(", "))
+        })
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> String {
+        scope.as_str().to_string()
+    }
+}
+
+/// The scopes of a key, stored space separated in 'api_keys.scopes'.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scopes(pub Vec<Scope>);
+
+impl Scopes {
+    pub fn contains(&self, scope: Scope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.0.iter().map(Scope::as_str).collect();
+        f.write_str(&names.join(" "))
+    }
+}
+
+// Lets 'sqlx::FromRow' read the VARCHAR column
+impl TryFrom<String> for Scopes {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.split_whitespace().map(str::parse).collect::<Result<_, _>>().map(Scopes)
+    }
+}
+
+/**
+ * A row of 'api_keys', the credentials of machine clients. Keys look like
+ * 'hk_<prefix>_<secret>': the prefix finds the row and only a hash of the
+ * secret is stored.
+ *
+ * # Fields:
+ * - 'created_by': The admin who made the key, 'None' once they're deleted.
+ *   Reviews added with the key are theirs.
+ * - 'expires_at': 'None' for keys that don't expire.
+ * - 'last_used_at': Updated at most once a minute, see
+ *   'LAST_USED_RESOLUTION_SECS'.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+    #[sqlx(try_from = "String")]
+    pub scopes: Scopes,
+    pub created_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// How stale 'ApiKey::last_used_at' may get, so busy keys don't write on
+/// every request.
+pub const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+impl ApiKey {
+    /// Whether the key is accepted.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    /// Whether 'last_used_at' is stale enough to be updated.
+    pub fn needs_touch(&self, now: DateTime<Utc>) -> bool {
+        self.last_used_at.is_none_or(|used| {
+            (now - used).num_seconds() >= LAST_USED_RESOLUTION_SECS
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewApiKey {
+    pub name: String,
+    pub prefix: String,
+    pub secret_hash: String,
+    pub scopes: Scopes,
+    pub created_by: i32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn add_api_key(&self, key: &NewApiKey) -> DbResult<()>;
+    async fn get_api_key(&self, prefix: &str) -> DbResult<Option<ApiKey>>;
+    async fn get_all_api_keys(&self) -> DbResult<Vec<ApiKey>>;
+    /// Returns false when there's no unrevoked key 'key_id'.
+    async fn revoke_api_key(&self, key_id: i32, now: DateTime<Utc>) -> DbResult<bool>;
+    async fn touch_api_key(&self, key_id: i32, now: DateTime<Utc>) -> DbResult<()>;
+}
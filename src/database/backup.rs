@@ -0,0 +1,709 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::database::error::DbError;
+use crate::database::migrations::*;
+use crate::database::models::{Role, UserAccount, UserReview};
+use crate::hotels_info::{Hotel, Review};
+
+/**
+ * Bumped whenever the layout of the archive or of its rows changes, with a
+ * step in 'upgrade_user' bringing rows of the version before up to date.
+ *
+ * - 1: The first archives.
+ * - 2: Users have a 'password_hash'.
+ * - 3: Users have a 'role'.
+ * - 4: Users have an 'email_verified_at'.
+ */
+pub const ARCHIVE_VERSION: u32 = 4;
+
+/// The oldest archives restores still read, upgrading their rows.
+pub const OLDEST_ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupTable {
+    Users,
+    Hotels,
+    Reviews,
+    UserReviews,
+}
+
+impl BackupTable {
+    /// Every table, each one after the tables its foreign keys point at.
+    pub const ALL: [BackupTable; 4] = [
+        BackupTable::Users, BackupTable::Hotels, BackupTable::Reviews,
+        BackupTable::UserReviews,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BackupTable::Users => "users",
+            BackupTable::Hotels => "hotels",
+            BackupTable::Reviews => "reviews",
+            BackupTable::UserReviews => "user_reviews",
+        }
+    }
+
+    fn file_name(self) -> String {
+        format!("{}.jsonl", self.name())
+    }
+}
+
+impl fmt::Display for BackupTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for BackupTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BackupTable::ALL.into_iter().find(|t| t.name() == s)
+            .ok_or_else(|| format!("Unknown table '{s}'"))
+    }
+}
+
+/// A batch of rows of one table, as they're written to and read from an
+/// archive.
+#[derive(Debug, Clone)]
+pub enum BackupRows {
+    Users(Vec<UserAccount>),
+    Hotels(Vec<Hotel>),
+    Reviews(Vec<Review>),
+    UserReviews(Vec<UserReview>),
+}
+
+impl BackupRows {
+    pub fn new(table: BackupTable) -> BackupRows {
+        match table {
+            BackupTable::Users => BackupRows::Users(vec![]),
+            BackupTable::Hotels => BackupRows::Hotels(vec![]),
+            BackupTable::Reviews => BackupRows::Reviews(vec![]),
+            BackupTable::UserReviews => BackupRows::UserReviews(vec![]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            BackupRows::Users(rows) => rows.len(),
+            BackupRows::Hotels(rows) => rows.len(),
+            BackupRows::Reviews(rows) => rows.len(),
+            BackupRows::UserReviews(rows) => rows.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Parses one JSON line of an archive written at 'version' and adds it
+    /// to the batch.
+    fn push_line(&mut self, line: &str, version: u32) -> serde_json::Result<()> {
+        match self {
+            BackupRows::Users(rows) => {
+                let mut row = serde_json::from_str(line)?;
+                upgrade_user(&mut row, version);
+                rows.push(serde_json::from_value(row)?)
+            },
+            BackupRows::Hotels(rows) => rows.push(serde_json::from_str(line)?),
+            BackupRows::Reviews(rows) => rows.push(serde_json::from_str(line)?),
+            BackupRows::UserReviews(rows) => rows.push(serde_json::from_str(line)?),
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Brings a user row of an archive written at 'version' up to
+ * 'ARCHIVE_VERSION', filling in the columns added since the way their
+ * migrations did.
+ */
+fn upgrade_user(row: &mut serde_json::Value, version: u32) {
+    let Some(row) = row.as_object_mut() else { return };
+    if version < 2 {
+        row.insert("password_hash".to_string(), serde_json::Value::Null);
+    }
+    if version < 3 {
+        row.insert("role".to_string(), Role::User.as_str().into());
+    }
+    if version < 4 {
+        row.insert("email_verified_at".to_string(), serde_json::Value::Null);
+    }
+}
+
+/**
+ * The first file of an archive, describing the rest.
+ *
+ * # Fields:
+ * - 'version': 'ARCHIVE_VERSION' of the binary that wrote it.
+ * - 'schema_version': Latest migration of the database it was taken from.
+ *   Archives can be restored into databases at that version or later.
+ * - 'tables': One JSON lines file per table, in foreign key order.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: i64,
+    pub tables: Vec<TableEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableEntry {
+    pub table: BackupTable,
+    pub file: String,
+    pub rows: usize,
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Archive version {}, schema version {}, taken {}",
+            self.version, self.schema_version, self.created_at)?;
+        for t in &self.tables {
+            write!(f, "\n  {:<14} {:>10} rows  {}", t.table.name(), t.rows, t.file)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * How 'restore_backup' runs.
+ *
+ * # Fields:
+ * - 'tables': Only restore these tables, every table in the archive when
+ *   empty. They're restored in foreign key order whatever the order here.
+ * - 'batch_size': Rows inserted per transaction.
+ */
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    pub tables: Vec<BackupTable>,
+    pub batch_size: usize,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions { tables: vec![], batch_size: 1000 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    /// Rows restored per table, in the order they were restored.
+    pub tables: Vec<(BackupTable, usize)>,
+    pub batches: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for RestoreReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tables: Vec<String> = self.tables.iter()
+            .map(|(table, rows)| format!("{table}: {rows}")).collect();
+        write!(f, "Restored {} in {} batches in {:.2?}",
+            tables.join(", "), self.batches, self.elapsed)
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    /// The archive is damaged, or wasn't written by a compatible binary.
+    Format(String),
+    /// The database isn't at the schema version backups work with.
+    Schema(String),
+    /// Restores only go into empty tables.
+    NotEmpty(BackupTable),
+    Db(DbError),
+}
+
+pub type BackupResult<T> = Result<T, BackupError>;
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "Archive I/O error: {e}"),
+            BackupError::Format(msg) => write!(f, "Invalid archive: {msg}"),
+            BackupError::Schema(msg) => write!(f, "{msg}"),
+            BackupError::NotEmpty(table) => write!(f,
+                "Table '{table}' isn't empty, restores only go into empty tables"),
+            BackupError::Db(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackupError::Io(e) => Some(e),
+            BackupError::Db(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        BackupError::Format(e.to_string())
+    }
+}
+
+impl From<DbError> for BackupError {
+    fn from(e: DbError) -> Self {
+        BackupError::Db(e)
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(e: sqlx::Error) -> Self {
+        BackupError::Db(e.into())
+    }
+}
+
+/**
+ * A database that can be backed up and restored.
+ *
+ * Each SQL backend implements this for its pool with 'backup_target!', so
+ * archives move freely between MySQL, SQLite and Postgres.
+ */
+#[async_trait]
+pub trait BackupTarget: Sync {
+    /**
+     * Writes every row of each table to its writer as JSON lines, in primary
+     * key order, all from the same snapshot of the database.
+     *
+     * # Returns:
+     * - The number of rows written for each table.
+     */
+    async fn dump_tables(
+        &self, tables: &mut [(BackupTable, &mut (dyn Write + Send))]
+    ) -> BackupResult<Vec<usize>>;
+    async fn count_rows(&self, table: BackupTable) -> BackupResult<i64>;
+    /// Inserts 'rows' with the ids they have, in a single transaction.
+    async fn restore_rows(&self, rows: &BackupRows) -> BackupResult<()>;
+    /// Brings what's derived from the restored rows up to date: id sequences
+    /// and rating summaries.
+    async fn finish_restore(&self) -> BackupResult<()>;
+}
+
+async fn require_up_to_date<T: MigrationTarget + ?Sized>(db: &T) -> BackupResult<()> {
+    match check_schema(db).await? {
+        SchemaCheck::UpToDate => Ok(()),
+        check => Err(BackupError::Schema(format!("{check}, run the 'migrate' binary first"))),
+    }
+}
+
+/// The tables that are in 'tables', or all of them when it's empty, in
+/// foreign key order.
+fn in_fk_order(tables: &[BackupTable]) -> Vec<BackupTable> {
+    BackupTable::ALL.into_iter()
+        .filter(|t| tables.is_empty() || tables.contains(t)).collect()
+}
+
+/**
+ * Snapshots 'tables' (every table when empty) of 'db' into a gzipped tar
+ * archive at 'path': 'manifest.json' followed by one JSON lines file per
+ * table.
+ *
+ * # Returns:
+ * - The manifest written to the archive.
+ */
+pub async fn create_backup<T: BackupTarget + MigrationTarget + ?Sized>(
+    db: &T, path: &Path, tables: &[BackupTable]
+) -> BackupResult<Manifest> {
+    require_up_to_date(db).await?;
+    let tables = in_fk_order(tables);
+
+    // Tar headers need each file's size, so tables are staged first
+    let mut staged = vec![];
+    for _ in &tables {
+        staged.push(tempfile::tempfile()?);
+    }
+    let mut writers: Vec<BufWriter<&mut File>> =
+        staged.iter_mut().map(BufWriter::new).collect();
+    let mut outs: Vec<(BackupTable, &mut (dyn Write + Send))> = tables.iter()
+        .zip(writers.iter_mut())
+        .map(|(&t, w)| (t, w as &mut (dyn Write + Send))).collect();
+    let rows = db.dump_tables(&mut outs).await?;
+    drop(outs);
+    for w in writers {
+        w.into_inner().map_err(|e| e.into_error())?;
+    }
+
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        created_at: Utc::now(),
+        schema_version: latest_version(db),
+        tables: tables.iter().zip(rows).map(|(&table, rows)| TableEntry {
+            table, file: table.file_name(), rows
+        }).collect(),
+    };
+
+    let gz = GzEncoder::new(File::create(path)?, Compression::default());
+    let mut archive = tar::Builder::new(gz);
+    let json = serde_json::to_vec_pretty(&manifest)?;
+    append(&mut archive, &manifest, MANIFEST_FILE, json.len() as u64, &json[..])?;
+    for (entry, mut file) in manifest.tables.iter().zip(staged) {
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        append(&mut archive, &manifest, &entry.file, len, file)?;
+    }
+    archive.into_inner()?.finish()?;
+
+    Ok(manifest)
+}
+
+fn append<W: Write>(
+    archive: &mut tar::Builder<W>, manifest: &Manifest, name: &str, len: u64,
+    data: impl Read
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(len);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)
+}
+
+type Entries<'a> = tar::Entries<'a, GzDecoder<File>>;
+
+/// Reads the manifest, which must be the archive's first file.
+fn next_manifest(entries: &mut Entries<'_>) -> BackupResult<Manifest> {
+    let entry = match entries.next() {
+        Some(entry) => entry?,
+        None => return Err(BackupError::Format("the archive is empty".to_string())),
+    };
+    if entry.path()?.as_os_str() != MANIFEST_FILE {
+        return Err(BackupError::Format(format!("'{MANIFEST_FILE}' isn't first")));
+    }
+    let manifest: Manifest = serde_json::from_reader(entry)?;
+
+    if !(OLDEST_ARCHIVE_VERSION..=ARCHIVE_VERSION).contains(&manifest.version) {
+        return Err(BackupError::Format(format!(
+            "archive version {} isn't supported, expected \
+            {OLDEST_ARCHIVE_VERSION} to {ARCHIVE_VERSION}",
+            manifest.version
+        )));
+    }
+    let tables: Vec<BackupTable> = manifest.tables.iter().map(|t| t.table).collect();
+    if tables != in_fk_order(&tables) {
+        return Err(BackupError::Format(
+            "tables aren't in foreign key order".to_string()
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Reads the manifest of the archive at 'path', without restoring anything.
+pub fn read_manifest(path: &Path) -> BackupResult<Manifest> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    next_manifest(&mut archive.entries()?)
+}
+
+/**
+ * Restores the archive at 'path' into 'db', whose tables being restored
+ * must be empty.
+ *
+ * Rows are inserted with their original ids, 'opts.batch_size' to a
+ * transaction, parents before children. Rows of archives older than
+ * 'ARCHIVE_VERSION' are upgraded on the way in. A failed batch stops the restore and
+ * leaves the batches before it in place.
+ */
+pub async fn restore_backup<T: BackupTarget + MigrationTarget + ?Sized>(
+    db: &T, path: &Path, opts: &RestoreOptions
+) -> BackupResult<RestoreReport> {
+    let start = Instant::now();
+    require_up_to_date(db).await?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut entries = archive.entries()?;
+    let manifest = next_manifest(&mut entries)?;
+    if manifest.schema_version > latest_version(db) {
+        return Err(BackupError::Schema(format!(
+            "The archive is from schema version {}, newer than this binary's {}",
+            manifest.schema_version, latest_version(db)
+        )));
+    }
+
+    let selected: Vec<&TableEntry> = manifest.tables.iter()
+        .filter(|t| opts.tables.is_empty() || opts.tables.contains(&t.table))
+        .collect();
+    if let Some(missing) = opts.tables.iter()
+        .find(|&&t| !selected.iter().any(|s| s.table == t)) {
+        return Err(BackupError::Format(format!("no '{missing}' table in the archive")));
+    }
+    for entry in &selected {
+        if db.count_rows(entry.table).await? > 0 {
+            return Err(BackupError::NotEmpty(entry.table));
+        }
+    }
+
+    let mut report = RestoreReport::default();
+    let batch_size = opts.batch_size.max(1);
+    for file in entries {
+        let file = file?;
+        let path = file.path()?.to_string_lossy().into_owned();
+        let entry = match selected.iter().find(|t| t.file == path) {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        let mut batch = BackupRows::new(entry.table);
+        let mut restored = 0;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            batch.push_line(&line, manifest.version).map_err(|e| BackupError::Format(
+                format!("'{path}' line {}: {e}", i + 1)
+            ))?;
+            if batch.len() >= batch_size {
+                db.restore_rows(&batch).await?;
+                restored += batch.len();
+                report.batches += 1;
+                batch = BackupRows::new(entry.table);
+            }
+        }
+        if !batch.is_empty() {
+            db.restore_rows(&batch).await?;
+            restored += batch.len();
+            report.batches += 1;
+        }
+
+        if restored != entry.rows {
+            return Err(BackupError::Format(format!(
+                "'{path}' has {restored} rows, the manifest lists {}", entry.rows
+            )));
+        }
+        report.tables.push((entry.table, restored));
+    }
+
+    if let Some(missing) = selected.iter()
+        .find(|s| !report.tables.iter().any(|(t, _)| *t == s.table)) {
+        return Err(BackupError::Format(format!("'{}' is missing", missing.file)));
+    }
+
+    db.finish_restore().await?;
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+/**
+ * Implements 'BackupTarget' for '$pool' using the statements of the '$strs'
+ * dialect module.
+ *
+ * # Parameters:
+ * - 'max_placeholders': Bind parameters the backend accepts per statement,
+ *   restore batches are split into as many INSERTs as that needs.
+ */
+macro_rules! backup_target {
+    ($pool:ty, $db:ty, $strs:path, max_placeholders: $max_placeholders:expr) => {
+        mod backup {
+            use std::io::Write;
+            use futures::TryStreamExt;
+            use sqlx::query_builder::Separated;
+            use sqlx::QueryBuilder;
+
+            use $strs::*;
+            use $crate::database::backup::*;
+            use $crate::database::models::{UserAccount, UserReview};
+            use $crate::hotels_info::{Hotel, Review};
+
+            type Connection = <$db as sqlx::Database>::Connection;
+
+            #[async_trait::async_trait]
+            impl BackupTarget for $pool {
+                async fn dump_tables(
+                    &self, tables: &mut [(BackupTable, &mut (dyn Write + Send))]
+                ) -> BackupResult<Vec<usize>> {
+                    let mut tx = self.begin().await?;
+                    for stmt in BEGIN_SNAPSHOT {
+                        sqlx::query(stmt).execute(&mut *tx).await?;
+                    }
+
+                    let mut rows = vec![];
+                    for (table, out) in tables.iter_mut() {
+                        let out = &mut **out;
+                        rows.push(match table {
+                            BackupTable::Users =>
+                                dump::<UserAccount>(&mut *tx, DUMP_USERS, out).await?,
+                            BackupTable::Hotels =>
+                                dump::<Hotel>(&mut *tx, DUMP_HOTELS, out).await?,
+                            BackupTable::Reviews =>
+                                dump::<Review>(&mut *tx, DUMP_REVIEWS, out).await?,
+                            BackupTable::UserReviews => dump::<UserReview>(
+                                &mut *tx, DUMP_USER_REVIEWS, out
+                            ).await?,
+                        });
+                    }
+                    tx.commit().await?;
+                    Ok(rows)
+                }
+
+                async fn count_rows(&self, table: BackupTable) -> BackupResult<i64> {
+                    let mut query: QueryBuilder<$db> = QueryBuilder::new(COUNT_ROWS);
+                    query.push(table.name());
+                    let (count,): (i64,) = query.build_query_as()
+                        .fetch_one(self).await?;
+                    Ok(count)
+                }
+
+                async fn restore_rows(&self, rows: &BackupRows) -> BackupResult<()> {
+                    let mut tx = self.begin().await?;
+                    match rows {
+                        BackupRows::Users(users) => insert(
+                            &mut tx, RESTORE_USERS, users, 6, |mut b, user| {
+                                b.push_bind(user.id).push_bind(user.username.clone())
+                                    .push_bind(user.email.clone())
+                                    .push_bind(user.password_hash.clone())
+                                    .push_bind(user.role.as_str())
+                                    .push_bind(user.email_verified_at);
+                            }
+                        ).await?,
+                        BackupRows::Hotels(hotels) => insert(
+                            &mut tx, INSERT_HOTELS, hotels, 6, |mut b, hotel| {
+                                b.push_bind(hotel.hotel_id)
+                                    .push_bind(hotel.name.clone())
+                                    .push_bind(hotel.address.clone())
+                                    .push_bind(hotel.city.clone())
+                                    .push_bind(hotel.province.clone())
+                                    .push_bind(hotel.country.clone());
+                            }
+                        ).await?,
+                        BackupRows::Reviews(reviews) => insert(
+                            &mut tx, INSERT_REVIEWS, reviews, 7, |mut b, review| {
+                                b.push_bind(review.review_id.clone())
+                                    .push_bind(review.hotel_id)
+                                    .push_bind(review.rating)
+                                    .push_bind(review.author.clone())
+                                    .push_bind(review.title.clone())
+                                    .push_bind(review.text.clone())
+                                    .push_bind(review.time);
+                            }
+                        ).await?,
+                        BackupRows::UserReviews(reviews) => insert(
+                            &mut tx, RESTORE_USER_REVIEWS, reviews, 7, |mut b, review| {
+                                b.push_bind(review.review_id)
+                                    .push_bind(review.user_id)
+                                    .push_bind(review.hotel_id)
+                                    .push_bind(review.title.clone())
+                                    .push_bind(review.text.clone())
+                                    .push_bind(review.rating)
+                                    .push_bind(review.created_at);
+                            }
+                        ).await?,
+                    }
+                    tx.commit().await?;
+                    Ok(())
+                }
+
+                async fn finish_restore(&self) -> BackupResult<()> {
+                    let mut tx = self.begin().await?;
+                    for stmt in RESET_SEQUENCES {
+                        sqlx::query(stmt).execute(&mut *tx).await?;
+                    }
+                    sqlx::query(DELETE_RATING_SUMMARIES).execute(&mut *tx).await?;
+                    sqlx::query(FILL_RATING_SUMMARY).execute(&mut *tx).await?;
+                    tx.commit().await?;
+                    Ok(())
+                }
+            }
+
+            /// Writes the rows 'select' returns to 'out', one JSON object a line.
+            async fn dump<T>(
+                conn: &mut Connection, select: &str, out: &mut (dyn Write + Send)
+            ) -> BackupResult<usize>
+            where
+                T: for<'r> sqlx::FromRow<'r, <$db as sqlx::Database>::Row>
+                    + serde::Serialize + Send + Unpin,
+            {
+                let mut rows = sqlx::query_as::<_, T>(select).fetch(&mut *conn);
+                let mut count = 0;
+                while let Some(row) = rows.try_next().await? {
+                    serde_json::to_writer(&mut *out, &row)?;
+                    out.write_all(b"\n")?;
+                    count += 1;
+                }
+                Ok(count)
+            }
+
+            async fn insert<T>(
+                conn: &mut Connection, insert: &'static str, rows: &[T],
+                columns: usize,
+                bind_row: fn(Separated<'_, '_, $db, &'static str>, &T),
+            ) -> sqlx::Result<()> {
+                for chunk in rows.chunks(($max_placeholders / columns).max(1)) {
+                    let mut query: QueryBuilder<$db> = QueryBuilder::new(insert);
+                    query.push_values(chunk, bind_row);
+                    query.build().execute(&mut *conn).await?;
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use backup_target;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users(version: u32, line: &str) -> serde_json::Result<Vec<UserAccount>> {
+        let mut rows = BackupRows::new(BackupTable::Users);
+        rows.push_line(line, version)?;
+        match rows {
+            BackupRows::Users(users) => Ok(users),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn version_1_users_have_no_password() {
+        let line = r#"{"id":1,"username":"ann","email":"ann@x.io"}"#;
+        let user = users(1, line).unwrap().remove(0);
+        assert_eq!((user.id, user.password_hash, user.role), (1, None, Role::User));
+        assert_eq!(user.email_verified_at, None);
+    }
+
+    #[test]
+    fn version_2_users_keep_their_password() {
+        let line = r#"{"id":1,"username":"ann","email":"ann@x.io","password_hash":"h"}"#;
+        let user = users(2, line).unwrap().remove(0);
+        assert_eq!(user.password_hash.as_deref(), Some("h"));
+        assert_eq!(user.role, Role::User);
+    }
+
+    #[test]
+    fn version_3_users_are_unverified() {
+        let line = r#"{"id":1,"username":"ann","email":"ann@x.io","role":"moderator",
+            "email_verified_at":"2020-01-01T00:00:00Z"}"#;
+        let user = users(3, line).unwrap().remove(0);
+        assert_eq!((user.role, user.email_verified_at), (Role::Moderator, None));
+    }
+
+    #[test]
+    fn current_users_need_a_role() {
+        let line = r#"{"id":1,"username":"ann","email":"ann@x.io","role":"admin"}"#;
+        assert_eq!(users(ARCHIVE_VERSION, line).unwrap()[0].role, Role::Admin);
+        let line = r#"{"id":1,"username":"ann","email":"ann@x.io"}"#;
+        assert!(users(ARCHIVE_VERSION, line).is_err());
+    }
+}
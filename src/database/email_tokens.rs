@@ -0,0 +1,82 @@
+use std::fmt;
+use std::str::FromStr;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DbResult;
+
+/// What an emailed link lets its reader do, tokens only work for their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl EmailPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailPurpose::VerifyEmail => "verify_email",
+            EmailPurpose::ResetPassword => "reset_password",
+        }
+    }
+}
+
+impl fmt::Display for EmailPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EmailPurpose {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verify_email" => Ok(EmailPurpose::VerifyEmail),
+            "reset_password" => Ok(EmailPurpose::ResetPassword),
+            _ => Err(format!("Unknown email token purpose '{s}'")),
+        }
+    }
+}
+
+/**
+ * A token sent by email, the server side of a signed link.
+ *
+ * The link carries a JWT naming 'token_id', the row makes it expire and work
+ * only once.
+ *
+ * # Fields:
+ * - 'token_id': The 'jti' of the JWT.
+ */
+#[derive(Debug, Clone)]
+pub struct NewEmailToken {
+    pub user_id: i32,
+    pub purpose: EmailPurpose,
+    pub token_id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/**
+ * Single-use tokens of email verification and password reset links.
+ *
+ * SQL backends use a token with one guarded UPDATE, so of two requests racing
+ * with the same link only one gets through.
+ */
+#[async_trait]
+pub trait EmailTokenRepository: Send + Sync {
+    /// Stores 'token' and revokes the unused tokens of the same user and
+    /// purpose, only the latest link works.
+    async fn add_email_token(&self, token: &NewEmailToken) -> DbResult<()>;
+
+    /**
+     * Marks the token used if it's for 'purpose', unused and unexpired by
+     * 'now'.
+     *
+     * # Returns:
+     * - The user the token was issued to, 'None' if it can't be used.
+     */
+    async fn use_email_token(
+        &self, token_id: &str, purpose: EmailPurpose, now: DateTime<Utc>
+    ) -> DbResult<Option<i32>>;
+}
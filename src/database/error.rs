@@ -0,0 +1,70 @@
+use std::fmt;
+
+/**
+ * Everything that can go wrong in the 'database' module.
+ *
+ * Functions here return it instead of logging, so callers decide whether a
+ * failure is fatal (startup), a bad request (routes) or worth a warning.
+ */
+#[derive(Debug)]
+pub enum DbError {
+    /// The database couldn't be reached, even after retrying.
+    Connection { attempts: u32, source: sqlx::Error },
+    /// No enabled backend understands the database URL.
+    UnsupportedUrl(String),
+    /// A UNIQUE or FOREIGN KEY constraint was violated.
+    Constraint(String),
+    /// A migration couldn't be applied or reverted.
+    Migration(String),
+    /// Loading rows into 'table' failed.
+    Load { table: &'static str, source: sqlx::Error },
+    /// Any other failed statement.
+    Sql(sqlx::Error),
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Connection { attempts, source } => write!(f,
+                "Couldn't connect to the database after {attempts} \
+                attempt(s): {source}"),
+            DbError::UnsupportedUrl(url) => write!(f,
+                "Unsupported database URL '{url}', is its cargo feature enabled?"),
+            DbError::Constraint(msg) => write!(f, "Constraint violated: {msg}"),
+            DbError::Migration(msg) => write!(f, "Migration failed: {msg}"),
+            DbError::Load { table, source } => write!(f,
+                "Error loading '{table}': {source}"),
+            DbError::Sql(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Connection { source, .. } | DbError::Load { source, .. }
+                | DbError::Sql(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// SQLSTATE class 23 on MySQL and Postgres, SQLITE_CONSTRAINT_* on SQLite.
+fn is_constraint_code(code: &str) -> bool {
+    (code.len() == 5 && code.starts_with("23"))
+        || matches!(code, "19" | "787" | "1555" | "2067")
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref db)
+                if db.code().is_some_and(|c| is_constraint_code(&c)) => {
+                DbError::Constraint(db.message().to_string())
+            },
+            e => DbError::Sql(e),
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::api_keys::*;
+use crate::database::email_tokens::*;
+use crate::database::error::{DbError, DbResult};
+use crate::database::login_events::*;
+use crate::database::models::*;
+use crate::database::repository::*;
+use crate::database::search::*;
+use crate::database::sessions::*;
+use crate::database::summary::*;
+use crate::database::totp::*;
+use crate::hotels_info::{Hotel, HotelsInfo, Review};
+
+#[derive(Debug, Default)]
+struct UsersTables {
+    users: Vec<User>,
+    password_hashes: HashMap<i32, String>,
+    email_verified_at: HashMap<i32, DateTime<Utc>>,
+    user_reviews: Vec<UserReview>,
+    refresh_tokens: Vec<RefreshToken>,
+    /// With when each one was used.
+    email_tokens: Vec<(NewEmailToken, Option<DateTime<Utc>>)>,
+    api_keys: Vec<ApiKey>,
+    login_events: Vec<LoginEvent>,
+    login_lockouts: HashMap<i32, LoginLockout>,
+    totp: HashMap<i32, Totp>,
+    /// The user, hash and when it was used of each code.
+    recovery_codes: Vec<(i32, String, Option<DateTime<Utc>>)>,
+    next_user_id: i32,
+    next_review_id: i32,
+    next_token_id: i32,
+    next_api_key_id: i32,
+    next_login_event_id: i32,
+}
+
+impl UsersTables {
+    fn add_refresh_token(&mut self, token: &NewRefreshToken) {
+        self.next_token_id += 1;
+        self.refresh_tokens.push(RefreshToken {
+            id: self.next_token_id, user_id: token.user_id,
+            family: token.family.clone(), token_hash: token.token_hash.clone(),
+            created_at: token.created_at, expires_at: token.expires_at,
+            used_at: None, revoked_at: None,
+        });
+    }
+
+    fn revoke(&mut self, now: DateTime<Utc>, which: impl Fn(&RefreshToken) -> bool
+    ) -> u64 {
+        let mut revoked = 0;
+        for token in self.refresh_tokens.iter_mut()
+            .filter(|t| t.revoked_at.is_none() && which(t)) {
+            token.revoked_at = Some(now);
+            revoked += 1;
+        }
+        revoked
+    }
+}
+
+/**
+ * Repository implementation that keeps everything in memory.
+ *
+ * Hotels and reviews come from a parsed 'HotelsInfo'; users and user reviews
+ * live in plain vectors. Ids are handed out like AUTO_INCREMENT and the
+ * UNIQUE/FOREIGN KEY constraints on users are enforced, so it behaves close
+ * enough to MySQL to serve demos and to back handler tests.
+ */
+#[derive(Debug, Default)]
+pub struct InMemoryRepository {
+    info: RwLock<HotelsInfo>,
+    tables: Mutex<UsersTables>,
+}
+
+const SEED_USERS: [(&str, &str); 6] = [
+    ("charles", "cMingus@moaning.ca"), ("willie", "wColon@miSueno.co"),
+    ("chet", "cBaker@fallingTooEasily.us"), ("dave", "dBrubeck@take5.po"),
+    ("bill", "bEvans@foolishHeart.es"), ("hector", "hLavoe@elCantante.com"),
+];
+
+impl InMemoryRepository {
+    pub fn new() -> InMemoryRepository {
+        InMemoryRepository::default()
+    }
+
+    pub fn from_hotels_info(info: HotelsInfo) -> InMemoryRepository {
+        InMemoryRepository { info: RwLock::new(info), ..Default::default() }
+    }
+
+    /// Mirrors 'INSERT_USERS' so the in-memory backend has the same seed data.
+    pub fn with_seed_users(self) -> InMemoryRepository {
+        {
+            let mut tables = self.tables.lock().unwrap();
+            for (username, email) in SEED_USERS {
+                tables.next_user_id += 1;
+                let id = tables.next_user_id;
+                tables.users.push(User {
+                    id, username: username.to_string(), email: email.to_string(),
+                    role: Role::User,
+                });
+            }
+        }
+        self
+    }
+
+    pub fn insert_hotel(&self, hotel: Hotel) {
+        self.info.write().unwrap()
+            .add_hotels(HashMap::from([(hotel.hotel_id, hotel)]));
+    }
+
+    pub fn insert_review(&self, review: Review) {
+        self.info.write().unwrap().add_reviews(review.hotel_id, vec![review]);
+    }
+}
+
+fn taken(users: &[User], id: i32, username: &str, email: &str) -> bool {
+    users.iter().any(|u| u.id != id && (u.username == username || u.email == email))
+}
+
+#[async_trait]
+impl HotelRepository for InMemoryRepository {
+    async fn get_hotel(&self, hotel_id: i32) -> DbResult<Option<Hotel>> {
+        Ok(self.info.read().unwrap().search_hotels(hotel_id))
+    }
+
+    async fn get_all_hotels(&self) -> DbResult<Vec<Hotel>> {
+        let mut hotels: Vec<Hotel> = self.info.read().unwrap()
+            .get_hotels().into_values().collect();
+        hotels.sort_by_key(|h| h.hotel_id);
+        Ok(hotels)
+    }
+
+    async fn get_like_hotels(&self, hotel_name: &str) -> DbResult<Vec<Hotel>> {
+        let hotel_name = hotel_name.to_lowercase();
+        let mut hotels: Vec<Hotel> = self.info.read().unwrap()
+            .get_hotels().into_values()
+            .filter(|h| h.name.to_lowercase().contains(&hotel_name))
+            .collect();
+        hotels.sort_by_key(|h| h.hotel_id);
+        Ok(hotels)
+    }
+}
+
+#[async_trait]
+impl ReviewRepository for InMemoryRepository {
+    async fn get_hotel_reviews(&self, hotel_id: i32, filter: &ReviewFilter
+    ) -> DbResult<Vec<Review>> {
+        let mut reviews: Vec<Review> = self.info.read().unwrap()
+            .search_reviews(hotel_id).unwrap_or_default()
+            .into_iter().filter(|r| filter.matches(r)).collect();
+        match filter.order {
+            Some(SortOrder::Asc) => reviews.sort_by_key(|r| r.time),
+            Some(SortOrder::Desc) => reviews.sort_by_key(|r| std::cmp::Reverse(r.time)),
+            None => {},
+        }
+        Ok(reviews)
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryRepository {
+    async fn add_user(&self, user: &NewUser) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if taken(&tables.users, 0, &user.username, &user.email) {
+            return Err(DbError::Constraint("Duplicate username or email".to_string()));
+        }
+        tables.next_user_id += 1;
+        let id = tables.next_user_id;
+        tables.users.push(User {
+            id, username: user.username.clone(), email: user.email.clone(),
+            role: Role::User,
+        });
+        if let Some(hash) = &user.password_hash {
+            tables.password_hashes.insert(id, hash.clone());
+        }
+        Ok(())
+    }
+
+    async fn get_user(&self, user_id: i32) -> DbResult<Option<User>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.users.iter().find(|u| u.id == user_id).cloned())
+    }
+
+    async fn get_user_by_creds(&self, username: &str, email: &str
+    ) -> DbResult<Option<User>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.users.iter()
+            .find(|u| u.username == username || u.email == email).cloned())
+    }
+
+    async fn get_all_users(&self) -> DbResult<Vec<User>> {
+        Ok(self.tables.lock().unwrap().users.clone())
+    }
+
+    async fn update_user(&self, user: &User) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if taken(&tables.users, user.id, &user.username, &user.email) {
+            return Err(DbError::Constraint("Duplicate username or email".to_string()));
+        }
+        if let Some(u) = tables.users.iter_mut().find(|u| u.id == user.id) {
+            let email_changed = u.email != user.email;
+            u.username = user.username.clone();
+            u.email = user.email.clone();
+            if email_changed {
+                tables.email_verified_at.remove(&user.id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: i32) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.user_reviews.iter().any(|r| r.user_id == user_id) {
+            return Err(DbError::Constraint(
+                "User still has reviews (foreign key constraint)".to_string()
+            ));
+        }
+        tables.users.retain(|u| u.id != user_id);
+        tables.password_hashes.remove(&user_id);
+        tables.email_verified_at.remove(&user_id);
+        tables.refresh_tokens.retain(|t| t.user_id != user_id);
+        tables.email_tokens.retain(|(t, _)| t.user_id != user_id);
+        for key in tables.api_keys.iter_mut().filter(|k| k.created_by == Some(user_id)) {
+            key.created_by = None;
+        }
+        for event in tables.login_events.iter_mut().filter(|e| e.user_id == Some(user_id)) {
+            event.user_id = None;
+        }
+        tables.login_lockouts.remove(&user_id);
+        tables.totp.remove(&user_id);
+        tables.recovery_codes.retain(|(id, _, _)| *id != user_id);
+        Ok(())
+    }
+
+    async fn get_account(&self, user_id: i32) -> DbResult<Option<UserAccount>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.users.iter().find(|u| u.id == user_id).map(|u| UserAccount {
+            id: u.id, username: u.username.clone(), email: u.email.clone(),
+            password_hash: tables.password_hashes.get(&u.id).cloned(), role: u.role,
+            email_verified_at: tables.email_verified_at.get(&u.id).copied(),
+        }))
+    }
+
+    async fn set_password_hash(&self, user_id: i32, password_hash: &str
+    ) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == user_id) {
+            return Ok(false);
+        }
+        tables.password_hashes.insert(user_id, password_hash.to_string());
+        Ok(true)
+    }
+
+    async fn set_role(&self, user_id: i32, role: Role) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.users.iter_mut().find(|u| u.id == user_id) {
+            Some(u) => {
+                u.role = role;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn set_email_verified(&self, user_id: i32, email: &str, verified_at: DateTime<Utc>
+    ) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == user_id && u.email == email) {
+            return Ok(false);
+        }
+        tables.email_verified_at.insert(user_id, verified_at);
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl UserReviewRepository for InMemoryRepository {
+    async fn add_user_review(&self, review: &NewUserReview) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == review.user_id) {
+            return Err(DbError::Constraint(
+                "Unknown user (foreign key constraint)".to_string()
+            ));
+        }
+        tables.next_review_id += 1;
+        let review_id = tables.next_review_id;
+        tables.user_reviews.push(UserReview {
+            review_id, user_id: review.user_id, hotel_id: review.hotel_id,
+            title: review.title.clone(), text: review.text.clone(),
+            rating: review.rating, created_at: Some(chrono::Utc::now()),
+        });
+        Ok(())
+    }
+
+    async fn get_all_user_reviews(&self) -> DbResult<Vec<UserReview>> {
+        Ok(self.tables.lock().unwrap().user_reviews.clone())
+    }
+
+    async fn get_user_reviews_by_username(&self, username: &str
+    ) -> DbResult<Vec<UserReview>> {
+        let tables = self.tables.lock().unwrap();
+        let user_id = match tables.users.iter().find(|u| u.username == username) {
+            Some(u) => u.id,
+            None => return Ok(vec![]),
+        };
+        Ok(tables.user_reviews.iter()
+            .filter(|r| r.user_id == user_id).cloned().collect())
+    }
+
+    async fn get_user_reviews_by_hotel_id(&self, hotel_id: i32
+    ) -> DbResult<Vec<UserReview>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.user_reviews.iter()
+            .filter(|r| r.hotel_id == hotel_id).cloned().collect())
+    }
+
+    async fn get_user_review(&self, review_id: i32) -> DbResult<Option<UserReview>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.user_reviews.iter().find(|r| r.review_id == review_id).cloned())
+    }
+
+    async fn delete_user_review(&self, review_id: i32) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.user_reviews.retain(|r| r.review_id != review_id);
+        Ok(())
+    }
+}
+
+/// Summaries are worked out on every request, there's no table to keep.
+#[async_trait]
+impl RatingSummaryRepository for InMemoryRepository {
+    async fn get_rating_summary(&self, hotel_id: i32
+    ) -> DbResult<Option<RatingSummary>> {
+        let mut summary = RatingSummary::new(hotel_id);
+        for r in self.info.read().unwrap().search_reviews(hotel_id).unwrap_or_default() {
+            summary.add(r.rating, Some(r.time));
+        }
+        for r in self.tables.lock().unwrap().user_reviews.iter()
+            .filter(|r| r.hotel_id == hotel_id) {
+            if let Some(rating) = r.rating {
+                summary.add(rating, r.created_at);
+            }
+        }
+        Ok(Some(summary).filter(|s| s.review_count > 0))
+    }
+
+    async fn rebuild_rating_summaries(&self) -> DbResult<Vec<SummaryDrift>> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl SearchRepository for InMemoryRepository {
+    async fn search(&self, query: &SearchQuery) -> DbResult<SearchResults> {
+        let info = self.info.read().unwrap();
+        let hotels = info.get_hotels();
+        let in_scope = |hotel_id: i32| {
+            query.hotel_id.is_none_or(|id| id == hotel_id)
+                && query.city.as_ref().is_none_or(|city| {
+                    hotels.get(&hotel_id).is_some_and(|h| &h.city == city)
+                })
+        };
+
+        let candidates = match query.target {
+            SearchTarget::Reviews => info.get_reviews().into_values().flatten()
+                .filter(|r| in_scope(r.hotel_id)
+                    && query.min_rating.is_none_or(|min| r.rating >= min))
+                .map(SearchItem::Review).collect(),
+            SearchTarget::Hotels => hotels.values()
+                .filter(|h| in_scope(h.hotel_id))
+                .cloned().map(SearchItem::Hotel).collect(),
+        };
+        Ok(SearchResults::rank(query, candidates))
+    }
+}
+
+#[async_trait]
+impl SessionRepository for InMemoryRepository {
+    async fn add_refresh_token(&self, token: &NewRefreshToken) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == token.user_id) {
+            return Err(DbError::Constraint(
+                "No such user (foreign key constraint)".to_string()
+            ));
+        }
+        tables.add_refresh_token(token);
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str
+    ) -> DbResult<Option<RefreshToken>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.refresh_tokens.iter()
+            .find(|t| t.token_hash == token_hash).cloned())
+    }
+
+    async fn rotate_refresh_token(
+        &self, token_hash: &str, next_hash: &str,
+        now: DateTime<Utc>, expires_at: DateTime<Utc>
+    ) -> DbResult<Rotation> {
+        let mut tables = self.tables.lock().unwrap();
+        let Some(token) = tables.refresh_tokens.iter_mut()
+            .find(|t| t.token_hash == token_hash) else {
+            return Ok(Rotation::Unknown);
+        };
+        let (user_id, family) = (token.user_id, token.family.clone());
+
+        if token.used_at.is_some() || token.revoked_at.is_some() {
+            tables.revoke(now, |t| t.family == family);
+            return Ok(Rotation::Reused { user_id, family });
+        }
+        if token.expires_at <= now {
+            return Ok(Rotation::Expired);
+        }
+
+        token.used_at = Some(now);
+        tables.add_refresh_token(&NewRefreshToken {
+            user_id, family, token_hash: next_hash.to_string(),
+            created_at: now, expires_at,
+        });
+        Ok(Rotation::Rotated { user_id })
+    }
+
+    async fn revoke_token_family(&self, family: &str, now: DateTime<Utc>
+    ) -> DbResult<u64> {
+        Ok(self.tables.lock().unwrap().revoke(now, |t| t.family == family))
+    }
+
+    async fn revoke_user_tokens(&self, user_id: i32, now: DateTime<Utc>
+    ) -> DbResult<u64> {
+        Ok(self.tables.lock().unwrap().revoke(now, |t| t.user_id == user_id))
+    }
+}
+
+#[async_trait]
+impl EmailTokenRepository for InMemoryRepository {
+    async fn add_email_token(&self, token: &NewEmailToken) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == token.user_id) {
+            return Err(DbError::Constraint(
+                "No such user (foreign key constraint)".to_string()
+            ));
+        }
+        for (_, used_at) in tables.email_tokens.iter_mut().filter(|(t, used_at)| {
+            t.user_id == token.user_id && t.purpose == token.purpose && used_at.is_none()
+        }) {
+            *used_at = Some(token.created_at);
+        }
+        tables.email_tokens.push((token.clone(), None));
+        Ok(())
+    }
+
+    async fn use_email_token(
+        &self, token_id: &str, purpose: EmailPurpose, now: DateTime<Utc>
+    ) -> DbResult<Option<i32>> {
+        let mut tables = self.tables.lock().unwrap();
+        Ok(tables.email_tokens.iter_mut()
+            .find(|(t, used_at)| {
+                t.token_id == token_id && t.purpose == purpose
+                    && used_at.is_none() && t.expires_at > now
+            })
+            .map(|(t, used_at)| {
+                *used_at = Some(now);
+                t.user_id
+            }))
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for InMemoryRepository {
+    async fn add_api_key(&self, key: &NewApiKey) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.api_keys.iter().any(|k| k.prefix == key.prefix) {
+            return Err(DbError::Constraint("Duplicate API key prefix".to_string()));
+        }
+        tables.next_api_key_id += 1;
+        let id = tables.next_api_key_id;
+        tables.api_keys.push(ApiKey {
+            id, name: key.name.clone(), prefix: key.prefix.clone(),
+            secret_hash: key.secret_hash.clone(), scopes: key.scopes.clone(),
+            created_by: Some(key.created_by), created_at: key.created_at,
+            expires_at: key.expires_at, last_used_at: None, revoked_at: None,
+        });
+        Ok(())
+    }
+
+    async fn get_api_key(&self, prefix: &str) -> DbResult<Option<ApiKey>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.api_keys.iter().find(|k| k.prefix == prefix).cloned())
+    }
+
+    async fn get_all_api_keys(&self) -> DbResult<Vec<ApiKey>> {
+        Ok(self.tables.lock().unwrap().api_keys.clone())
+    }
+
+    async fn revoke_api_key(&self, key_id: i32, now: DateTime<Utc>) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.api_keys.iter_mut().find(|k| k.id == key_id && k.revoked_at.is_none()) {
+            Some(key) => {
+                key.revoked_at = Some(now);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn touch_api_key(&self, key_id: i32, now: DateTime<Utc>) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(key) = tables.api_keys.iter_mut().find(|k| k.id == key_id) {
+            key.last_used_at = Some(now);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LoginEventRepository for InMemoryRepository {
+    async fn add_login_event(&self, event: &NewLoginEvent) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.next_login_event_id += 1;
+        let id = tables.next_login_event_id;
+        tables.login_events.push(LoginEvent {
+            id, user_id: event.user_id, username: event.username.clone(),
+            ip: event.ip.clone(), user_agent: event.user_agent.clone(),
+            success: event.failure.is_none(),
+            reason: event.failure.map(|failure| failure.to_string()),
+            created_at: event.created_at,
+        });
+        Ok(())
+    }
+
+    async fn get_login_events(&self, user_id: i32, limit: i64) -> DbResult<Vec<LoginEvent>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.login_events.iter().rev()
+            .filter(|e| e.user_id == Some(user_id))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_login_lockout(&self, user_id: i32) -> DbResult<Option<LoginLockout>> {
+        Ok(self.tables.lock().unwrap().login_lockouts.get(&user_id).cloned())
+    }
+
+    async fn add_login_failure(
+        &self, user_id: i32, policy: &LockoutPolicy, now: DateTime<Utc>
+    ) -> DbResult<LoginLockout> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == user_id) {
+            return Err(DbError::Constraint(
+                "No such user (foreign key constraint)".to_string()
+            ));
+        }
+        let lockout = tables.login_lockouts.entry(user_id).or_insert(LoginLockout {
+            user_id, failed_logins: 0, locked_until: None,
+        });
+        lockout.failed_logins += 1;
+        if let Some(locked_until) = policy.locked_until(lockout.failed_logins, now) {
+            lockout.locked_until = Some(locked_until);
+        }
+        Ok(lockout.clone())
+    }
+
+    async fn clear_login_failures(&self, user_id: i32) -> DbResult<()> {
+        self.tables.lock().unwrap().login_lockouts.remove(&user_id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TotpRepository for InMemoryRepository {
+    async fn get_totp(&self, user_id: i32) -> DbResult<Option<Totp>> {
+        Ok(self.tables.lock().unwrap().totp.get(&user_id).cloned())
+    }
+
+    async fn set_totp_secret(&self, user_id: i32, secret: &str, now: DateTime<Utc>
+    ) -> DbResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.users.iter().any(|u| u.id == user_id) {
+            return Err(DbError::Constraint(
+                "No such user (foreign key constraint)".to_string()
+            ));
+        }
+        if tables.totp.get(&user_id).is_some_and(Totp::is_confirmed) {
+            return Err(DbError::Constraint("Duplicate TOTP of a user".to_string()));
+        }
+        tables.totp.insert(user_id, Totp {
+            user_id, secret: secret.to_string(), confirmed_at: None,
+            last_used_step: None, created_at: now,
+        });
+        Ok(())
+    }
+
+    async fn confirm_totp(
+        &self, user_id: i32, step: i64, recovery_code_hashes: &[String], now: DateTime<Utc>
+    ) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.totp.get_mut(&user_id).filter(|totp| !totp.is_confirmed()) {
+            Some(totp) => {
+                totp.confirmed_at = Some(now);
+                totp.last_used_step = Some(step);
+            },
+            None => return Ok(false),
+        }
+        tables.recovery_codes.retain(|(id, _, _)| *id != user_id);
+        for code_hash in recovery_code_hashes {
+            tables.recovery_codes.push((user_id, code_hash.clone(), None));
+        }
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: i32, step: i64) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.totp.get_mut(&user_id).filter(|totp| {
+            totp.is_confirmed() && totp.last_used_step.is_none_or(|last| last < step)
+        }) {
+            Some(totp) => {
+                totp.last_used_step = Some(step);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str, now: DateTime<Utc>
+    ) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.recovery_codes.iter_mut().find(|(id, hash, used_at)| {
+            *id == user_id && hash == code_hash && used_at.is_none()
+        }) {
+            Some((_, _, used_at)) => {
+                *used_at = Some(now);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_totp(&self, user_id: i32) -> DbResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.recovery_codes.retain(|(id, _, _)| *id != user_id);
+        Ok(tables.totp.remove(&user_id).is_some())
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use async_trait::async_trait;
+
+use crate::database::error::DbResult;
+use crate::hotels_info::{Hotel, Review};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Plain INSERT, rows that already exist make their batch fail.
+    Insert,
+    /// INSERT ... ON DUPLICATE KEY UPDATE, so loads can be re-run safely.
+    Upsert,
+}
+
+/**
+ * How bulk loads are split up and run.
+ *
+ * # Fields:
+ * - 'batch_size': Rows per multi-row INSERT (clamped to the placeholder limit).
+ * - 'concurrency': Batches in flight at once, each on its own pooled
+ *   connection and transaction.
+ * - 'all_or_nothing': Run every batch in a single transaction and roll it all
+ *   back on the first failure. Batches then run one after the other.
+ * - 'mode': Whether existing rows are an error or get updated.
+ */
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    pub batch_size: usize,
+    pub concurrency: usize,
+    pub all_or_nothing: bool,
+    pub mode: LoadMode,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            batch_size: 1000, concurrency: 4, all_or_nothing: false,
+            mode: LoadMode::Insert,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl RowCounts {
+    pub fn total(&self) -> usize {
+        self.inserted + self.updated + self.unchanged
+    }
+
+    pub fn add(&mut self, other: RowCounts) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    pub table: &'static str,
+    pub rows: RowCounts,
+    pub batches: usize,
+    pub failed_batches: usize,
+    /// Why each failed batch failed, for the caller to report.
+    pub errors: Vec<String>,
+    pub elapsed: Duration,
+}
+
+impl LoadReport {
+    pub fn rows_per_sec(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.rows.total() as f64 / secs,
+            _ => self.rows.total() as f64,
+        }
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} rows ({} inserted, {} updated, {} unchanged) \
+            in {} batches ({} failed) in {:.2?}, {:.0} rows/s",
+            self.table, self.rows.total(), self.rows.inserted,
+            self.rows.updated, self.rows.unchanged, self.batches,
+            self.failed_batches, self.elapsed, self.rows_per_sec())
+    }
+}
+
+/**
+ * Bulk loading of seed users and parsed hotel data into a SQL backend.
+ *
+ * Failures come back as 'DbError::Load' naming the table, except for failed
+ * batches outside 'all_or_nothing', which are listed in the report.
+ */
+#[async_trait]
+pub trait BulkLoader: Sync {
+    async fn add_users(&self) -> DbResult<()>;
+    async fn add_hotels_data(
+        &self, hotels: HashMap<i32, Hotel>, opts: &LoadOptions
+    ) -> DbResult<LoadReport>;
+    async fn add_reviews_data(
+        &self, reviews: HashMap<i32, Vec<Review>>, opts: &LoadOptions
+    ) -> DbResult<LoadReport>;
+}
+
+/**
+ * Implements 'BulkLoader' for '$pool' using the statements of the '$strs'
+ * dialect module.
+ *
+ * # Parameters:
+ * - 'max_placeholders': Bind parameters the backend accepts per statement.
+ * - 'max_concurrency': Upper bound on batches in flight, whatever the options.
+ * - 'found_rows': Whether an upsert reports unchanged rows as affected, like
+ *   MySQL does with CLIENT_FOUND_ROWS. Otherwise only inserted and actually
+ *   updated rows are counted.
+ */
+macro_rules! bulk_loader {
+    (
+        $pool:ty, $db:ty, $conn:ty, $strs:path,
+        max_placeholders: $max_placeholders:expr,
+        max_concurrency: $max_concurrency:expr,
+        found_rows: $found_rows:expr
+    ) => {
+        mod bulk {
+            use std::collections::HashMap;
+            use std::time::{Duration, Instant};
+            use futures::stream::{self, StreamExt};
+            use sqlx::query_builder::Separated;
+            use sqlx::{QueryBuilder, Result};
+
+            use $strs::*;
+            use $crate::database::error::{DbError, DbResult};
+            use $crate::database::loader::*;
+            use $crate::database::summary::refresh_statements;
+            use $crate::hotels_info::{Hotel, Review};
+
+            /**
+             * What 'load' needs to know about a table.
+             *
+             * # Fields:
+             * - 'insert': 'insert into tbl(cols...) ' prefix for the VALUES list.
+             * - 'upsert': Conflict clause suffix used in 'LoadMode::Upsert'.
+             * - 'count_existing': 'select count(*) ... in (' prefix for keys.
+             * - 'columns': Number of values 'bind_row' pushes per row.
+             * - 'bind_row': Pushes one row's values onto the VALUES tuple.
+             * - 'bind_key': Pushes one row's primary key onto the IN list.
+             * - 'rated_hotel': The hotel whose rating summary a row feeds, if
+             *   the table has ratings.
+             */
+            struct TableSpec<T> {
+                name: &'static str,
+                insert: &'static str,
+                upsert: &'static str,
+                count_existing: &'static str,
+                columns: usize,
+                bind_row: fn(Separated<'_, '_, $db, &'static str>, &T),
+                bind_key: fn(&mut Separated<'_, '_, $db, &'static str>, &T),
+                rated_hotel: Option<fn(&T) -> i32>,
+            }
+
+            const HOTELS: TableSpec<Hotel> = TableSpec {
+                name: "hotels",
+                insert: INSERT_HOTELS,
+                upsert: UPSERT_HOTELS,
+                count_existing: COUNT_EXISTING_HOTELS,
+                columns: 6,
+                bind_row: |mut b, hotel| {
+                    b.push_bind(hotel.hotel_id).push_bind(hotel.name.clone())
+                        .push_bind(hotel.address.clone())
+                        .push_bind(hotel.city.clone())
+                        .push_bind(hotel.province.clone())
+                        .push_bind(hotel.country.clone());
+                },
+                bind_key: |b, hotel| { b.push_bind(hotel.hotel_id); },
+                rated_hotel: None,
+            };
+
+            const REVIEWS: TableSpec<Review> = TableSpec {
+                name: "reviews",
+                insert: INSERT_REVIEWS,
+                upsert: UPSERT_REVIEWS,
+                count_existing: COUNT_EXISTING_REVIEWS,
+                columns: 7,
+                bind_row: |mut b, review| {
+                    b.push_bind(review.review_id.clone()).push_bind(review.hotel_id)
+                        .push_bind(review.rating).push_bind(review.author.clone())
+                        .push_bind(review.title.clone())
+                        .push_bind(review.text.clone())
+                        .push_bind(review.time);
+                },
+                bind_key: |b, review| { b.push_bind(review.review_id.clone()); },
+                rated_hotel: Some(|review| review.hotel_id),
+            };
+
+            #[async_trait::async_trait]
+            impl BulkLoader for $pool {
+                async fn add_users(&self) -> DbResult<()> {
+                    sqlx::query(INSERT_USERS).execute(self).await
+                        .map_err(|source| DbError::Load { table: "users", source })?;
+                    Ok(())
+                }
+
+                async fn add_hotels_data(
+                    &self, hotels: HashMap<i32, Hotel>, opts: &LoadOptions
+                ) -> DbResult<LoadReport> {
+                    let rows: Vec<Hotel> = hotels.into_values().collect();
+                    load(self, &HOTELS, rows, opts).await
+                        .map_err(|source| DbError::Load { table: HOTELS.name, source })
+                }
+
+                async fn add_reviews_data(
+                    &self, reviews: HashMap<i32, Vec<Review>>, opts: &LoadOptions
+                ) -> DbResult<LoadReport> {
+                    let rows: Vec<Review> = reviews.into_values().flatten().collect();
+                    load(self, &REVIEWS, rows, opts).await
+                        .map_err(|source| DbError::Load { table: REVIEWS.name, source })
+                }
+            }
+
+            /**
+             * Inserts 'rows' into 'table' in multi-row batches as described
+             * by 'opts'.
+             *
+             * # Returns:
+             * - A report of what was loaded. Failed batches are counted and
+             *   their errors collected, unless 'all_or_nothing' is set, in
+             *   which case the first failure is returned.
+             */
+            async fn load<T: Sync>(
+                pool: &$pool, table: &TableSpec<T>, rows: Vec<T>,
+                opts: &LoadOptions
+            ) -> Result<LoadReport> {
+                let start = Instant::now();
+                let batch_size = opts.batch_size
+                    .clamp(1, $max_placeholders / table.columns);
+                let batches: Vec<&[T]> = rows.chunks(batch_size).collect();
+                let mut report = LoadReport {
+                    table: table.name, rows: RowCounts::default(),
+                    batches: batches.len(), failed_batches: 0,
+                    errors: vec![], elapsed: Duration::ZERO,
+                };
+
+                if opts.all_or_nothing {
+                    let mut tx = pool.begin().await?;
+                    for batch in &batches {
+                        match insert_rows(&mut tx, table, batch, opts.mode).await {
+                            Ok(counts) => report.rows.add(counts),
+                            Err(e) => {
+                                tx.rollback().await?;
+                                return Err(e);
+                            }
+                        }
+                    }
+                    if let Err(e) = refresh_summaries(&mut tx, table, &rows).await {
+                        tx.rollback().await?;
+                        return Err(e);
+                    }
+                    tx.commit().await?;
+                } else {
+                    let concurrency = opts.concurrency.clamp(1, $max_concurrency);
+                    // Built in a loop rather than with 'StreamExt::map', whose
+                    // closure trips up the 'Send' check in async_trait.
+                    let mut pending = vec![];
+                    for batch in batches {
+                        pending.push(async move {
+                            (batch.len(),
+                                insert_batch(pool, table, batch, opts.mode).await)
+                        });
+                    }
+                    let results: Vec<(usize, Result<RowCounts>)> =
+                        stream::iter(pending)
+                        .buffer_unordered(concurrency)
+                        .collect().await;
+
+                    for (len, res) in results {
+                        match res {
+                            Ok(counts) => report.rows.add(counts),
+                            Err(e) => {
+                                report.errors.push(
+                                    format!("batch of {len} rows: {e}")
+                                );
+                                report.failed_batches += 1;
+                            }
+                        }
+                    }
+
+                    // Once every batch is in, as a hotel's rows can be split
+                    // across batches that would race refreshing its summary.
+                    let mut tx = pool.begin().await?;
+                    refresh_summaries(&mut tx, table, &rows).await?;
+                    tx.commit().await?;
+                }
+
+                report.elapsed = start.elapsed();
+                Ok(report)
+            }
+
+            async fn insert_batch<T>(
+                pool: &$pool, table: &TableSpec<T>, rows: &[T], mode: LoadMode
+            ) -> Result<RowCounts> {
+                let mut tx = pool.begin().await?;
+                let counts = insert_rows(&mut tx, table, rows, mode).await?;
+                tx.commit().await?;
+                Ok(counts)
+            }
+
+            /**
+             * Runs one multi-row INSERT and works out what happened to each
+             * row.
+             *
+             * Counting the keys that already existed first is enough to tell
+             * inserted, updated and unchanged rows apart from the number of
+             * affected rows.
+             */
+            async fn insert_rows<T>(
+                conn: &mut $conn, table: &TableSpec<T>, rows: &[T], mode: LoadMode
+            ) -> Result<RowCounts> {
+                let counts = match mode {
+                    LoadMode::Insert => {
+                        let mut query: QueryBuilder<$db> =
+                            QueryBuilder::new(table.insert);
+                        query.push_values(rows, table.bind_row);
+                        query.build().execute(&mut *conn).await?;
+                        RowCounts { inserted: rows.len(), ..Default::default() }
+                    },
+                    LoadMode::Upsert => upsert_rows(conn, table, rows).await?,
+                };
+                Ok(counts)
+            }
+
+            /**
+             * Recomputes the rating summaries of the hotels 'rows' belong to,
+             * once for the whole load. Their hotels are locked first, in
+             * order, like user review writes lock theirs. A review moved to
+             * another hotel by an upsert leaves its old hotel's summary stale
+             * until the next rebuild.
+             */
+            async fn refresh_summaries<T>(
+                conn: &mut $conn, table: &TableSpec<T>, rows: &[T]
+            ) -> Result<()> {
+                let Some(rated_hotel) = table.rated_hotel else {
+                    return Ok(());
+                };
+                let mut hotel_ids: Vec<i32> = rows.iter().map(rated_hotel).collect();
+                hotel_ids.sort_unstable();
+                hotel_ids.dedup();
+
+                for &hotel_id in &hotel_ids {
+                    sqlx::query(LOCK_HOTEL).bind(hotel_id).execute(&mut *conn).await?;
+                }
+                for ids in hotel_ids.chunks($max_placeholders) {
+                    let (mut delete, mut insert) = refresh_statements::<$db>(ids);
+                    delete.build().execute(&mut *conn).await?;
+                    insert.build().execute(&mut *conn).await?;
+                }
+                Ok(())
+            }
+
+            async fn upsert_rows<T>(
+                conn: &mut $conn, table: &TableSpec<T>, rows: &[T]
+            ) -> Result<RowCounts> {
+
+                let mut count: QueryBuilder<$db> =
+                    QueryBuilder::new(table.count_existing);
+                let mut keys = count.separated(", ");
+                for row in rows {
+                    (table.bind_key)(&mut keys, row);
+                }
+                keys.push_unseparated(")");
+                let (existing,): (i64,) = count.build_query_as()
+                    .fetch_one(&mut *conn).await?;
+                let existing = existing as usize;
+                let inserted = rows.len() - existing;
+
+                let mut query: QueryBuilder<$db> = QueryBuilder::new(table.insert);
+                query.push_values(rows, table.bind_row);
+                query.push(table.upsert);
+                let affected = query.build().execute(&mut *conn).await?
+                    .rows_affected() as usize;
+
+                // With found rows every row counts once and updates twice,
+                // otherwise unchanged rows aren't counted at all.
+                let updated = match $found_rows {
+                    true => affected.saturating_sub(rows.len()),
+                    false => affected.saturating_sub(inserted),
+                };
+                Ok(RowCounts {
+                    inserted,
+                    updated,
+                    unchanged: existing.saturating_sub(updated),
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use bulk_loader;
@@ -0,0 +1,180 @@
+use std::fmt;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::database::error::DbResult;
+
+/// Why a login failed, stored in 'login_events.reason'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginFailure {
+    UnknownUser,
+    WrongPassword,
+    /// The account has no password set.
+    NoPassword,
+    /// The account was locked, the password wasn't checked.
+    Locked,
+    /// The password was right but the TOTP or recovery code wasn't.
+    WrongCode,
+}
+
+impl LoginFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginFailure::UnknownUser => "unknown_user",
+            LoginFailure::WrongPassword => "wrong_password",
+            LoginFailure::NoPassword => "no_password",
+            LoginFailure::Locked => "locked",
+            LoginFailure::WrongCode => "wrong_code",
+        }
+    }
+}
+
+impl fmt::Display for LoginFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/**
+ * A row of 'login_events', one per login attempt.
+ *
+ * # Fields:
+ * - 'user_id': 'None' for unknown usernames, and once the user is deleted.
+ * - 'username': The username or email the attempt was made with.
+ * - 'reason': A 'LoginFailure', 'None' for successful logins.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct LoginEvent {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub username: String,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub success: bool,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewLoginEvent {
+    pub user_id: Option<i32>,
+    pub username: String,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub failure: Option<LoginFailure>,
+    pub created_at: DateTime<Utc>,
+}
+
+/**
+ * A row of 'login_lockouts', the failed logins of a user since their last
+ * successful one.
+ *
+ * # Fields:
+ * - 'locked_until': Logins are refused until then, 'None' if the user was
+ *   never locked since their last successful login.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct LoginLockout {
+    pub user_id: i32,
+    pub failed_logins: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl LoginLockout {
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until.is_some_and(|locked_until| locked_until > now)
+    }
+}
+
+/**
+ * When consecutive failed logins lock an account.
+ *
+ * The 'threshold'-th failure locks it for 'base', each one after it for
+ * twice as long as the one before, up to 'max'. A successful login or an
+ * admin resets the count.
+ *
+ * # Fields:
+ * - 'threshold': 0 never locks accounts.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub threshold: u32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: 5,
+            base: Duration::minutes(1),
+            max: Duration::hours(1),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// Until when 'failed_logins' consecutive failures lock the account.
+    pub fn locked_until(&self, failed_logins: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let over = i64::from(failed_logins) - i64::from(self.threshold);
+        if self.threshold == 0 || over < 0 {
+            return None;
+        }
+        // A long run of failures can't overflow it
+        let lock_secs = self.base.num_seconds().saturating_mul(1 << over.min(30));
+        Some(now + Duration::seconds(lock_secs).min(self.max))
+    }
+}
+
+#[async_trait]
+pub trait LoginEventRepository: Send + Sync {
+    async fn add_login_event(&self, event: &NewLoginEvent) -> DbResult<()>;
+
+    /// The latest 'limit' events of 'user_id', newest first.
+    async fn get_login_events(&self, user_id: i32, limit: i64) -> DbResult<Vec<LoginEvent>>;
+
+    async fn get_login_lockout(&self, user_id: i32) -> DbResult<Option<LoginLockout>>;
+
+    /**
+     * Counts a failed login of 'user_id' and locks the account as 'policy'
+     * says. Concurrent failures are all counted.
+     *
+     * # Returns:
+     * - The user's lockout after this failure.
+     */
+    async fn add_login_failure(
+        &self, user_id: i32, policy: &LockoutPolicy, now: DateTime<Utc>
+    ) -> DbResult<LoginLockout>;
+
+    /// Resets the failed logins of 'user_id', unlocking the account.
+    async fn clear_login_failures(&self, user_id: i32) -> DbResult<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn lock_secs(policy: &LockoutPolicy, failed_logins: i32) -> Option<i64> {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        policy.locked_until(failed_logins, now).map(|until| (until - now).num_seconds())
+    }
+
+    #[test]
+    fn lockouts_double_up_to_the_max() {
+        let policy = LockoutPolicy::default();
+        assert_eq!(lock_secs(&policy, 0), None);
+        assert_eq!(lock_secs(&policy, 4), None);
+        let locks: Vec<Option<i64>> = (5..=12).map(|n| lock_secs(&policy, n)).collect();
+        assert_eq!(locks, [60, 120, 240, 480, 960, 1920, 3600, 3600].map(Some));
+        assert_eq!(lock_secs(&policy, i32::MAX), Some(3600));
+    }
+
+    #[test]
+    fn a_zero_threshold_never_locks() {
+        let policy = LockoutPolicy { threshold: 0, ..LockoutPolicy::default() };
+        assert_eq!(lock_secs(&policy, 100), None);
+    }
+}
@@ -0,0 +1,318 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Result;
+
+use crate::database::error::{DbError, DbResult};
+use crate::database::sql_strs::*;
+
+/**
+ * A versioned schema change.
+ *
+ * 'up' statements are run in order to apply the migration and 'down'
+ * statements undo it. MySQL commits DDL implicitly, so a migration is
+ * recorded in 'schema_migrations' only after all of its statements ran.
+ */
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static [&'static str],
+    pub down: &'static [&'static str],
+}
+
+/// Every known MySQL migration, in the order they must be applied.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        up: &[CREATE_USERS_TABLE],
+        down: &[DROP_USERS_TABLE],
+    },
+    Migration {
+        version: 2,
+        name: "create_hotels",
+        up: &[CREATE_HOTELS_TABLE],
+        down: &[DROP_HOTELS_TABLE],
+    },
+    Migration {
+        version: 3,
+        name: "create_reviews",
+        up: &[CREATE_REVIEWS_TABLE],
+        down: &[DROP_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 4,
+        name: "create_user_reviews",
+        up: &[CREATE_USER_REVIEWS_TABLE],
+        down: &[DROP_USER_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 5,
+        name: "reviews_time_datetime",
+        up: &[
+            STRIP_REVIEWS_TIME_SUFFIX,
+            ALTER_REVIEWS_TIME_DATETIME,
+            CREATE_REVIEWS_TIME_INDEX,
+        ],
+        down: &[
+            DROP_REVIEWS_TIME_INDEX,
+            ALTER_REVIEWS_TIME_VARCHAR,
+            ADD_REVIEWS_TIME_SUFFIX,
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "fulltext_search",
+        up: &[CREATE_REVIEWS_FULLTEXT_INDEX, CREATE_HOTELS_FULLTEXT_INDEX],
+        down: &[DROP_HOTELS_FULLTEXT_INDEX, DROP_REVIEWS_FULLTEXT_INDEX],
+    },
+    Migration {
+        version: 7,
+        name: "hotel_rating_summary",
+        up: &[
+            ADD_USER_REVIEWS_RATING, ADD_USER_REVIEWS_CREATED_AT,
+            CREATE_RATING_SUMMARY_TABLE, FILL_RATING_SUMMARY,
+        ],
+        down: &[
+            DROP_RATING_SUMMARY_TABLE, DROP_USER_REVIEWS_CREATED_AT,
+            DROP_USER_REVIEWS_RATING,
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "user_passwords",
+        up: &[ADD_USERS_PASSWORD_HASH],
+        down: &[DROP_USERS_PASSWORD_HASH],
+    },
+    Migration {
+        version: 9,
+        name: "refresh_tokens",
+        up: &[CREATE_REFRESH_TOKENS_TABLE, CREATE_REFRESH_TOKENS_FAMILY_INDEX],
+        down: &[DROP_REFRESH_TOKENS_TABLE],
+    },
+    Migration {
+        version: 10,
+        name: "user_roles",
+        up: &[ADD_USERS_ROLE],
+        down: &[DROP_USERS_ROLE],
+    },
+    Migration {
+        version: 11,
+        name: "rate_limits",
+        up: &[CREATE_RATE_LIMITS_TABLE, CREATE_RATE_LIMITS_FULL_AT_INDEX],
+        down: &[DROP_RATE_LIMITS_TABLE],
+    },
+    Migration {
+        version: 12,
+        name: "email_verification",
+        up: &[
+            ADD_USERS_EMAIL_VERIFIED_AT, CREATE_EMAIL_TOKENS_TABLE,
+            CREATE_EMAIL_TOKENS_USER_INDEX,
+        ],
+        down: &[DROP_EMAIL_TOKENS_TABLE, DROP_USERS_EMAIL_VERIFIED_AT],
+    },
+    Migration {
+        version: 13,
+        name: "api_keys",
+        up: &[CREATE_API_KEYS_TABLE],
+        down: &[DROP_API_KEYS_TABLE],
+    },
+    Migration {
+        version: 14,
+        name: "login_events",
+        up: &[
+            CREATE_LOGIN_EVENTS_TABLE, CREATE_LOGIN_EVENTS_USER_INDEX,
+            CREATE_LOGIN_LOCKOUTS_TABLE,
+        ],
+        down: &[DROP_LOGIN_LOCKOUTS_TABLE, DROP_LOGIN_EVENTS_TABLE],
+    },
+    Migration {
+        version: 15,
+        name: "totp",
+        up: &[
+            CREATE_TOTP_TABLE, CREATE_RECOVERY_CODES_TABLE,
+            CREATE_RECOVERY_CODES_USER_INDEX,
+        ],
+        down: &[DROP_RECOVERY_CODES_TABLE, DROP_TOTP_TABLE],
+    },
+];
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied_at: Option<DateTime<Utc>>,
+    /// Applied in the database but unknown to this binary.
+    pub unknown: bool,
+}
+
+impl std::fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match (self.applied_at, self.unknown) {
+            (Some(t), false) => format!("applied {t}"),
+            (Some(t), true) => format!("applied {t} (unknown)"),
+            (None, _) => "pending".to_string(),
+        };
+        write!(f, "{:>4}  {:<24} {state}", self.version, self.name)
+    }
+}
+
+/**
+ * A database migrations can be run against.
+ *
+ * Each SQL backend implements this for its pool, with its own dialect's
+ * list of migrations, so the functions below work the same on all of them.
+ */
+#[async_trait]
+pub trait MigrationTarget: Sync {
+    fn migrations(&self) -> &'static [Migration];
+    async fn execute(&self, stmt: &str) -> Result<()>;
+    /// Creates 'schema_migrations' if needed and lists its rows by version.
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>>;
+    async fn record_migration(&self, migration: &Migration) -> Result<()>;
+    async fn forget_migration(&self, version: i64) -> Result<()>;
+}
+
+pub fn latest_version<T: MigrationTarget + ?Sized>(db: &T) -> i64 {
+    db.migrations().last().map(|m| m.version).unwrap_or(0)
+}
+
+/**
+ * Applies pending migrations up to and including 'target' (all of them when
+ * 'None') and returns the versions that were applied.
+ */
+pub async fn migrate_up<T: MigrationTarget + ?Sized>(
+    db: &T, target: Option<i64>
+) -> DbResult<Vec<i64>> {
+    let applied: Vec<i64> = db.applied_migrations().await?
+        .iter().map(|m| m.version).collect();
+    let target = target.unwrap_or_else(|| latest_version(db));
+    let mut done = vec![];
+
+    for migration in db.migrations() {
+        if migration.version > target || applied.contains(&migration.version) {
+            continue;
+        }
+        for stmt in migration.up {
+            db.execute(stmt).await.map_err(|e| failed(migration, e))?;
+        }
+        db.record_migration(migration).await?;
+        done.push(migration.version);
+    }
+
+    Ok(done)
+}
+
+/**
+ * Reverts the 'steps' most recently applied migrations, newest first, and
+ * returns the versions that were reverted.
+ */
+pub async fn migrate_down<T: MigrationTarget + ?Sized>(
+    db: &T, steps: usize
+) -> DbResult<Vec<i64>> {
+    let applied = db.applied_migrations().await?;
+    let mut done = vec![];
+
+    for applied in applied.iter().rev().take(steps) {
+        let migration = match db.migrations().iter()
+            .find(|m| m.version == applied.version) {
+            Some(m) => m,
+            None => return Err(DbError::Migration(format!(
+                "Can't revert unknown migration {} ({})",
+                applied.version, applied.name
+            ))),
+        };
+        for stmt in migration.down {
+            db.execute(stmt).await.map_err(|e| failed(migration, e))?;
+        }
+        db.forget_migration(migration.version).await?;
+        done.push(migration.version);
+    }
+
+    Ok(done)
+}
+
+fn failed(migration: &Migration, e: sqlx::Error) -> DbError {
+    DbError::Migration(format!("{} ({}): {e}", migration.version, migration.name))
+}
+
+pub async fn migration_status<T: MigrationTarget + ?Sized>(
+    db: &T
+) -> DbResult<Vec<MigrationStatus>> {
+    let applied = db.applied_migrations().await?;
+    let migrations = db.migrations();
+
+    let mut status: Vec<MigrationStatus> = migrations.iter().map(|m| {
+        MigrationStatus {
+            version: m.version,
+            name: m.name.to_string(),
+            applied_at: applied.iter()
+                .find(|a| a.version == m.version).map(|a| a.applied_at),
+            unknown: false,
+        }
+    }).collect();
+
+    for a in applied.iter()
+        .filter(|a| !migrations.iter().any(|m| m.version == a.version)) {
+        status.push(MigrationStatus {
+            version: a.version,
+            name: a.name.clone(),
+            applied_at: Some(a.applied_at),
+            unknown: true,
+        });
+    }
+    status.sort_by_key(|s| s.version);
+
+    Ok(status)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchemaCheck {
+    UpToDate,
+    /// Known migrations that haven't been applied yet.
+    Pending(Vec<String>),
+    /// Applied migrations this binary doesn't know about.
+    Ahead(Vec<String>),
+}
+
+impl std::fmt::Display for SchemaCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaCheck::UpToDate => write!(f, "Database schema is up to date"),
+            SchemaCheck::Pending(ms) => write!(f,
+                "Database schema is out of date, pending migrations: {}",
+                ms.join(", ")),
+            SchemaCheck::Ahead(ms) => write!(f,
+                "Database has migrations unknown to this binary: {}",
+                ms.join(", ")),
+        }
+    }
+}
+
+/// Compares the applied migrations against the known ones.
+pub async fn check_schema<T: MigrationTarget + ?Sized>(db: &T
+) -> DbResult<SchemaCheck> {
+    let status = migration_status(db).await?;
+
+    let unknown: Vec<String> = status.iter()
+        .filter(|s| s.unknown)
+        .map(|s| format!("{} ({})", s.version, s.name)).collect();
+    if !unknown.is_empty() {
+        return Ok(SchemaCheck::Ahead(unknown));
+    }
+
+    let pending: Vec<String> = status.iter()
+        .filter(|s| s.applied_at.is_none())
+        .map(|s| format!("{} ({})", s.version, s.name)).collect();
+    if !pending.is_empty() {
+        return Ok(SchemaCheck::Pending(pending));
+    }
+
+    Ok(SchemaCheck::UpToDate)
+}
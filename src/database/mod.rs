@@ -1,120 +1,140 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 use sqlx::MySqlPool;
 
+use crate::jwt::JwtKeys;
+use crate::mailer::Mail;
+use crate::passwords::Passwords;
+use crate::rate_limit::RateLimiter;
+use crate::tokens::TokenLifetimes;
+
 pub mod sql_strs;
+pub mod error;
+pub mod models;
+pub mod repository;
+pub mod mysql;
+pub mod in_memory;
+pub mod migrations;
+pub mod loader;
+pub mod search;
+pub mod summary;
+pub mod sessions;
+pub mod email_tokens;
+pub mod api_keys;
+pub mod login_events;
+pub mod totp;
+pub mod rate_limits;
+pub mod backup;
+pub mod pool;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 
 pub use sql_strs::*;
+pub use error::*;
+pub use models::*;
+pub use repository::*;
+pub use mysql::*;
+pub use in_memory::*;
+pub use migrations::*;
+pub use loader::*;
+pub use search::*;
+pub use summary::*;
+pub use sessions::*;
+pub use email_tokens::*;
+pub use api_keys::*;
+pub use login_events::*;
+pub use totp::*;
+pub use rate_limits::*;
+pub use backup::*;
+pub use pool::*;
 pub use crate::hotels_info::*;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: MySqlPool,
-    pub secret: String,
+    pub hotels: Arc<dyn HotelRepository>,
+    pub reviews: Arc<dyn ReviewRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub user_reviews: Arc<dyn UserReviewRepository>,
+    pub search: Arc<dyn SearchRepository>,
+    pub ratings: Arc<dyn RatingSummaryRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub email_tokens: Arc<dyn EmailTokenRepository>,
+    pub api_keys: Arc<dyn ApiKeyRepository>,
+    pub logins: Arc<dyn LoginEventRepository>,
+    pub totp: Arc<dyn TotpRepository>,
+    pub keys: Arc<JwtKeys>,
+    pub passwords: Passwords,
+    pub tokens: TokenLifetimes,
+    pub lockout: LockoutPolicy,
+    pub rate_limits: Arc<RateLimiter>,
+    pub mail: Mail,
 }
 
-pub async fn create_tbls(app_state: &AppState) {
-    let users_tbl = sqlx::query(
-        CREATE_USERS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = users_tbl {
-        eprintln!("Error creating 'users' table: {}", e);
+impl AppState {
+    /// Builds the state from a single type implementing every repository.
+    pub fn from_repository<R>(repo: Arc<R>) -> AppState
+    where
+        R: HotelRepository + ReviewRepository + UserRepository
+            + UserReviewRepository + SearchRepository
+            + RatingSummaryRepository + SessionRepository
+            + EmailTokenRepository + ApiKeyRepository
+            + LoginEventRepository + TotpRepository + 'static,
+    {
+        AppState {
+            hotels: repo.clone(),
+            reviews: repo.clone(),
+            users: repo.clone(),
+            user_reviews: repo.clone(),
+            search: repo.clone(),
+            ratings: repo.clone(),
+            sessions: repo.clone(),
+            email_tokens: repo.clone(),
+            api_keys: repo.clone(),
+            logins: repo.clone(),
+            totp: repo,
+            keys: Arc::new(JwtKeys::default()),
+            passwords: Passwords::default(),
+            tokens: TokenLifetimes::default(),
+            lockout: LockoutPolicy::default(),
+            rate_limits: Arc::new(RateLimiter::default()),
+            mail: Mail::default(),
+        }
     }
 
-    let hotels_tbl = sqlx::query(
-        CREATE_HOTELS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = hotels_tbl {
-        eprintln!("Error creating 'hotels' table: {}", e);
+    /// Replaces the default keys, a random HS256 secret, of access tokens.
+    pub fn with_keys(self, keys: JwtKeys) -> AppState {
+        AppState { keys: Arc::new(keys), ..self }
     }
 
-    let reviews_tbl = sqlx::query(
-        CREATE_REVIEWS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = reviews_tbl {
-        eprintln!("Error creating 'hotels' table: {}", e);
+    /// Replaces the default argon2 parameters of new password hashes.
+    pub fn with_passwords(self, passwords: Passwords) -> AppState {
+        AppState { passwords, ..self }
     }
 
-    let user_reviews_tbl = sqlx::query(
-        CREATE_USER_REVIEWS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = user_reviews_tbl {
-        eprintln!("Error creating 'users_reviews' table: {}", e);
+    pub fn with_tokens(self, tokens: TokenLifetimes) -> AppState {
+        AppState { tokens, ..self }
     }
-}
 
-pub async fn drop_tbls(app_state: &AppState) {
-    let users_tbl = sqlx::query(
-        DROP_USERS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = users_tbl {
-        eprintln!("Error creating 'users' table: {}", e);
+    pub fn with_lockout(self, lockout: LockoutPolicy) -> AppState {
+        AppState { lockout, ..self }
     }
 
-    let reviews_tbl = sqlx::query(
-        DROP_REVIEWS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = reviews_tbl {
-        eprintln!("Error creating 'hotels' table: {}", e);
+    /// Replaces the default rate limiter, which limits nothing.
+    pub fn with_rate_limits(self, rate_limits: RateLimiter) -> AppState {
+        AppState { rate_limits: Arc::new(rate_limits), ..self }
     }
 
-    let hotels_tbl = sqlx::query(
-        DROP_HOTELS_TABLE
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = hotels_tbl {
-        eprintln!("Error creating 'hotels' table: {}", e);
+    /// Replaces the default mailer, which prints emails to stdout.
+    pub fn with_mail(self, mail: Mail) -> AppState {
+        AppState { mail, ..self }
     }
-}
 
-pub async fn add_users(app_state: &AppState) {
-    let added_users = sqlx::query(
-        INSERT_USERS
-    ).execute(&app_state.pool).await;
-
-    if let Err(e) = added_users {
-        eprintln!("Error adding users: {e}");
+    pub fn mysql(pool: MySqlPool) -> AppState {
+        AppState::from_repository(Arc::new(MySqlRepository::new(pool)))
     }
-}
 
-pub async fn add_hotels_data(
-    app_state: &AppState, hotels: HashMap<i32, Hotel>
-) {    
-    for hotel in hotels.values() {
-        let added_hotel = sqlx::query(
-            INSERT_HOTEL
-        ).bind(hotel.hotel_id).bind(hotel.name.clone())
-        .bind(hotel.address.clone()).bind(hotel.city.clone())
-        .bind(hotel.province.clone()).bind(hotel.country.clone())
-        .execute(&app_state.pool).await;
-    
-        if let Err(e) = added_hotel {
-            eprintln!("Error adding users: {e}");
-        }
+    pub fn in_memory(repo: InMemoryRepository) -> AppState {
+        AppState::from_repository(Arc::new(repo))
     }
 }
-
-pub async fn add_reviews_data(
-    app_state: &AppState, reviews: HashMap<i32, Vec<Review>>
-) {    
-    for review_set in reviews.values() {
-        for review in review_set {
-            let added_review = sqlx::query(
-                INSERT_REVIEW
-            ).bind(review.review_id.clone()).bind(review.hotel_id.clone())
-            .bind(review.rating).bind(review.author.clone())
-            .bind(review.title.clone()).bind(review.text.clone())
-            .bind(review.time.to_string())
-            .execute(&app_state.pool).await;
-        
-            if let Err(e) = added_review {
-                eprintln!("Error adding users: {e}");
-            }
-        }
-    }
-}
\ No newline at end of file
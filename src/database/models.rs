@@ -0,0 +1,149 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::hotels_info::Review;
+
+/**
+ * What a user may do. Stored in 'users.role' and carried in the JWT claims,
+ * so a changed role applies from the next token refresh.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    /// Can delete other users' reviews.
+    Moderator,
+    /// Can manage users and their roles.
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::User, Role::Moderator, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL.into_iter().find(|r| r.as_str() == s)
+            .ok_or_else(|| format!("Unknown role '{s}', expected user, moderator or admin"))
+    }
+}
+
+// Lets 'sqlx::FromRow' read the VARCHAR column
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/**
+ * # Fields:
+ * - 'password_hash': An argon2 PHC string, 'None' leaves the account
+ *   unable to log in until a password is set.
+ */
+#[derive(Clone)]
+pub struct NewUser {
+    pub username: String,
+    pub email: String,
+    pub password_hash: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    /// Ignored by updates, roles are changed on their own.
+    #[serde(default)]
+    #[sqlx(try_from = "String")]
+    pub role: Role,
+}
+
+/// A user with their password hash, which never goes out in responses.
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct UserAccount {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub password_hash: Option<String>,
+    #[sqlx(try_from = "String")]
+    pub role: Role,
+    /// When the user followed a verification link sent to 'email', 'None'
+    /// until then and again after the email changes.
+    pub email_verified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct NewUserReview {
+    /// Not read from requests, the author is the token's user or the API key's creator.
+    #[serde(skip_deserializing)]
+    pub user_id: i32,
+    pub hotel_id: i32,
+    pub title: String,
+    pub text: String,
+    /// 1 to 5 stars, unrated reviews don't count towards the hotel's rating.
+    #[serde(default)]
+    pub rating: Option<i32>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct UserReview {
+    pub review_id: i32,
+    pub user_id: i32,
+    pub hotel_id: i32,
+    pub title: String,
+    pub text: String,
+    pub rating: Option<i32>,
+    /// Missing for reviews added before ratings were tracked.
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/**
+ * Optional filters for a hotel's reviews, taken from the query string
+ * (e.g. '?from=2016-01-01T00:00:00Z&order=desc').
+ *
+ * # Fields:
+ * - 'from': Only reviews submitted at or after this time.
+ * - 'to': Only reviews submitted before this time.
+ * - 'order': Sort by submission time, unsorted when absent.
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReviewFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub order: Option<SortOrder>,
+}
+
+impl ReviewFilter {
+    pub fn matches(&self, review: &Review) -> bool {
+        self.from.is_none_or(|from| review.time >= from)
+            && self.to.is_none_or(|to| review.time < to)
+    }
+}
@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use sqlx::{MySqlPool, Result};
+
+use crate::database::backup::backup_target;
+use crate::database::error::DbResult;
+use crate::database::loader::bulk_loader;
+use crate::database::migrations::*;
+use crate::database::repository::sql_repository;
+use crate::database::search::*;
+use crate::database::sql_strs::*;
+
+sql_repository!(MySqlRepository, sqlx::MySql, MySqlPool, crate::database::sql_strs);
+
+// sqlx connects to MySQL with CLIENT_FOUND_ROWS set.
+bulk_loader!(
+    sqlx::MySqlPool, sqlx::MySql, sqlx::MySqlConnection, crate::database::sql_strs,
+    max_placeholders: 65535,
+    max_concurrency: usize::MAX,
+    found_rows: true
+);
+
+backup_target!(
+    sqlx::MySqlPool, sqlx::MySql, crate::database::sql_strs,
+    max_placeholders: 65535
+);
+
+#[async_trait]
+impl MigrationTarget for MySqlPool {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn execute(&self, stmt: &str) -> Result<()> {
+        sqlx::query(stmt).execute(self).await.map(|_| ())
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        sqlx::query(CREATE_SCHEMA_MIGRATIONS_TABLE).execute(self).await?;
+        sqlx::query_as(SELECT_SCHEMA_MIGRATIONS).fetch_all(self).await
+    }
+
+    async fn record_migration(&self, migration: &Migration) -> Result<()> {
+        sqlx::query(INSERT_SCHEMA_MIGRATION)
+            .bind(migration.version).bind(migration.name)
+            .execute(self).await.map(|_| ())
+    }
+
+    async fn forget_migration(&self, version: i64) -> Result<()> {
+        sqlx::query(DELETE_SCHEMA_MIGRATION).bind(version)
+            .execute(self).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl SearchRepository for MySqlRepository {
+    /// Runs the query against the FULLTEXT indexes with MATCH ... AGAINST.
+    async fn search(&self, query: &SearchQuery) -> DbResult<SearchResults> {
+        let matcher = match query.target {
+            SearchTarget::Reviews => MATCH_REVIEWS,
+            SearchTarget::Hotels => MATCH_HOTELS,
+        };
+        let mode = match query.mode {
+            SearchMode::Natural => NATURAL_LANGUAGE_MODE,
+            SearchMode::Boolean => BOOLEAN_MODE,
+        };
+        let (mut count, mut select) = search_statements::<sqlx::MySql>(
+            query, [matcher, mode], [matcher, mode]
+        );
+
+        let (total,): (i64,) = count.build_query_as().fetch_one(self.pool()).await?;
+        let ranked = match query.target {
+            SearchTarget::Reviews => select.build_query_as::<ScoredReview>()
+                .fetch_all(self.pool()).await?.into_iter()
+                .map(|r| (SearchItem::Review(r.review), r.score)).collect(),
+            SearchTarget::Hotels => select.build_query_as::<ScoredHotel>()
+                .fetch_all(self.pool()).await?.into_iter()
+                .map(|h| (SearchItem::Hotel(h.hotel), h.score)).collect(),
+        };
+
+        Ok(SearchResults::new(query, total, ranked))
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::{MySqlPool, Result};
+
+use crate::database::*;
+
+/// How long acquiring a connection may take, so retries kick in early.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/**
+ * A connection pool to whichever SQL backend a database URL points at.
+ *
+ * 'mysql://...' URLs are always supported, 'sqlite:...' ones (a file path or
+ * 'sqlite::memory:') need the 'sqlite' cargo feature and 'postgres://...'
+ * ones the 'postgres' cargo feature.
+ */
+#[derive(Clone)]
+pub enum DbPool {
+    MySql(MySqlPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite { pool: sqlx::SqlitePool, in_memory: bool },
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::PgPool),
+}
+
+impl DbPool {
+    pub async fn connect(url: &str, max_connections: u32) -> DbResult<DbPool> {
+        if url.starts_with("mysql:") {
+            let pool = MySqlPoolOptions::new()
+                .max_connections(max_connections)
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect(url)
+                .await
+                .map_err(|source| DbError::Connection { attempts: 1, source })?;
+            return Ok(DbPool::MySql(pool));
+        }
+
+        #[cfg(feature = "sqlite")]
+        if url.starts_with("sqlite:") {
+            return connect_sqlite(url, max_connections).await
+                .map_err(|source| DbError::Connection { attempts: 1, source });
+        }
+
+        #[cfg(feature = "postgres")]
+        if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            let pool = sqlx::postgres::PgPoolOptions::new()
+                .max_connections(max_connections)
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect(url)
+                .await
+                .map_err(|source| DbError::Connection { attempts: 1, source })?;
+            return Ok(DbPool::Postgres(pool));
+        }
+
+        Err(DbError::UnsupportedUrl(url.to_string()))
+    }
+
+    /**
+     * Like 'connect', but retries failed connections with exponential
+     * backoff, for when the database is still starting up.
+     *
+     * # Parameters:
+     * - 'attempts': Connections tried before giving up, at least one.
+     * - 'delay': Wait after the first failure, doubled after each one after
+     *   that.
+     *
+     * # Returns:
+     * - The pool, or the last connection error with the number of attempts.
+     */
+    pub async fn connect_with_retry(
+        url: &str, max_connections: u32, attempts: u32, mut delay: Duration
+    ) -> DbResult<DbPool> {
+        let attempts = attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            match DbPool::connect(url, max_connections).await {
+                // A malformed URL won't get any better by waiting
+                Err(DbError::Connection { source, .. }) if attempt < attempts
+                    && !matches!(source, sqlx::Error::Configuration(_)) => {
+                    eprintln!("Couldn't connect to the database (attempt \
+                        {attempt}/{attempts}): {source}, retrying in {delay:?}");
+                    actix_web::rt::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                },
+                Err(DbError::Connection { source, .. }) => {
+                    return Err(DbError::Connection { attempts: attempt, source });
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Whether the data lives only as long as this process does.
+    pub fn is_in_memory(&self) -> bool {
+        match self {
+            DbPool::MySql(_) => false,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { in_memory, .. } => *in_memory,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(_) => false,
+        }
+    }
+
+    /// The 'rate_limits' table of the database, as a rate limit store.
+    pub fn rate_limit_store(&self) -> std::sync::Arc<dyn RateLimitRepository> {
+        match self {
+            DbPool::MySql(pool) => std::sync::Arc::new(MySqlRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } =>
+                std::sync::Arc::new(sqlite::SqliteRepository::new(pool.clone())),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) =>
+                std::sync::Arc::new(postgres::PgRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn app_state(&self) -> AppState {
+        match self {
+            DbPool::MySql(pool) => AppState::mysql(pool.clone()),
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => AppState::from_repository(
+                std::sync::Arc::new(sqlite::SqliteRepository::new(pool.clone())),
+            ),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => AppState::from_repository(
+                std::sync::Arc::new(postgres::PgRepository::new(pool.clone())),
+            ),
+        }
+    }
+}
+
+/**
+ * Every connection to 'sqlite::memory:' opens its own empty database, so an
+ * in-memory pool is pinned to a single connection that's never recycled.
+ */
+#[cfg(feature = "sqlite")]
+async fn connect_sqlite(url: &str, max_connections: u32) -> Result<DbPool> {
+    use std::str::FromStr;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    let pool = match in_memory {
+        true => SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None),
+        false => SqlitePoolOptions::new().max_connections(max_connections),
+    }.connect_with(options).await?;
+
+    Ok(DbPool::Sqlite { pool, in_memory })
+}
+
+#[async_trait]
+impl MigrationTarget for DbPool {
+    fn migrations(&self) -> &'static [Migration] {
+        match self {
+            DbPool::MySql(pool) => pool.migrations(),
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.migrations(),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.migrations(),
+        }
+    }
+
+    async fn execute(&self, stmt: &str) -> Result<()> {
+        match self {
+            DbPool::MySql(pool) => MigrationTarget::execute(pool, stmt).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => MigrationTarget::execute(pool, stmt).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => MigrationTarget::execute(pool, stmt).await,
+        }
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        match self {
+            DbPool::MySql(pool) => pool.applied_migrations().await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.applied_migrations().await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.applied_migrations().await,
+        }
+    }
+
+    async fn record_migration(&self, migration: &Migration) -> Result<()> {
+        match self {
+            DbPool::MySql(pool) => pool.record_migration(migration).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.record_migration(migration).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.record_migration(migration).await,
+        }
+    }
+
+    async fn forget_migration(&self, version: i64) -> Result<()> {
+        match self {
+            DbPool::MySql(pool) => pool.forget_migration(version).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.forget_migration(version).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.forget_migration(version).await,
+        }
+    }
+}
+
+#[async_trait]
+impl BulkLoader for DbPool {
+    async fn add_users(&self) -> DbResult<()> {
+        match self {
+            DbPool::MySql(pool) => pool.add_users().await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.add_users().await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.add_users().await,
+        }
+    }
+
+    async fn add_hotels_data(
+        &self, hotels: HashMap<i32, Hotel>, opts: &LoadOptions
+    ) -> DbResult<LoadReport> {
+        match self {
+            DbPool::MySql(pool) => pool.add_hotels_data(hotels, opts).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.add_hotels_data(hotels, opts).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.add_hotels_data(hotels, opts).await,
+        }
+    }
+
+    async fn add_reviews_data(
+        &self, reviews: HashMap<i32, Vec<Review>>, opts: &LoadOptions
+    ) -> DbResult<LoadReport> {
+        match self {
+            DbPool::MySql(pool) => pool.add_reviews_data(reviews, opts).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.add_reviews_data(reviews, opts).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.add_reviews_data(reviews, opts).await,
+        }
+    }
+}
+
+#[async_trait]
+impl BackupTarget for DbPool {
+    async fn dump_tables(
+        &self, tables: &mut [(BackupTable, &mut (dyn std::io::Write + Send))]
+    ) -> BackupResult<Vec<usize>> {
+        match self {
+            DbPool::MySql(pool) => pool.dump_tables(tables).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.dump_tables(tables).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.dump_tables(tables).await,
+        }
+    }
+
+    async fn count_rows(&self, table: BackupTable) -> BackupResult<i64> {
+        match self {
+            DbPool::MySql(pool) => pool.count_rows(table).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.count_rows(table).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.count_rows(table).await,
+        }
+    }
+
+    async fn restore_rows(&self, rows: &BackupRows) -> BackupResult<()> {
+        match self {
+            DbPool::MySql(pool) => pool.restore_rows(rows).await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.restore_rows(rows).await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.restore_rows(rows).await,
+        }
+    }
+
+    async fn finish_restore(&self) -> BackupResult<()> {
+        match self {
+            DbPool::MySql(pool) => pool.finish_restore().await,
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite { pool, .. } => pool.finish_restore().await,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => pool.finish_restore().await,
+        }
+    }
+}
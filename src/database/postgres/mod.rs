@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use sqlx::{PgPool, Result};
+
+use crate::database::backup::backup_target;
+use crate::database::error::DbResult;
+use crate::database::loader::bulk_loader;
+use crate::database::migrations::*;
+use crate::database::repository::sql_repository;
+use crate::database::search::*;
+
+pub mod sql_strs;
+
+use sql_strs::*;
+
+sql_repository!(PgRepository, sqlx::Postgres, sqlx::PgPool,
+    crate::database::postgres::sql_strs);
+
+// The upserts skip unchanged rows, so those aren't counted as affected.
+bulk_loader!(
+    sqlx::PgPool, sqlx::Postgres, sqlx::PgConnection,
+    crate::database::postgres::sql_strs,
+    max_placeholders: 65535,
+    max_concurrency: usize::MAX,
+    found_rows: false
+);
+
+backup_target!(
+    sqlx::PgPool, sqlx::Postgres, crate::database::postgres::sql_strs,
+    max_placeholders: 65535
+);
+
+/**
+ * Postgres migrations. Versions line up with the MySQL ones, but there's no
+ * legacy data to convert, so tables are created in their final shape.
+ */
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        up: &[CREATE_USERS_TABLE],
+        down: &[DROP_USERS_TABLE],
+    },
+    Migration {
+        version: 2,
+        name: "create_hotels",
+        up: &[CREATE_HOTELS_TABLE],
+        down: &[DROP_HOTELS_TABLE],
+    },
+    Migration {
+        version: 3,
+        name: "create_reviews",
+        up: &[CREATE_REVIEWS_TABLE],
+        down: &[DROP_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 4,
+        name: "create_user_reviews",
+        up: &[CREATE_USER_REVIEWS_TABLE],
+        down: &[DROP_USER_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 5,
+        name: "reviews_time_datetime",
+        up: &[CREATE_REVIEWS_TIME_INDEX],
+        down: &[DROP_REVIEWS_TIME_INDEX],
+    },
+    Migration {
+        version: 6,
+        name: "fulltext_search",
+        up: &[CREATE_REVIEWS_FULLTEXT_INDEX, CREATE_HOTELS_FULLTEXT_INDEX],
+        down: &[DROP_HOTELS_FULLTEXT_INDEX, DROP_REVIEWS_FULLTEXT_INDEX],
+    },
+    Migration {
+        version: 7,
+        name: "hotel_rating_summary",
+        up: &[
+            ADD_USER_REVIEWS_RATING, ADD_USER_REVIEWS_CREATED_AT,
+            CREATE_RATING_SUMMARY_TABLE, FILL_RATING_SUMMARY,
+        ],
+        down: &[
+            DROP_RATING_SUMMARY_TABLE, DROP_USER_REVIEWS_CREATED_AT,
+            DROP_USER_REVIEWS_RATING,
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "user_passwords",
+        up: &[ADD_USERS_PASSWORD_HASH],
+        down: &[DROP_USERS_PASSWORD_HASH],
+    },
+    Migration {
+        version: 9,
+        name: "refresh_tokens",
+        up: &[CREATE_REFRESH_TOKENS_TABLE, CREATE_REFRESH_TOKENS_FAMILY_INDEX],
+        down: &[DROP_REFRESH_TOKENS_TABLE],
+    },
+    Migration {
+        version: 10,
+        name: "user_roles",
+        up: &[ADD_USERS_ROLE],
+        down: &[DROP_USERS_ROLE],
+    },
+    Migration {
+        version: 11,
+        name: "rate_limits",
+        up: &[CREATE_RATE_LIMITS_TABLE, CREATE_RATE_LIMITS_FULL_AT_INDEX],
+        down: &[DROP_RATE_LIMITS_TABLE],
+    },
+    Migration {
+        version: 12,
+        name: "email_verification",
+        up: &[
+            ADD_USERS_EMAIL_VERIFIED_AT, CREATE_EMAIL_TOKENS_TABLE,
+            CREATE_EMAIL_TOKENS_USER_INDEX,
+        ],
+        down: &[DROP_EMAIL_TOKENS_TABLE, DROP_USERS_EMAIL_VERIFIED_AT],
+    },
+    Migration {
+        version: 13,
+        name: "api_keys",
+        up: &[CREATE_API_KEYS_TABLE],
+        down: &[DROP_API_KEYS_TABLE],
+    },
+    Migration {
+        version: 14,
+        name: "login_events",
+        up: &[
+            CREATE_LOGIN_EVENTS_TABLE, CREATE_LOGIN_EVENTS_USER_INDEX,
+            CREATE_LOGIN_LOCKOUTS_TABLE,
+        ],
+        down: &[DROP_LOGIN_LOCKOUTS_TABLE, DROP_LOGIN_EVENTS_TABLE],
+    },
+    Migration {
+        version: 15,
+        name: "totp",
+        up: &[
+            CREATE_TOTP_TABLE, CREATE_RECOVERY_CODES_TABLE,
+            CREATE_RECOVERY_CODES_USER_INDEX,
+        ],
+        down: &[DROP_RECOVERY_CODES_TABLE, DROP_TOTP_TABLE],
+    },
+];
+
+#[async_trait]
+impl MigrationTarget for PgPool {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn execute(&self, stmt: &str) -> Result<()> {
+        sqlx::query(stmt).execute(self).await.map(|_| ())
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        sqlx::query(CREATE_SCHEMA_MIGRATIONS_TABLE).execute(self).await?;
+        sqlx::query_as(SELECT_SCHEMA_MIGRATIONS).fetch_all(self).await
+    }
+
+    async fn record_migration(&self, migration: &Migration) -> Result<()> {
+        sqlx::query(INSERT_SCHEMA_MIGRATION)
+            .bind(migration.version).bind(migration.name)
+            .execute(self).await.map(|_| ())
+    }
+
+    async fn forget_migration(&self, version: i64) -> Result<()> {
+        sqlx::query(DELETE_SCHEMA_MIGRATION).bind(version)
+            .execute(self).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl SearchRepository for PgRepository {
+    /// Ranks rows with 'ts_rank' over the GIN-indexed text search vectors.
+    async fn search(&self, query: &SearchQuery) -> DbResult<SearchResults> {
+        let document = match query.target {
+            SearchTarget::Reviews => REVIEWS_DOCUMENT,
+            SearchTarget::Hotels => HOTELS_DOCUMENT,
+        };
+        let [tsquery, tsquery_end] = match query.mode {
+            SearchMode::Natural => NATURAL_TSQUERY,
+            SearchMode::Boolean => BOOLEAN_TSQUERY,
+        };
+        let score = format!("ts_rank({document}, {tsquery}");
+        let score_end = format!("{tsquery_end})::float8");
+        let matches = format!("{document} @@ {tsquery}");
+        let (mut count, mut select) = search_statements::<sqlx::Postgres>(
+            query, [&score, &score_end], [&matches, tsquery_end]
+        );
+
+        let (total,): (i64,) = count.build_query_as().fetch_one(self.pool()).await?;
+        let ranked = match query.target {
+            SearchTarget::Reviews => select.build_query_as::<ScoredReview>()
+                .fetch_all(self.pool()).await?.into_iter()
+                .map(|r| (SearchItem::Review(r.review), r.score)).collect(),
+            SearchTarget::Hotels => select.build_query_as::<ScoredHotel>()
+                .fetch_all(self.pool()).await?.into_iter()
+                .map(|h| (SearchItem::Hotel(h.hotel), h.score)).collect(),
+        };
+
+        Ok(SearchResults::new(query, total, ranked))
+    }
+}
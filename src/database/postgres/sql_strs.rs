@@ -0,0 +1,304 @@
+// Postgres numbers its placeholders ('$1', '$2', ...) and has its own DDL,
+// so every statement that binds values or creates a table is overridden.
+pub use crate::database::sql_strs::*;
+
+pub const CREATE_USERS_TABLE: &str = "\
+    create table if not exists users(id SERIAL PRIMARY KEY, \
+    username VARCHAR(15) NOT NULL UNIQUE, email VARCHAR(255) NOT NULL UNIQUE)";
+
+pub const CREATE_REVIEWS_TABLE: &str = "\
+    create table if not exists reviews(review_id VARCHAR(100) NOT NULL, \
+    hotel_id INT NOT NULL, rating INT NOT NULL, \
+    author VARCHAR(100), title VARCHAR(200), text VARCHAR(5000), \
+    time TIMESTAMPTZ NOT NULL, PRIMARY KEY(review_id), \
+    FOREIGN KEY(hotel_id) REFERENCES hotels(hotel_id))";
+
+pub const CREATE_USER_REVIEWS_TABLE: &str = "\
+    create table if not exists user_reviews(review_id SERIAL PRIMARY KEY, \
+    user_id INT NOT NULL, hotel_id INT NOT NULL, title VARCHAR(200), \
+    text VARCHAR(5000), FOREIGN KEY(user_id) REFERENCES users(id), \
+    FOREIGN KEY(hotel_id) REFERENCES hotels(hotel_id))";
+
+pub const CREATE_SCHEMA_MIGRATIONS_TABLE: &str = "\
+    create table if not exists schema_migrations(version BIGINT PRIMARY KEY, \
+    name VARCHAR(200) NOT NULL, \
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP)";
+
+pub const UPSERT_HOTELS: &str = " on conflict(hotel_id) do update set \
+    name = excluded.name, address = excluded.address, city = excluded.city, \
+    province = excluded.province, country = excluded.country \
+    where (hotels.name, hotels.address, hotels.city, hotels.province, \
+    hotels.country) is distinct from (excluded.name, excluded.address, \
+    excluded.city, excluded.province, excluded.country)";
+
+pub const UPSERT_REVIEWS: &str = " on conflict(review_id) do update set \
+    hotel_id = excluded.hotel_id, rating = excluded.rating, \
+    author = excluded.author, title = excluded.title, text = excluded.text, \
+    time = excluded.time \
+    where (reviews.hotel_id, reviews.rating, reviews.author, reviews.title, \
+    reviews.text, reviews.time) is distinct from (excluded.hotel_id, \
+    excluded.rating, excluded.author, excluded.title, excluded.text, \
+    excluded.time)";
+
+pub const INSERT_USER_REVIEW: &str = "insert into user_reviews(user_id, \
+    hotel_id, title, text, rating, created_at) values ($1, $2, $3, $4, $5, $6)";
+
+pub const INSERT_USER: &str = "insert into users(username, email, \
+    password_hash) values ($1, $2, $3)";
+
+pub const SELECT_USER: &str = "select * from users where id = $1";
+
+pub const SELECT_USER_BY_CREDS: &str = "select * from users where \
+    username = $1 or email = $2";
+
+pub const SELECT_HOTEL: &str = "select * from hotels where hotel_id = $1";
+
+// MySQL's default collation makes LIKE case-insensitive, ILIKE matches that.
+pub const SELECT_LIKE_HOTELS: &str = "select * from hotels where name ilike $1";
+
+pub const SELECT_USER_REVIEWS_BY_USERNAME: &str = "select user_reviews.* \
+    from user_reviews inner join users on user_reviews.user_id = users.id \
+    where users.username = $1";
+
+pub const SELECT_USER_REVIEWS_BY_HOTEL_ID: &str = "select * from user_reviews \
+    where hotel_id = $1";
+
+pub const SELECT_USER_REVIEW: &str = "select * from user_reviews \
+    where review_id = $1";
+
+pub const DELETE_USER: &str = "delete from users where id = $1";
+
+pub const SELECT_USER_REVIEW_HOTEL_ID: &str = "select hotel_id \
+    from user_reviews where review_id = $1";
+
+/// 'no key update' leaves the key share lock of foreign key checks free.
+pub const LOCK_HOTEL: &str = "select hotel_id from hotels where hotel_id = $1 \
+    for no key update";
+
+pub const DELETE_USER_REVIEW: &str = "delete from user_reviews where \
+    review_id = $1";
+
+// Unlike MySQL, every assignment reads the old row, the order doesn't matter.
+pub const UPDATE_USER: &str = "update users set email_verified_at = \
+    case when email = $1 then email_verified_at else null end, \
+    username = $2, email = $3 where id = $4";
+
+pub const UPDATE_USER_PASSWORD_HASH: &str = "update users set \
+    password_hash = $1 where id = $2";
+
+pub const UPDATE_USER_ROLE: &str = "update users set role = $1 where id = $2";
+
+pub const INSERT_SCHEMA_MIGRATION: &str = "insert into \
+    schema_migrations(version, name) values ($1, $2)";
+
+pub const DELETE_SCHEMA_MIGRATION: &str = "delete from schema_migrations \
+    where version = $1";
+
+pub const DROP_REVIEWS_TIME_INDEX: &str = "drop index reviews_hotel_time";
+
+// The same expressions the GIN indexes are built on, so they get used.
+pub const REVIEWS_DOCUMENT: &str = "to_tsvector('english', \
+    coalesce(reviews.title, '') || ' ' || coalesce(reviews.text, ''))";
+
+pub const HOTELS_DOCUMENT: &str = "to_tsvector('english', \
+    hotels.name || ' ' || hotels.city)";
+
+// plainto_tsquery ANDs the words, natural mode matches any of them instead.
+pub const NATURAL_TSQUERY: [&str; 2] = [
+    "replace(plainto_tsquery('english', ", ")::text, '&', '|')::tsquery",
+];
+
+pub const BOOLEAN_TSQUERY: [&str; 2] = ["websearch_to_tsquery('english', ", ")"];
+
+pub const CREATE_REVIEWS_FULLTEXT_INDEX: &str = "create index reviews_fulltext \
+    on reviews using gin (to_tsvector('english', \
+    coalesce(title, '') || ' ' || coalesce(text, '')))";
+
+pub const CREATE_HOTELS_FULLTEXT_INDEX: &str = "create index hotels_fulltext \
+    on hotels using gin (to_tsvector('english', name || ' ' || city))";
+
+pub const DROP_REVIEWS_FULLTEXT_INDEX: &str = "drop index reviews_fulltext";
+
+pub const DROP_HOTELS_FULLTEXT_INDEX: &str = "drop index hotels_fulltext";
+
+pub const ADD_USER_REVIEWS_CREATED_AT: &str = "alter table user_reviews \
+    add created_at TIMESTAMPTZ";
+
+pub const CREATE_RATING_SUMMARY_TABLE: &str = "\
+    create table if not exists hotel_rating_summary(\
+    hotel_id INT PRIMARY KEY REFERENCES hotels(hotel_id), \
+    review_count INT NOT NULL, rating_sum INT NOT NULL, \
+    rating_1 INT NOT NULL, rating_2 INT NOT NULL, rating_3 INT NOT NULL, \
+    rating_4 INT NOT NULL, rating_5 INT NOT NULL, \
+    last_review_time TIMESTAMPTZ)";
+
+pub const SELECT_RATING_SUMMARY: &str = "select * from hotel_rating_summary \
+    where hotel_id = $1";
+
+// Must come first in the transaction, statements otherwise each see the
+// latest committed rows.
+pub const BEGIN_SNAPSHOT: &[&str] = &[
+    "set transaction isolation level repeatable read, read only",
+];
+
+// SERIAL sequences don't notice explicitly inserted ids.
+pub const RESET_SEQUENCES: &[&str] = &[
+    "select setval(pg_get_serial_sequence('users', 'id'), max(id)) from users",
+    "select setval(pg_get_serial_sequence('user_reviews', 'review_id'), \
+        max(review_id)) from user_reviews",
+];
+
+pub const CREATE_REFRESH_TOKENS_TABLE: &str = "\
+    create table if not exists refresh_tokens(id SERIAL PRIMARY KEY, \
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+    family CHAR(32) NOT NULL, token_hash CHAR(64) NOT NULL UNIQUE, \
+    created_at TIMESTAMPTZ NOT NULL, expires_at TIMESTAMPTZ NOT NULL, \
+    used_at TIMESTAMPTZ, revoked_at TIMESTAMPTZ)";
+
+pub const INSERT_REFRESH_TOKEN: &str = "insert into refresh_tokens(user_id, \
+    family, token_hash, created_at, expires_at) values ($1, $2, $3, $4, $5)";
+
+pub const SELECT_REFRESH_TOKEN: &str = "select * from refresh_tokens \
+    where token_hash = $1";
+
+pub const MARK_REFRESH_TOKEN_USED: &str = "update refresh_tokens \
+    set used_at = $1 where id = $2 and used_at is null and revoked_at is null";
+
+pub const REVOKE_TOKEN_FAMILY: &str = "update refresh_tokens \
+    set revoked_at = $1 where family = $2 and revoked_at is null";
+
+pub const REVOKE_USER_TOKENS: &str = "update refresh_tokens \
+    set revoked_at = $1 where user_id = $2 and revoked_at is null";
+
+pub const CREATE_RATE_LIMITS_TABLE: &str = "\
+    create table if not exists rate_limits(bucket_key VARCHAR(255) PRIMARY KEY, \
+    tokens DOUBLE PRECISION NOT NULL, updated_at TIMESTAMPTZ NOT NULL, \
+    full_at TIMESTAMPTZ NOT NULL, version INT NOT NULL)";
+
+pub const SELECT_RATE_LIMIT: &str = "select * from rate_limits \
+    where bucket_key = $1";
+
+pub const INSERT_RATE_LIMIT: &str = "insert into rate_limits(\
+    bucket_key, tokens, updated_at, full_at, version) values ($1, $2, $3, $4, 0) \
+    on conflict do nothing";
+
+pub const UPDATE_RATE_LIMIT: &str = "update rate_limits set tokens = $1, \
+    updated_at = $2, full_at = $3, version = version + 1 \
+    where bucket_key = $4 and version = $5";
+
+pub const DELETE_FULL_RATE_LIMITS: &str = "delete from rate_limits \
+    where full_at <= $1";
+
+pub const ADD_USERS_EMAIL_VERIFIED_AT: &str = "alter table users \
+    add email_verified_at TIMESTAMPTZ";
+
+pub const CREATE_EMAIL_TOKENS_TABLE: &str = "\
+    create table if not exists email_tokens(id SERIAL PRIMARY KEY, \
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+    purpose VARCHAR(32) NOT NULL, token_id CHAR(32) NOT NULL UNIQUE, \
+    created_at TIMESTAMPTZ NOT NULL, expires_at TIMESTAMPTZ NOT NULL, \
+    used_at TIMESTAMPTZ)";
+
+pub const INSERT_EMAIL_TOKEN: &str = "insert into email_tokens(user_id, \
+    purpose, token_id, created_at, expires_at) values ($1, $2, $3, $4, $5)";
+
+pub const REVOKE_EMAIL_TOKENS: &str = "update email_tokens set used_at = $1 \
+    where user_id = $2 and purpose = $3 and used_at is null";
+
+pub const USE_EMAIL_TOKEN: &str = "update email_tokens set used_at = $1 \
+    where token_id = $2 and purpose = $3 and used_at is null and expires_at > $4";
+
+pub const SELECT_EMAIL_TOKEN_USER: &str = "select user_id from email_tokens \
+    where token_id = $1";
+
+pub const UPDATE_USER_EMAIL_VERIFIED: &str = "update users set \
+    email_verified_at = $1 where id = $2 and email = $3";
+
+pub const CREATE_API_KEYS_TABLE: &str = "\
+    create table if not exists api_keys(id SERIAL PRIMARY KEY, \
+    name VARCHAR(100) NOT NULL, prefix CHAR(12) NOT NULL UNIQUE, \
+    secret_hash CHAR(64) NOT NULL, scopes VARCHAR(255) NOT NULL, \
+    created_by INT REFERENCES users(id) ON DELETE SET NULL, \
+    created_at TIMESTAMPTZ NOT NULL, expires_at TIMESTAMPTZ, \
+    last_used_at TIMESTAMPTZ, revoked_at TIMESTAMPTZ)";
+
+pub const INSERT_API_KEY: &str = "insert into api_keys(name, prefix, \
+    secret_hash, scopes, created_by, created_at, expires_at) \
+    values ($1, $2, $3, $4, $5, $6, $7)";
+
+pub const SELECT_API_KEY: &str = "select * from api_keys where prefix = $1";
+
+pub const REVOKE_API_KEY: &str = "update api_keys set revoked_at = $1 \
+    where id = $2 and revoked_at is null";
+
+pub const TOUCH_API_KEY: &str = "update api_keys set last_used_at = $1 \
+    where id = $2";
+
+pub const CREATE_LOGIN_EVENTS_TABLE: &str = "\
+    create table if not exists login_events(id SERIAL PRIMARY KEY, \
+    user_id INT REFERENCES users(id) ON DELETE SET NULL, \
+    username VARCHAR(255) NOT NULL, ip VARCHAR(45) NOT NULL, \
+    user_agent VARCHAR(255), success BOOLEAN NOT NULL, reason VARCHAR(32), \
+    created_at TIMESTAMPTZ NOT NULL)";
+
+pub const CREATE_LOGIN_LOCKOUTS_TABLE: &str = "\
+    create table if not exists login_lockouts(\
+    user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE, \
+    failed_logins INT NOT NULL, locked_until TIMESTAMPTZ)";
+
+pub const INSERT_LOGIN_EVENT: &str = "insert into login_events(user_id, \
+    username, ip, user_agent, success, reason, created_at) \
+    values ($1, $2, $3, $4, $5, $6, $7)";
+
+pub const SELECT_LOGIN_EVENTS: &str = "select * from login_events \
+    where user_id = $1 order by id desc limit $2";
+
+pub const SELECT_LOGIN_LOCKOUT: &str = "select * from login_lockouts \
+    where user_id = $1";
+
+pub const INCREMENT_LOGIN_FAILURES: &str = "insert into login_lockouts(\
+    user_id, failed_logins) values ($1, 1) on conflict(user_id) \
+    do update set failed_logins = login_lockouts.failed_logins + 1";
+
+pub const UPDATE_LOGIN_LOCKED_UNTIL: &str = "update login_lockouts \
+    set locked_until = $1 where user_id = $2";
+
+pub const DELETE_LOGIN_LOCKOUT: &str = "delete from login_lockouts \
+    where user_id = $1";
+
+pub const CREATE_TOTP_TABLE: &str = "\
+    create table if not exists totp(\
+    user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE, \
+    secret VARCHAR(64) NOT NULL, confirmed_at TIMESTAMPTZ, \
+    last_used_step BIGINT, created_at TIMESTAMPTZ NOT NULL)";
+
+pub const CREATE_RECOVERY_CODES_TABLE: &str = "\
+    create table if not exists recovery_codes(id SERIAL PRIMARY KEY, \
+    user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+    code_hash CHAR(64) NOT NULL, used_at TIMESTAMPTZ)";
+
+pub const SELECT_TOTP: &str = "select * from totp where user_id = $1";
+
+pub const DELETE_UNCONFIRMED_TOTP: &str = "delete from totp \
+    where user_id = $1 and confirmed_at is null";
+
+pub const INSERT_TOTP: &str = "insert into totp(user_id, secret, created_at) \
+    values ($1, $2, $3)";
+
+pub const CONFIRM_TOTP: &str = "update totp set confirmed_at = $1, \
+    last_used_step = $2 where user_id = $3 and confirmed_at is null";
+
+pub const USE_TOTP_STEP: &str = "update totp set last_used_step = $1 \
+    where user_id = $2 and confirmed_at is not null \
+    and (last_used_step is null or last_used_step < $3)";
+
+pub const DELETE_TOTP: &str = "delete from totp where user_id = $1";
+
+pub const INSERT_RECOVERY_CODE: &str = "insert into recovery_codes(\
+    user_id, code_hash) values ($1, $2)";
+
+pub const USE_RECOVERY_CODE: &str = "update recovery_codes set used_at = $1 \
+    where user_id = $2 and code_hash = $3 and used_at is null";
+
+pub const DELETE_RECOVERY_CODES: &str = "delete from recovery_codes \
+    where user_id = $1";
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::error::DbResult;
+
+/**
+ * A token bucket: a client may make 'burst' requests at once, then the
+ * bucket refills at 'per_minute' requests a minute.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/**
+ * The outcome of taking a token.
+ *
+ * # Fields:
+ * - 'tokens': What's left in the bucket, a fraction while it refills.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Take {
+    pub allowed: bool,
+    pub tokens: f64,
+}
+
+impl Policy {
+    fn per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// The tokens of a bucket that had 'tokens' at 'since', by 'now'.
+    pub fn refill(&self, tokens: f64, since: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+        let elapsed = (now - since).num_milliseconds().max(0) as f64 / 1000.0;
+        (tokens + elapsed * self.per_second()).min(f64::from(self.burst))
+    }
+
+    /// Takes a token from a bucket holding 'tokens', if there's a whole one.
+    pub fn take(&self, tokens: f64) -> Take {
+        match tokens >= 1.0 {
+            true => Take { allowed: true, tokens: tokens - 1.0 },
+            false => Take { allowed: false, tokens },
+        }
+    }
+
+    fn seconds_until(&self, tokens: f64, target: f64) -> i64 {
+        ((target - tokens).max(0.0) / self.per_second()).ceil() as i64
+    }
+
+    /// Seconds until a bucket holding 'tokens' has a whole token.
+    pub fn retry_after(&self, tokens: f64) -> i64 {
+        self.seconds_until(tokens, 1.0)
+    }
+
+    /// Seconds until a bucket holding 'tokens' is full again.
+    pub fn reset_after(&self, tokens: f64) -> i64 {
+        self.seconds_until(tokens, f64::from(self.burst))
+    }
+}
+
+/**
+ * A row of 'rate_limits'.
+ *
+ * # Fields:
+ * - 'full_at': When the bucket is full again, from then on it's the same as
+ *   no row and can be pruned.
+ * - 'version': Bumped on every update, so concurrent updates of a bucket
+ *   don't overwrite each other.
+ */
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Bucket {
+    pub bucket_key: String,
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+    pub full_at: DateTime<Utc>,
+    pub version: i32,
+}
+
+/// Attempts at updating a bucket other requests keep updating first.
+pub const TAKE_ATTEMPTS: usize = 5;
+
+/**
+ * Token buckets of the rate limiter, by key.
+ *
+ * Keys are made by the caller, e.g. 'auth:ip:127.0.0.1'. A key without a
+ * bucket has a full one.
+ */
+#[async_trait]
+pub trait RateLimitRepository: Send + Sync {
+    /**
+     * Refills the bucket of 'key' and takes a token from it, atomically.
+     *
+     * SQL backends give up after 'TAKE_ATTEMPTS' lost races for the same
+     * bucket and deny the request, its client is busy enough already.
+     */
+    async fn take_token(&self, key: &str, policy: Policy, now: DateTime<Utc>
+    ) -> DbResult<Take>;
+
+    /// Deletes the buckets that are full by 'now', returns how many.
+    async fn prune_buckets(&self, now: DateTime<Utc>) -> DbResult<u64>;
+}
+
+/// When a bucket left with 'tokens' at 'now' is full again.
+pub fn full_at(policy: Policy, tokens: f64, now: DateTime<Utc>) -> DateTime<Utc> {
+    now + Duration::seconds(policy.reset_after(tokens))
+}
+
+/// Buckets kept in the server's memory, the default store.
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimits {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl InMemoryRateLimits {
+    pub fn new() -> InMemoryRateLimits {
+        InMemoryRateLimits::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitRepository for InMemoryRateLimits {
+    async fn take_token(&self, key: &str, policy: Policy, now: DateTime<Utc>
+    ) -> DbResult<Take> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let tokens = match buckets.get(key) {
+            Some(bucket) => policy.refill(bucket.tokens, bucket.updated_at, now),
+            None => f64::from(policy.burst),
+        };
+        let take = policy.take(tokens);
+        let version = buckets.get(key).map_or(0, |b| b.version + 1);
+        buckets.insert(key.to_string(), Bucket {
+            bucket_key: key.to_string(),
+            tokens: take.tokens,
+            updated_at: now,
+            full_at: full_at(policy, take.tokens, now),
+            version,
+        });
+        Ok(take)
+    }
+
+    async fn prune_buckets(&self, now: DateTime<Utc>) -> DbResult<u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, bucket| bucket.full_at > now);
+        Ok((before - buckets.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Half a token a second.
+    const POLICY: Policy = Policy { burst: 3, per_minute: 30 };
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn buckets_refill_up_to_the_burst() {
+        assert_eq!(POLICY.refill(0.0, at(0), at(3)), 1.5);
+        assert_eq!(POLICY.refill(0.25, at(0), at(0)), 0.25);
+        assert_eq!(POLICY.refill(1.0, at(0), at(3600)), 3.0);
+        // A clock going backwards doesn't drain the bucket
+        assert_eq!(POLICY.refill(1.0, at(10), at(0)), 1.0);
+    }
+
+    #[test]
+    fn takes_need_a_whole_token() {
+        assert_eq!(POLICY.take(1.5), Take { allowed: true, tokens: 0.5 });
+        assert_eq!(POLICY.take(1.0), Take { allowed: true, tokens: 0.0 });
+        assert_eq!(POLICY.take(0.75), Take { allowed: false, tokens: 0.75 });
+    }
+
+    #[test]
+    fn retries_wait_for_the_next_token() {
+        assert_eq!(POLICY.retry_after(0.0), 2);
+        assert_eq!(POLICY.retry_after(0.25), 2);
+        assert_eq!(POLICY.retry_after(0.75), 1);
+        assert_eq!(POLICY.retry_after(2.0), 0);
+        assert_eq!(POLICY.reset_after(0.25), 6);
+        assert_eq!(POLICY.reset_after(3.0), 0);
+        assert_eq!(full_at(POLICY, 1.0, at(0)), at(4));
+    }
+
+    #[actix_web::test]
+    async fn in_memory_buckets_limit_each_key() {
+        let store = InMemoryRateLimits::new();
+        for tokens in [2.0, 1.0, 0.0] {
+            let take = store.take_token("a", POLICY, at(0)).await.unwrap();
+            assert_eq!(take, Take { allowed: true, tokens });
+        }
+        assert!(!store.take_token("a", POLICY, at(1)).await.unwrap().allowed);
+        assert!(store.take_token("b", POLICY, at(1)).await.unwrap().allowed);
+        // Denied takes don't cost anything, 2 seconds is a whole token
+        assert!(store.take_token("a", POLICY, at(2)).await.unwrap().allowed);
+
+        assert_eq!(store.prune_buckets(at(5)).await.unwrap(), 1);
+        assert_eq!(store.prune_buckets(at(3600)).await.unwrap(), 1);
+    }
+}
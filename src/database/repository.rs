@@ -0,0 +1,651 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DbResult;
+use crate::database::models::*;
+use crate::hotels_info::{Hotel, Review};
+
+/**
+ * Repository traits sitting between the routes and the database.
+ *
+ * 'AppState' holds one trait object per repository, so handlers never touch
+ * a pool directly. 'MySqlRepository' is the production implementation and
+ * 'InMemoryRepository' backs the in-memory mode and the handler tests in
+ * 'tests/'.
+ */
+#[async_trait]
+pub trait HotelRepository: Send + Sync {
+    async fn get_hotel(&self, hotel_id: i32) -> DbResult<Option<Hotel>>;
+    async fn get_all_hotels(&self) -> DbResult<Vec<Hotel>>;
+    async fn get_like_hotels(&self, hotel_name: &str) -> DbResult<Vec<Hotel>>;
+}
+
+#[async_trait]
+pub trait ReviewRepository: Send + Sync {
+    async fn get_hotel_reviews(&self, hotel_id: i32, filter: &ReviewFilter
+    ) -> DbResult<Vec<Review>>;
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn add_user(&self, user: &NewUser) -> DbResult<()>;
+    async fn get_user(&self, user_id: i32) -> DbResult<Option<User>>;
+    async fn get_user_by_creds(&self, username: &str, email: &str
+    ) -> DbResult<Option<User>>;
+    async fn get_all_users(&self) -> DbResult<Vec<User>>;
+    async fn update_user(&self, user: &User) -> DbResult<()>;
+    async fn delete_user(&self, user_id: i32) -> DbResult<()>;
+    async fn get_account(&self, user_id: i32) -> DbResult<Option<UserAccount>>;
+    /// Returns false when there's no user 'user_id'.
+    async fn set_password_hash(&self, user_id: i32, password_hash: &str
+    ) -> DbResult<bool>;
+    /// Returns false when there's no user 'user_id'.
+    async fn set_role(&self, user_id: i32, role: Role) -> DbResult<bool>;
+    /// Returns false when there's no user 'user_id' with 'email' any more.
+    async fn set_email_verified(&self, user_id: i32, email: &str, verified_at: DateTime<Utc>
+    ) -> DbResult<bool>;
+}
+
+#[async_trait]
+pub trait UserReviewRepository: Send + Sync {
+    async fn add_user_review(&self, review: &NewUserReview) -> DbResult<()>;
+    async fn get_all_user_reviews(&self) -> DbResult<Vec<UserReview>>;
+    async fn get_user_reviews_by_username(&self, username: &str
+    ) -> DbResult<Vec<UserReview>>;
+    async fn get_user_reviews_by_hotel_id(&self, hotel_id: i32
+    ) -> DbResult<Vec<UserReview>>;
+    async fn get_user_review(&self, review_id: i32) -> DbResult<Option<UserReview>>;
+    async fn delete_user_review(&self, review_id: i32) -> DbResult<()>;
+}
+
+/**
+ * Implements every repository trait for '$repo', a wrapper around a pool of
+ * the sqlx database '$db', using the queries of the '$strs' dialect module.
+ *
+ * Each SQL backend instantiates this once, so the query logic is shared and
+ * only the SQL strings differ between dialects.
+ */
+macro_rules! sql_repository {
+    ($repo:ident, $db:ty, $pool:ty, $strs:path) => {
+        #[derive(Clone)]
+        pub struct $repo {
+            pool: $pool,
+        }
+
+        impl $repo {
+            pub fn new(pool: $pool) -> $repo {
+                $repo { pool }
+            }
+
+            pub fn pool(&self) -> &$pool {
+                &self.pool
+            }
+        }
+
+        mod queries {
+            pub use $strs::*;
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::HotelRepository for $repo {
+            async fn get_hotel(&self, hotel_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::hotels_info::Hotel>> {
+                Ok(sqlx::query_as(queries::SELECT_HOTEL).bind(hotel_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn get_all_hotels(&self
+            ) -> $crate::database::DbResult<Vec<$crate::hotels_info::Hotel>> {
+                Ok(sqlx::query_as(queries::SELECT_ALL_HOTELS)
+                    .fetch_all(&self.pool).await?)
+            }
+
+            async fn get_like_hotels(&self, hotel_name: &str
+            ) -> $crate::database::DbResult<Vec<$crate::hotels_info::Hotel>> {
+                let pattern = "%".to_string() + hotel_name + "%";
+                Ok(sqlx::query_as(queries::SELECT_LIKE_HOTELS).bind(pattern)
+                    .fetch_all(&self.pool).await?)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::ReviewRepository for $repo {
+            async fn get_hotel_reviews(
+                &self, hotel_id: i32, filter: &$crate::database::ReviewFilter
+            ) -> $crate::database::DbResult<Vec<$crate::hotels_info::Review>> {
+                use $crate::database::SortOrder;
+
+                let mut query: sqlx::QueryBuilder<$db> =
+                    sqlx::QueryBuilder::new(queries::SELECT_HOTEL_REVIEWS);
+                query.push_bind(hotel_id);
+                if let Some(from) = filter.from {
+                    query.push(" and time >= ").push_bind(from);
+                }
+                if let Some(to) = filter.to {
+                    query.push(" and time < ").push_bind(to);
+                }
+                match filter.order {
+                    Some(SortOrder::Asc) => { query.push(" order by time asc"); },
+                    Some(SortOrder::Desc) => { query.push(" order by time desc"); },
+                    None => {},
+                }
+                Ok(query.build_query_as().fetch_all(&self.pool).await?)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::UserRepository for $repo {
+            async fn add_user(&self, user: &$crate::database::NewUser
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::INSERT_USER)
+                    .bind(&user.username).bind(&user.email)
+                    .bind(&user.password_hash)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn get_user(&self, user_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::User>> {
+                Ok(sqlx::query_as(queries::SELECT_USER).bind(user_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn get_user_by_creds(&self, username: &str, email: &str
+            ) -> $crate::database::DbResult<Option<$crate::database::User>> {
+                Ok(sqlx::query_as(queries::SELECT_USER_BY_CREDS)
+                    .bind(username).bind(email)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn get_all_users(&self
+            ) -> $crate::database::DbResult<Vec<$crate::database::User>> {
+                Ok(sqlx::query_as(queries::SELECT_ALL_USERS)
+                    .fetch_all(&self.pool).await?)
+            }
+
+            async fn update_user(&self, user: &$crate::database::User
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::UPDATE_USER)
+                    .bind(&user.email).bind(&user.username).bind(&user.email)
+                    .bind(user.id)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn delete_user(&self, user_id: i32
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::DELETE_USER).bind(user_id)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn get_account(&self, user_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::UserAccount>> {
+                Ok(sqlx::query_as(queries::SELECT_USER).bind(user_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn set_password_hash(&self, user_id: i32, password_hash: &str
+            ) -> $crate::database::DbResult<bool> {
+                let updated = sqlx::query(queries::UPDATE_USER_PASSWORD_HASH)
+                    .bind(password_hash).bind(user_id)
+                    .execute(&self.pool).await?;
+                Ok(updated.rows_affected() > 0)
+            }
+
+            async fn set_role(&self, user_id: i32, role: $crate::database::Role
+            ) -> $crate::database::DbResult<bool> {
+                let updated = sqlx::query(queries::UPDATE_USER_ROLE)
+                    .bind(role.as_str()).bind(user_id)
+                    .execute(&self.pool).await?;
+                Ok(updated.rows_affected() > 0)
+            }
+
+            async fn set_email_verified(
+                &self, user_id: i32, email: &str,
+                verified_at: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<bool> {
+                let updated = sqlx::query(queries::UPDATE_USER_EMAIL_VERIFIED)
+                    .bind(verified_at).bind(user_id).bind(email)
+                    .execute(&self.pool).await?;
+                Ok(updated.rows_affected() > 0)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::UserReviewRepository for $repo {
+            async fn add_user_review(
+                &self, review: &$crate::database::NewUserReview
+            ) -> $crate::database::DbResult<()> {
+                let mut tx = self.pool.begin().await?;
+                lock_hotel(&mut *tx, review.hotel_id).await?;
+                sqlx::query(queries::INSERT_USER_REVIEW)
+                    .bind(review.user_id).bind(review.hotel_id)
+                    .bind(&review.title).bind(&review.text)
+                    .bind(review.rating).bind(chrono::Utc::now())
+                    .execute(&mut *tx).await?;
+                refresh_rating_summaries(&mut *tx, &[review.hotel_id]).await?;
+                tx.commit().await?;
+                Ok(())
+            }
+
+            async fn get_all_user_reviews(&self
+            ) -> $crate::database::DbResult<Vec<$crate::database::UserReview>> {
+                Ok(sqlx::query_as(queries::SELECT_ALL_USER_REVIEWS)
+                    .fetch_all(&self.pool).await?)
+            }
+
+            async fn get_user_reviews_by_username(&self, username: &str
+            ) -> $crate::database::DbResult<Vec<$crate::database::UserReview>> {
+                Ok(sqlx::query_as(queries::SELECT_USER_REVIEWS_BY_USERNAME)
+                    .bind(username).fetch_all(&self.pool).await?)
+            }
+
+            async fn get_user_reviews_by_hotel_id(&self, hotel_id: i32
+            ) -> $crate::database::DbResult<Vec<$crate::database::UserReview>> {
+                Ok(sqlx::query_as(queries::SELECT_USER_REVIEWS_BY_HOTEL_ID)
+                    .bind(hotel_id).fetch_all(&self.pool).await?)
+            }
+
+            async fn get_user_review(&self, review_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::UserReview>> {
+                Ok(sqlx::query_as(queries::SELECT_USER_REVIEW).bind(review_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn delete_user_review(&self, review_id: i32
+            ) -> $crate::database::DbResult<()> {
+                // Reviews don't move between hotels, so the hotel to lock can
+                // be looked up before the transaction
+                let hotel_id: Option<(i32,)> =
+                    sqlx::query_as(queries::SELECT_USER_REVIEW_HOTEL_ID)
+                    .bind(review_id).fetch_optional(&self.pool).await?;
+                let Some((hotel_id,)) = hotel_id else { return Ok(()) };
+
+                let mut tx = self.pool.begin().await?;
+                lock_hotel(&mut *tx, hotel_id).await?;
+                sqlx::query(queries::DELETE_USER_REVIEW).bind(review_id)
+                    .execute(&mut *tx).await?;
+                refresh_rating_summaries(&mut *tx, &[hotel_id]).await?;
+                tx.commit().await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::RatingSummaryRepository for $repo {
+            async fn get_rating_summary(&self, hotel_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::RatingSummary>> {
+                Ok(sqlx::query_as(queries::SELECT_RATING_SUMMARY).bind(hotel_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn rebuild_rating_summaries(&self
+            ) -> $crate::database::DbResult<Vec<$crate::database::SummaryDrift>> {
+                let mut tx = self.pool.begin().await?;
+                let stored = sqlx::query_as(queries::SELECT_ALL_RATING_SUMMARIES)
+                    .fetch_all(&mut *tx).await?;
+                sqlx::query(queries::DELETE_RATING_SUMMARIES)
+                    .execute(&mut *tx).await?;
+                sqlx::query(queries::FILL_RATING_SUMMARY)
+                    .execute(&mut *tx).await?;
+                let rebuilt = sqlx::query_as(queries::SELECT_ALL_RATING_SUMMARIES)
+                    .fetch_all(&mut *tx).await?;
+                tx.commit().await?;
+                Ok($crate::database::summary::drift(stored, rebuilt))
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::SessionRepository for $repo {
+            async fn add_refresh_token(
+                &self, token: &$crate::database::NewRefreshToken
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::INSERT_REFRESH_TOKEN)
+                    .bind(token.user_id).bind(&token.family).bind(&token.token_hash)
+                    .bind(token.created_at).bind(token.expires_at)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn get_refresh_token(&self, token_hash: &str
+            ) -> $crate::database::DbResult<Option<$crate::database::RefreshToken>> {
+                Ok(sqlx::query_as(queries::SELECT_REFRESH_TOKEN).bind(token_hash)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn rotate_refresh_token(
+                &self, token_hash: &str, next_hash: &str,
+                now: chrono::DateTime<chrono::Utc>,
+                expires_at: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<$crate::database::Rotation> {
+                use $crate::database::{RefreshToken, Rotation};
+
+                let mut tx = self.pool.begin().await?;
+                let token: Option<RefreshToken> =
+                    sqlx::query_as(queries::SELECT_REFRESH_TOKEN)
+                    .bind(token_hash).fetch_optional(&mut *tx).await?;
+                let Some(token) = token else {
+                    return Ok(Rotation::Unknown);
+                };
+                if token.used_at.is_none() && token.revoked_at.is_none()
+                    && token.expires_at <= now {
+                    return Ok(Rotation::Expired);
+                }
+
+                let marked = sqlx::query(queries::MARK_REFRESH_TOKEN_USED)
+                    .bind(now).bind(token.id)
+                    .execute(&mut *tx).await?;
+                if marked.rows_affected() == 0 {
+                    sqlx::query(queries::REVOKE_TOKEN_FAMILY)
+                        .bind(now).bind(&token.family)
+                        .execute(&mut *tx).await?;
+                    tx.commit().await?;
+                    return Ok(Rotation::Reused {
+                        user_id: token.user_id, family: token.family
+                    });
+                }
+
+                sqlx::query(queries::INSERT_REFRESH_TOKEN)
+                    .bind(token.user_id).bind(&token.family).bind(next_hash)
+                    .bind(now).bind(expires_at)
+                    .execute(&mut *tx).await?;
+                tx.commit().await?;
+                Ok(Rotation::Rotated { user_id: token.user_id })
+            }
+
+            async fn revoke_token_family(
+                &self, family: &str, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<u64> {
+                Ok(sqlx::query(queries::REVOKE_TOKEN_FAMILY)
+                    .bind(now).bind(family)
+                    .execute(&self.pool).await?.rows_affected())
+            }
+
+            async fn revoke_user_tokens(
+                &self, user_id: i32, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<u64> {
+                Ok(sqlx::query(queries::REVOKE_USER_TOKENS)
+                    .bind(now).bind(user_id)
+                    .execute(&self.pool).await?.rows_affected())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::EmailTokenRepository for $repo {
+            async fn add_email_token(
+                &self, token: &$crate::database::NewEmailToken
+            ) -> $crate::database::DbResult<()> {
+                let mut tx = self.pool.begin().await?;
+                sqlx::query(queries::REVOKE_EMAIL_TOKENS)
+                    .bind(token.created_at).bind(token.user_id)
+                    .bind(token.purpose.as_str())
+                    .execute(&mut *tx).await?;
+                sqlx::query(queries::INSERT_EMAIL_TOKEN)
+                    .bind(token.user_id).bind(token.purpose.as_str())
+                    .bind(&token.token_id).bind(token.created_at)
+                    .bind(token.expires_at)
+                    .execute(&mut *tx).await?;
+                tx.commit().await?;
+                Ok(())
+            }
+
+            async fn use_email_token(
+                &self, token_id: &str, purpose: $crate::database::EmailPurpose,
+                now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<Option<i32>> {
+                let mut tx = self.pool.begin().await?;
+                let used = sqlx::query(queries::USE_EMAIL_TOKEN)
+                    .bind(now).bind(token_id).bind(purpose.as_str()).bind(now)
+                    .execute(&mut *tx).await?;
+                if used.rows_affected() == 0 {
+                    return Ok(None);
+                }
+                let user_id: (i32,) = sqlx::query_as(queries::SELECT_EMAIL_TOKEN_USER)
+                    .bind(token_id).fetch_one(&mut *tx).await?;
+                tx.commit().await?;
+                Ok(Some(user_id.0))
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::ApiKeyRepository for $repo {
+            async fn add_api_key(&self, key: &$crate::database::NewApiKey
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::INSERT_API_KEY)
+                    .bind(&key.name).bind(&key.prefix).bind(&key.secret_hash)
+                    .bind(key.scopes.to_string()).bind(key.created_by)
+                    .bind(key.created_at).bind(key.expires_at)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn get_api_key(&self, prefix: &str
+            ) -> $crate::database::DbResult<Option<$crate::database::ApiKey>> {
+                Ok(sqlx::query_as(queries::SELECT_API_KEY).bind(prefix)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn get_all_api_keys(&self
+            ) -> $crate::database::DbResult<Vec<$crate::database::ApiKey>> {
+                Ok(sqlx::query_as(queries::SELECT_ALL_API_KEYS)
+                    .fetch_all(&self.pool).await?)
+            }
+
+            async fn revoke_api_key(
+                &self, key_id: i32, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<bool> {
+                let revoked = sqlx::query(queries::REVOKE_API_KEY)
+                    .bind(now).bind(key_id)
+                    .execute(&self.pool).await?;
+                Ok(revoked.rows_affected() > 0)
+            }
+
+            async fn touch_api_key(
+                &self, key_id: i32, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::TOUCH_API_KEY).bind(now).bind(key_id)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::LoginEventRepository for $repo {
+            async fn add_login_event(&self, event: &$crate::database::NewLoginEvent
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::INSERT_LOGIN_EVENT)
+                    .bind(event.user_id).bind(&event.username).bind(&event.ip)
+                    .bind(&event.user_agent).bind(event.failure.is_none())
+                    .bind(event.failure.map(|failure| failure.as_str()))
+                    .bind(event.created_at)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn get_login_events(&self, user_id: i32, limit: i64
+            ) -> $crate::database::DbResult<Vec<$crate::database::LoginEvent>> {
+                Ok(sqlx::query_as(queries::SELECT_LOGIN_EVENTS)
+                    .bind(user_id).bind(limit)
+                    .fetch_all(&self.pool).await?)
+            }
+
+            async fn get_login_lockout(&self, user_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::LoginLockout>> {
+                Ok(sqlx::query_as(queries::SELECT_LOGIN_LOCKOUT).bind(user_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn add_login_failure(
+                &self, user_id: i32, policy: &$crate::database::LockoutPolicy,
+                now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<$crate::database::LoginLockout> {
+                let mut tx = self.pool.begin().await?;
+                sqlx::query(queries::INCREMENT_LOGIN_FAILURES).bind(user_id)
+                    .execute(&mut *tx).await?;
+                let mut lockout: $crate::database::LoginLockout =
+                    sqlx::query_as(queries::SELECT_LOGIN_LOCKOUT).bind(user_id)
+                        .fetch_one(&mut *tx).await?;
+                if let Some(locked_until) = policy.locked_until(lockout.failed_logins, now) {
+                    sqlx::query(queries::UPDATE_LOGIN_LOCKED_UNTIL)
+                        .bind(locked_until).bind(user_id)
+                        .execute(&mut *tx).await?;
+                    lockout.locked_until = Some(locked_until);
+                }
+                tx.commit().await?;
+                Ok(lockout)
+            }
+
+            async fn clear_login_failures(&self, user_id: i32
+            ) -> $crate::database::DbResult<()> {
+                sqlx::query(queries::DELETE_LOGIN_LOCKOUT).bind(user_id)
+                    .execute(&self.pool).await?;
+                Ok(())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::TotpRepository for $repo {
+            async fn get_totp(&self, user_id: i32
+            ) -> $crate::database::DbResult<Option<$crate::database::Totp>> {
+                Ok(sqlx::query_as(queries::SELECT_TOTP).bind(user_id)
+                    .fetch_optional(&self.pool).await?)
+            }
+
+            async fn set_totp_secret(
+                &self, user_id: i32, secret: &str, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<()> {
+                let mut tx = self.pool.begin().await?;
+                sqlx::query(queries::DELETE_UNCONFIRMED_TOTP).bind(user_id)
+                    .execute(&mut *tx).await?;
+                sqlx::query(queries::INSERT_TOTP).bind(user_id).bind(secret).bind(now)
+                    .execute(&mut *tx).await?;
+                tx.commit().await?;
+                Ok(())
+            }
+
+            async fn confirm_totp(
+                &self, user_id: i32, step: i64, recovery_code_hashes: &[String],
+                now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<bool> {
+                let mut tx = self.pool.begin().await?;
+                let confirmed = sqlx::query(queries::CONFIRM_TOTP)
+                    .bind(now).bind(step).bind(user_id)
+                    .execute(&mut *tx).await?;
+                if confirmed.rows_affected() == 0 {
+                    return Ok(false);
+                }
+                sqlx::query(queries::DELETE_RECOVERY_CODES).bind(user_id)
+                    .execute(&mut *tx).await?;
+                for code_hash in recovery_code_hashes {
+                    sqlx::query(queries::INSERT_RECOVERY_CODE)
+                        .bind(user_id).bind(code_hash)
+                        .execute(&mut *tx).await?;
+                }
+                tx.commit().await?;
+                Ok(true)
+            }
+
+            async fn use_totp_step(&self, user_id: i32, step: i64
+            ) -> $crate::database::DbResult<bool> {
+                let used = sqlx::query(queries::USE_TOTP_STEP)
+                    .bind(step).bind(user_id).bind(step)
+                    .execute(&self.pool).await?;
+                Ok(used.rows_affected() > 0)
+            }
+
+            async fn use_recovery_code(
+                &self, user_id: i32, code_hash: &str, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<bool> {
+                let used = sqlx::query(queries::USE_RECOVERY_CODE)
+                    .bind(now).bind(user_id).bind(code_hash)
+                    .execute(&self.pool).await?;
+                Ok(used.rows_affected() > 0)
+            }
+
+            async fn delete_totp(&self, user_id: i32) -> $crate::database::DbResult<bool> {
+                let mut tx = self.pool.begin().await?;
+                sqlx::query(queries::DELETE_RECOVERY_CODES).bind(user_id)
+                    .execute(&mut *tx).await?;
+                let deleted = sqlx::query(queries::DELETE_TOTP).bind(user_id)
+                    .execute(&mut *tx).await?;
+                tx.commit().await?;
+                Ok(deleted.rows_affected() > 0)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::database::RateLimitRepository for $repo {
+            async fn take_token(
+                &self, key: &str, policy: $crate::database::Policy,
+                now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<$crate::database::Take> {
+                use $crate::database::{full_at, Bucket, TAKE_ATTEMPTS};
+
+                // Compare-and-swap on the bucket's version, retried when
+                // another request got there first
+                for _ in 0..TAKE_ATTEMPTS {
+                    let bucket: Option<Bucket> = sqlx::query_as(queries::SELECT_RATE_LIMIT)
+                        .bind(key).fetch_optional(&self.pool).await?;
+                    let (take, stored) = match bucket {
+                        Some(bucket) => {
+                            let take = policy.take(
+                                policy.refill(bucket.tokens, bucket.updated_at, now)
+                            );
+                            let updated = sqlx::query(queries::UPDATE_RATE_LIMIT)
+                                .bind(take.tokens).bind(now)
+                                .bind(full_at(policy, take.tokens, now))
+                                .bind(key).bind(bucket.version)
+                                .execute(&self.pool).await?;
+                            (take, updated.rows_affected())
+                        },
+                        None => {
+                            let take = policy.take(f64::from(policy.burst));
+                            let inserted = sqlx::query(queries::INSERT_RATE_LIMIT)
+                                .bind(key).bind(take.tokens).bind(now)
+                                .bind(full_at(policy, take.tokens, now))
+                                .execute(&self.pool).await?;
+                            (take, inserted.rows_affected())
+                        },
+                    };
+                    if stored == 1 {
+                        return Ok(take);
+                    }
+                }
+                Ok($crate::database::Take { allowed: false, tokens: 0.0 })
+            }
+
+            async fn prune_buckets(&self, now: chrono::DateTime<chrono::Utc>
+            ) -> $crate::database::DbResult<u64> {
+                Ok(sqlx::query(queries::DELETE_FULL_RATE_LIMITS).bind(now)
+                    .execute(&self.pool).await?.rows_affected())
+            }
+        }
+
+        /**
+         * Locks 'hotel_id' until 'conn''s transaction ends. Taken before its
+         * reviews are written, the refresh of its summary that follows then
+         * sees every review committed before it.
+         */
+        async fn lock_hotel(
+            conn: &mut <$db as sqlx::Database>::Connection, hotel_id: i32
+        ) -> sqlx::Result<()> {
+            sqlx::query(queries::LOCK_HOTEL).bind(hotel_id).execute(&mut *conn).await?;
+            Ok(())
+        }
+
+        /// Recomputes the rating summaries of 'hotel_ids' inside 'conn''s
+        /// transaction, whose writes to them hold 'lock_hotel'.
+        async fn refresh_rating_summaries(
+            conn: &mut <$db as sqlx::Database>::Connection, hotel_ids: &[i32]
+        ) -> sqlx::Result<()> {
+            let (mut delete, mut insert) =
+                $crate::database::summary::refresh_statements::<$db>(hotel_ids);
+            delete.build().execute(&mut *conn).await?;
+            insert.build().execute(&mut *conn).await?;
+            Ok(())
+        }
+    };
+}
+
+pub(crate) use sql_repository;
@@ -0,0 +1,385 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+use crate::database::error::DbResult;
+use crate::database::sql_strs::{SEARCH_HOTELS_FROM, SEARCH_REVIEWS_FROM};
+use crate::hotels_info::{Hotel, Review};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Plain words, ranked by relevance.
+    #[default]
+    Natural,
+    /// '+required -excluded optional' words, like MySQL's boolean mode.
+    Boolean,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchTarget {
+    /// Matches review titles and texts.
+    #[default]
+    Reviews,
+    /// Matches hotel names and cities.
+    Hotels,
+}
+
+/**
+ * A '/search' request, taken from the query string
+ * (e.g. '?q=quiet+pool&in=reviews&city=Boston&min_rating=4&page=2').
+ *
+ * # Fields:
+ * - 'q': The words to search for.
+ * - 'mode': 'natural' (default) or 'boolean'.
+ * - 'target': What to search, 'reviews' (default) or 'hotels', given as 'in'.
+ * - 'hotel_id', 'city': Only results of that hotel or city.
+ * - 'min_rating': Only reviews rated at least this, ignored for hotels.
+ * - 'page', 'per_page': 1-based page of results and its size (at most 100).
+ */
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(default)]
+    pub mode: SearchMode,
+    #[serde(default, rename = "in")]
+    pub target: SearchTarget,
+    pub hotel_id: Option<i32>,
+    pub city: Option<String>,
+    pub min_rating: Option<i32>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+impl SearchQuery {
+    pub fn page(&self) -> u32 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn per_page(&self) -> u32 {
+        self.per_page.unwrap_or(20).clamp(1, 100)
+    }
+
+    pub fn offset(&self) -> u32 {
+        (self.page() - 1) * self.per_page()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchItem {
+    Hotel(Hotel),
+    Review(Review),
+}
+
+impl SearchItem {
+    /// The text a snippet is cut from.
+    fn text(&self) -> String {
+        match self {
+            SearchItem::Hotel(h) => format!("{}, {}", h.name, h.city),
+            SearchItem::Review(r) => format!("{}. {}", r.title, r.text),
+        }
+    }
+
+    /// Breaks ties between equal scores, like the SQL backends do.
+    fn key(&self) -> String {
+        match self {
+            SearchItem::Hotel(h) => format!("{:010}", h.hotel_id),
+            SearchItem::Review(r) => r.review_id.clone(),
+        }
+    }
+}
+
+/**
+ * One search result.
+ *
+ * # Fields:
+ * - 'score': Relevance as computed by the backend, higher is better. Scores
+ *   are only comparable within one backend.
+ * - 'snippet': Part of the matched text, HTML-escaped, with the matched
+ *   words wrapped in '<mark>' tags.
+ * - 'item': The hotel or review, as a 'hotel' or 'review' field.
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub score: f64,
+    pub snippet: String,
+    #[serde(flatten)]
+    pub item: SearchItem,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub total: i64,
+    pub page: u32,
+    pub per_page: u32,
+    pub hits: Vec<SearchHit>,
+}
+
+impl SearchResults {
+    /// Wraps one page of already ranked items, adding their snippets.
+    pub fn new(query: &SearchQuery, total: i64, ranked: Vec<(SearchItem, f64)>
+    ) -> SearchResults {
+        let terms = Terms::parse(&query.q, query.mode);
+        SearchResults {
+            total,
+            page: query.page(),
+            per_page: query.per_page(),
+            hits: ranked.into_iter().map(|(item, score)| SearchHit {
+                score, snippet: terms.snippet(&item.text(), SNIPPET_LEN), item,
+            }).collect(),
+        }
+    }
+
+    /**
+     * Scores 'candidates' against the query words, for backends without a
+     * full-text index. Candidates must already match the query's filters.
+     */
+    pub fn rank(query: &SearchQuery, candidates: Vec<SearchItem>) -> SearchResults {
+        let terms = Terms::parse(&query.q, query.mode);
+        let mut scored: Vec<(SearchItem, f64)> = candidates.into_iter()
+            .filter_map(|item| terms.score(&item.text()).map(|s| (item, s)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.key().cmp(&b.0.key())));
+
+        let total = scored.len() as i64;
+        let page = scored.into_iter()
+            .skip(query.offset() as usize).take(query.per_page() as usize)
+            .collect();
+        SearchResults::new(query, total, page)
+    }
+}
+
+/// Full-text search over hotels and reviews.
+#[async_trait]
+pub trait SearchRepository: Send + Sync {
+    async fn search(&self, query: &SearchQuery) -> DbResult<SearchResults>;
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct ScoredHotel {
+    #[sqlx(flatten)]
+    pub hotel: Hotel,
+    pub score: f64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct ScoredReview {
+    #[sqlx(flatten)]
+    pub review: Review,
+    pub score: f64,
+}
+
+/**
+ * Builds the count and the page statements of a full-text search, for
+ * backends whose match is written as '<prefix> <query words> <suffix>'.
+ *
+ * # Parameters:
+ * - 'score': Prefix and suffix of the relevance expression, a float.
+ * - 'matches': Prefix and suffix of the condition selecting matching rows.
+ *
+ * # Returns:
+ * - The 'select count(*)' statement and the one selecting the page's rows
+ *   with their 'score', best first.
+ */
+pub(crate) fn search_statements<'a, DB: Database>(
+    query: &SearchQuery, score: [&str; 2], matches: [&str; 2]
+) -> (QueryBuilder<'a, DB>, QueryBuilder<'a, DB>)
+where
+    i32: 'a + Encode<'a, DB> + Type<DB> + Send,
+    i64: 'a + Encode<'a, DB> + Type<DB> + Send,
+    String: 'a + Encode<'a, DB> + Type<DB> + Send,
+{
+    let (table, key, from) = match query.target {
+        SearchTarget::Reviews => ("reviews", "review_id", SEARCH_REVIEWS_FROM),
+        SearchTarget::Hotels => ("hotels", "hotel_id", SEARCH_HOTELS_FROM),
+    };
+
+    let mut count = QueryBuilder::new("select count(*)");
+    count.push(from).push(matches[0]).push_bind(query.q.clone()).push(matches[1]);
+    push_search_filters(&mut count, query, table);
+
+    let mut select = QueryBuilder::new(format!("select {table}.*, "));
+    select.push(score[0]).push_bind(query.q.clone()).push(score[1])
+        .push(" as score").push(from)
+        .push(matches[0]).push_bind(query.q.clone()).push(matches[1]);
+    push_search_filters(&mut select, query, table);
+    select.push(format!(" order by score desc, {table}.{key} limit "))
+        .push_bind(query.per_page() as i64)
+        .push(" offset ").push_bind(query.offset() as i64);
+
+    (count, select)
+}
+
+/**
+ * Builds a statement selecting every row the query's filters allow, for
+ * backends that rank candidates with 'SearchResults::rank'.
+ */
+#[cfg(feature = "sqlite")]
+pub(crate) fn search_candidates<'a, DB: Database>(query: &SearchQuery
+) -> QueryBuilder<'a, DB>
+where
+    i32: 'a + Encode<'a, DB> + Type<DB> + Send,
+    String: 'a + Encode<'a, DB> + Type<DB> + Send,
+{
+    let (table, from) = match query.target {
+        SearchTarget::Reviews => ("reviews", SEARCH_REVIEWS_FROM),
+        SearchTarget::Hotels => ("hotels", SEARCH_HOTELS_FROM),
+    };
+    let mut select = QueryBuilder::new(format!("select {table}.*"));
+    select.push(from).push("1 = 1");
+    push_search_filters(&mut select, query, table);
+    select
+}
+
+/// Pushes the query's filters as ' and ...' conditions.
+fn push_search_filters<'a, DB: Database>(
+    qb: &mut QueryBuilder<'a, DB>, query: &SearchQuery, table: &str
+) where
+    i32: 'a + Encode<'a, DB> + Type<DB> + Send,
+    String: 'a + Encode<'a, DB> + Type<DB> + Send,
+{
+    if let Some(hotel_id) = query.hotel_id {
+        qb.push(format!(" and {table}.hotel_id = ")).push_bind(hotel_id);
+    }
+    if let Some(city) = &query.city {
+        qb.push(" and hotels.city = ").push_bind(city.clone());
+    }
+    if let (Some(rating), SearchTarget::Reviews) = (query.min_rating, query.target) {
+        qb.push(" and reviews.rating >= ").push_bind(rating);
+    }
+}
+
+const SNIPPET_LEN: usize = 160;
+
+/// Shorter words are ignored, like MySQL's default 'ft_min_word_len'.
+const MIN_WORD_LEN: usize = 3;
+
+/// A query word, lowercased. 'word*' matches every word starting with 'word'.
+#[derive(Debug)]
+struct Term {
+    word: String,
+    prefix: bool,
+}
+
+impl Term {
+    fn matches(&self, word: &str) -> bool {
+        match self.prefix {
+            true => word.starts_with(&self.word),
+            false => word == self.word,
+        }
+    }
+}
+
+/// Query words split up by what boolean mode makes of them.
+#[derive(Debug, Default)]
+struct Terms {
+    required: Vec<Term>,
+    excluded: Vec<Term>,
+    optional: Vec<Term>,
+}
+
+/// The words of 'text', lowercased, with their byte ranges in 'text'.
+fn words(text: &str) -> impl Iterator<Item = (usize, usize, String)> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(move |w| {
+            let start = w.as_ptr() as usize - text.as_ptr() as usize;
+            (start, start + w.len(), w.to_lowercase())
+        })
+        .filter(|(_, _, w)| !w.is_empty())
+}
+
+impl Terms {
+    fn parse(q: &str, mode: SearchMode) -> Terms {
+        let mut terms = Terms::default();
+        for word in q.split_whitespace() {
+            let (op, word) = match mode {
+                SearchMode::Boolean => word.split_at(
+                    if word.starts_with(['+', '-']) { 1 } else { 0 }
+                ),
+                SearchMode::Natural => ("", word),
+            };
+            let term = Term {
+                word: word.trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase(),
+                prefix: mode == SearchMode::Boolean && word.ends_with('*'),
+            };
+            if term.word.chars().count() < MIN_WORD_LEN {
+                continue;
+            }
+            match op {
+                "+" => terms.required.push(term),
+                "-" => terms.excluded.push(term),
+                _ => terms.optional.push(term),
+            }
+        }
+        terms
+    }
+
+    fn positive(&self) -> impl Iterator<Item = &Term> {
+        self.required.iter().chain(&self.optional)
+    }
+
+    /// Query words found in 'text', 'None' if it doesn't match.
+    fn score(&self, text: &str) -> Option<f64> {
+        let words: Vec<String> = words(text).map(|(_, _, w)| w).collect();
+        let found = |t: &Term| words.iter().any(|w| t.matches(w));
+        if self.excluded.iter().any(found) || !self.required.iter().all(found) {
+            return None;
+        }
+        let hits = words.iter()
+            .filter(|w| self.positive().any(|t| t.matches(w))).count();
+        (hits > 0).then_some(hits as f64)
+    }
+
+    /**
+     * Cuts about 'len' bytes of 'text' around its first matched word and
+     * marks every matched word in it.
+     */
+    fn snippet(&self, text: &str, len: usize) -> String {
+        let matched: Vec<(usize, usize)> = words(text)
+            .filter(|(_, _, w)| self.positive().any(|t| t.matches(w)))
+            .map(|(start, end, _)| (start, end)).collect();
+        let first = matched.first().map_or(0, |m| m.0);
+
+        let mut start = first.saturating_sub(len / 3);
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (start + len).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        // Cut at spaces so no word is shown half
+        if start > 0 {
+            start = text[start..first].find(' ').map_or(first, |i| start + i + 1);
+        }
+        if end < text.len() {
+            end = text[start..end].rfind(' ').map_or(end, |i| start + i);
+        }
+
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        let mut pos = start;
+        for &(m_start, m_end) in matched.iter()
+            .filter(|&&(s, e)| s >= start && e <= end) {
+            snippet.push_str(&escape(&text[pos..m_start]));
+            snippet.push_str("<mark>");
+            snippet.push_str(&escape(&text[m_start..m_end]));
+            snippet.push_str("</mark>");
+            pos = m_end;
+        }
+        snippet.push_str(&escape(&text[pos..end]));
+        if end < text.len() {
+            snippet.push('…');
+        }
+        snippet
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DbResult;
+
+/**
+ * A row of 'refresh_tokens'. Only a hash of the token is stored.
+ *
+ * Tokens are rotated: using one marks it used and issues the next one in the
+ * same family, so a family is one login session.
+ *
+ * # Fields:
+ * - 'family': Shared by every token issued from the same login.
+ * - 'used_at': When the token was exchanged for the next one.
+ * - 'revoked_at': When the session was logged out or found reused.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub family: String,
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl RefreshToken {
+    /// Whether the token can still be exchanged.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.used_at.is_none() && self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewRefreshToken {
+    pub user_id: i32,
+    pub family: String,
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rotation {
+    /// The next token of the family was stored.
+    Rotated { user_id: i32 },
+    /// The token was used or revoked already, so it was stolen or replayed:
+    /// its whole family is revoked.
+    Reused { user_id: i32, family: String },
+    Expired,
+    Unknown,
+}
+
+/**
+ * Refresh tokens, the server side of login sessions.
+ *
+ * SQL backends rotate tokens in one transaction, so of two requests racing
+ * with the same token only one gets the next token, the other one counts as
+ * reuse.
+ */
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn add_refresh_token(&self, token: &NewRefreshToken) -> DbResult<()>;
+    async fn get_refresh_token(&self, token_hash: &str
+    ) -> DbResult<Option<RefreshToken>>;
+
+    /**
+     * Exchanges the token with 'token_hash' for one with 'next_hash', in the
+     * same family and for the same user.
+     *
+     * # Parameters:
+     * - 'now': When the exchange happens, the used token is stamped with it.
+     * - 'expires_at': When the next token expires.
+     */
+    async fn rotate_refresh_token(
+        &self, token_hash: &str, next_hash: &str,
+        now: DateTime<Utc>, expires_at: DateTime<Utc>
+    ) -> DbResult<Rotation>;
+
+    /// Revokes one session, returns how many tokens were revoked.
+    async fn revoke_token_family(&self, family: &str, now: DateTime<Utc>
+    ) -> DbResult<u64>;
+
+    /// Revokes every session of 'user_id', returns how many tokens were revoked.
+    async fn revoke_user_tokens(&self, user_id: i32, now: DateTime<Utc>
+    ) -> DbResult<u64>;
+}
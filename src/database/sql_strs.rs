@@ -27,17 +27,32 @@ pub const INSERT_USERS: &str = "insert into users(username, email) values \
     ('chet', 'cBaker@fallingTooEasily.us'), ('dave', 'dBrubeck@take5.po'), \
     ('bill', 'bEvans@foolishHeart.es'), ('hector', 'hLavoe@elCantante.com');";
 
-pub const INSERT_HOTEL: &str = "insert into hotels(hotel_id, name, address, \
-    city, province, country) values (?, ?, ?, ?, ?, ?);";
+pub const INSERT_HOTELS: &str = "insert into hotels(hotel_id, name, address, \
+    city, province, country) ";
 
-pub const INSERT_REVIEW: &str = "insert into reviews(review_id, hotel_id, \
-    rating, author, title, text, time) values (?, ?, ?, ?, ?, ?, ?);";
+pub const INSERT_REVIEWS: &str = "insert into reviews(review_id, hotel_id, \
+    rating, author, title, text, time) ";
+
+pub const UPSERT_HOTELS: &str = " on duplicate key update \
+    name = values(name), address = values(address), city = values(city), \
+    province = values(province), country = values(country)";
+
+pub const UPSERT_REVIEWS: &str = " on duplicate key update \
+    hotel_id = values(hotel_id), rating = values(rating), \
+    author = values(author), title = values(title), text = values(text), \
+    time = values(time)";
+
+pub const COUNT_EXISTING_HOTELS: &str = "select count(*) from hotels \
+    where hotel_id in (";
+
+pub const COUNT_EXISTING_REVIEWS: &str = "select count(*) from reviews \
+    where review_id in (";
 
 pub const INSERT_USER_REVIEW: &str = "insert into user_reviews(user_id, \
-    hotel_id, title, text) values (?, ?, ?, ?);";
+    hotel_id, title, text, rating, created_at) values (?, ?, ?, ?, ?, ?);";
 
-pub const INSERT_USER: &str = "insert into users(username, email) values \
-    (?, ?);";
+pub const INSERT_USER: &str = "insert into users(username, email, \
+    password_hash) values (?, ?, ?);";
 
 pub const SELECT_USER: &str = "select * from users where id = ?";
 
@@ -47,7 +62,7 @@ pub const SELECT_USER_BY_CREDS: &str = "select * from users where \
 pub const SELECT_HOTEL: &str = "select * from hotels where hotel_id = ?";
 
 pub const SELECT_HOTEL_REVIEWS: &str = "select * from reviews where \
-    hotel_id = ?";
+    hotel_id = ";
 
 pub const SELECT_LIKE_HOTELS: &str = "select * from hotels where name like ?";
 
@@ -64,16 +79,386 @@ pub const SELECT_USER_REVIEWS_BY_USERNAME: &str = "select * from user_reviews \
 pub const SELECT_USER_REVIEWS_BY_HOTEL_ID: &str = "select * from user_reviews \
     where hotel_id = ?";
 
+pub const SELECT_USER_REVIEW: &str = "select * from user_reviews \
+    where review_id = ?";
+
 pub const DELETE_USER: &str = "delete from users where id = ?";
 
+pub const SELECT_USER_REVIEW_HOTEL_ID: &str = "select hotel_id \
+    from user_reviews where review_id = ?";
+
 pub const DELETE_USER_REVIEW: &str = "delete from user_reviews where \
     review_id = ?";
 
-pub const UPDATE_USER: &str = "update users set username = ?,\
-    email = ? where id = ?";
+// A new email has to be verified again. MySQL assigns left to right and later
+// assignments see the new values of earlier ones, so 'email_verified_at' has
+// to stay first to compare the old email. SQLite, which shares this
+// statement, reads the old row in every assignment.
+pub const UPDATE_USER: &str = "update users set email_verified_at = \
+    case when email = ? then email_verified_at else null end, \
+    username = ?, email = ? where id = ?";
+
+pub const UPDATE_USER_PASSWORD_HASH: &str = "update users set \
+    password_hash = ? where id = ?";
+
+pub const UPDATE_USER_ROLE: &str = "update users set role = ? where id = ?";
+
+pub const CREATE_SCHEMA_MIGRATIONS_TABLE: &str = "\
+    create table if not exists schema_migrations(version BIGINT NOT NULL, \
+    name VARCHAR(200) NOT NULL, \
+    applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, \
+    PRIMARY KEY(version))";
+
+pub const SELECT_SCHEMA_MIGRATIONS: &str = "select version, name, applied_at \
+    from schema_migrations order by version";
+
+pub const INSERT_SCHEMA_MIGRATION: &str = "insert into \
+    schema_migrations(version, name) values (?, ?)";
+
+pub const DELETE_SCHEMA_MIGRATION: &str = "delete from schema_migrations \
+    where version = ?";
+
+pub const STRIP_REVIEWS_TIME_SUFFIX: &str = "update reviews \
+    set time = replace(time, ' UTC', '')";
+
+pub const ALTER_REVIEWS_TIME_DATETIME: &str = "alter table reviews \
+    modify time DATETIME NOT NULL";
+
+pub const CREATE_REVIEWS_TIME_INDEX: &str = "create index reviews_hotel_time \
+    on reviews(hotel_id, time)";
+
+pub const DROP_REVIEWS_TIME_INDEX: &str = "drop index reviews_hotel_time \
+    on reviews";
+
+pub const ALTER_REVIEWS_TIME_VARCHAR: &str = "alter table reviews \
+    modify time VARCHAR(100) NOT NULL";
+
+pub const ADD_REVIEWS_TIME_SUFFIX: &str = "update reviews \
+    set time = concat(time, ' UTC')";
 
 pub const DROP_USERS_TABLE: &str = "drop table users;";
 
 pub const DROP_HOTELS_TABLE: &str = "drop table hotels;";
 
-pub const DROP_REVIEWS_TABLE: &str = "drop table reviews;";
\ No newline at end of file
+pub const DROP_REVIEWS_TABLE: &str = "drop table reviews;";
+
+pub const DROP_USER_REVIEWS_TABLE: &str = "drop table user_reviews;";
+
+pub const SEARCH_REVIEWS_FROM: &str = " from reviews inner join hotels \
+    on reviews.hotel_id = hotels.hotel_id where ";
+
+pub const SEARCH_HOTELS_FROM: &str = " from hotels where ";
+
+pub const MATCH_REVIEWS: &str = "match(reviews.title, reviews.text) against (";
+
+pub const MATCH_HOTELS: &str = "match(hotels.name, hotels.city) against (";
+
+pub const NATURAL_LANGUAGE_MODE: &str = " in natural language mode)";
+
+pub const BOOLEAN_MODE: &str = " in boolean mode)";
+
+pub const CREATE_REVIEWS_FULLTEXT_INDEX: &str = "create fulltext index \
+    reviews_fulltext on reviews(title, text)";
+
+pub const CREATE_HOTELS_FULLTEXT_INDEX: &str = "create fulltext index \
+    hotels_fulltext on hotels(name, city)";
+
+pub const DROP_REVIEWS_FULLTEXT_INDEX: &str = "drop index reviews_fulltext \
+    on reviews";
+
+pub const DROP_HOTELS_FULLTEXT_INDEX: &str = "drop index hotels_fulltext \
+    on hotels";
+
+pub const ADD_USER_REVIEWS_RATING: &str = "alter table user_reviews \
+    add rating INT";
+
+pub const ADD_USER_REVIEWS_CREATED_AT: &str = "alter table user_reviews \
+    add created_at DATETIME";
+
+pub const DROP_USER_REVIEWS_RATING: &str = "alter table user_reviews \
+    drop column rating";
+
+pub const DROP_USER_REVIEWS_CREATED_AT: &str = "alter table user_reviews \
+    drop column created_at";
+
+// NULL until a password is set, such accounts can't log in.
+pub const ADD_USERS_PASSWORD_HASH: &str = "alter table users \
+    add password_hash VARCHAR(255)";
+
+pub const DROP_USERS_PASSWORD_HASH: &str = "alter table users \
+    drop column password_hash";
+
+pub const ADD_USERS_ROLE: &str = "alter table users \
+    add role VARCHAR(16) NOT NULL DEFAULT 'user'";
+
+pub const DROP_USERS_ROLE: &str = "alter table users drop column role";
+
+pub const CREATE_REFRESH_TOKENS_TABLE: &str = "\
+    create table if not exists refresh_tokens(id INT AUTO_INCREMENT, \
+    user_id INT NOT NULL, family CHAR(32) NOT NULL, \
+    token_hash CHAR(64) NOT NULL UNIQUE, created_at DATETIME NOT NULL, \
+    expires_at DATETIME NOT NULL, used_at DATETIME, revoked_at DATETIME, \
+    PRIMARY KEY(id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_REFRESH_TOKENS_FAMILY_INDEX: &str = "create index \
+    refresh_tokens_family on refresh_tokens(family)";
+
+pub const DROP_REFRESH_TOKENS_TABLE: &str = "drop table refresh_tokens";
+
+pub const INSERT_REFRESH_TOKEN: &str = "insert into refresh_tokens(user_id, \
+    family, token_hash, created_at, expires_at) values (?, ?, ?, ?, ?)";
+
+pub const SELECT_REFRESH_TOKEN: &str = "select * from refresh_tokens \
+    where token_hash = ?";
+
+// Only matches an active token, so of two racing rotations one updates nothing.
+pub const MARK_REFRESH_TOKEN_USED: &str = "update refresh_tokens \
+    set used_at = ? where id = ? and used_at is null and revoked_at is null";
+
+pub const REVOKE_TOKEN_FAMILY: &str = "update refresh_tokens \
+    set revoked_at = ? where family = ? and revoked_at is null";
+
+pub const REVOKE_USER_TOKENS: &str = "update refresh_tokens \
+    set revoked_at = ? where user_id = ? and revoked_at is null";
+
+pub const CREATE_RATING_SUMMARY_TABLE: &str = "\
+    create table if not exists hotel_rating_summary(hotel_id INT NOT NULL, \
+    review_count INT NOT NULL, rating_sum INT NOT NULL, \
+    rating_1 INT NOT NULL, rating_2 INT NOT NULL, rating_3 INT NOT NULL, \
+    rating_4 INT NOT NULL, rating_5 INT NOT NULL, last_review_time DATETIME, \
+    PRIMARY KEY(hotel_id), FOREIGN KEY(hotel_id) REFERENCES hotels(hotel_id))";
+
+pub const DROP_RATING_SUMMARY_TABLE: &str = "drop table hotel_rating_summary";
+
+/// Summarizes the 'rated' reviews into 'hotel_rating_summary', the statements
+/// built from it add the 'where' on them and the 'group by hotel_id'.
+macro_rules! summarize_ratings {
+    () => {
+        "insert into hotel_rating_summary(\
+        hotel_id, review_count, rating_sum, rating_1, rating_2, rating_3, \
+        rating_4, rating_5, last_review_time) \
+        select hotel_id, count(*), sum(rating), \
+        sum(case when rating = 1 then 1 else 0 end), \
+        sum(case when rating = 2 then 1 else 0 end), \
+        sum(case when rating = 3 then 1 else 0 end), \
+        sum(case when rating = 4 then 1 else 0 end), \
+        sum(case when rating = 5 then 1 else 0 end), max(time) \
+        from (select hotel_id, rating, time from reviews union all \
+        select hotel_id, rating, created_at from user_reviews \
+        where rating is not null) rated "
+    };
+}
+
+pub const SUMMARIZE_RATINGS: &str = summarize_ratings!();
+
+pub const FILL_RATING_SUMMARY: &str = concat!(summarize_ratings!(), "group by hotel_id");
+
+pub const DELETE_RATING_SUMMARIES: &str = "delete from hotel_rating_summary";
+
+/// Taken before a hotel's summary is recomputed, so concurrent writes of its
+/// reviews recompute it one after the other instead of racing to insert it.
+pub const LOCK_HOTEL: &str = "select hotel_id from hotels where hotel_id = ? \
+    for update";
+
+pub const SELECT_RATING_SUMMARY: &str = "select * from hotel_rating_summary \
+    where hotel_id = ?";
+
+pub const SELECT_ALL_RATING_SUMMARIES: &str = "select * from \
+    hotel_rating_summary order by hotel_id";
+
+pub const DUMP_USERS: &str = "select * from users order by id";
+
+pub const DUMP_HOTELS: &str = "select * from hotels order by hotel_id";
+
+pub const DUMP_REVIEWS: &str = "select * from reviews order by review_id";
+
+pub const DUMP_USER_REVIEWS: &str = "select * from user_reviews \
+    order by review_id";
+
+pub const COUNT_ROWS: &str = "select count(*) from ";
+
+pub const RESTORE_USERS: &str = "insert into users(id, username, email, \
+    password_hash, role, email_verified_at) ";
+
+pub const RESTORE_USER_REVIEWS: &str = "insert into user_reviews(review_id, \
+    user_id, hotel_id, title, text, rating, created_at) ";
+
+// MySQL's default REPEATABLE READ already gives a transaction one snapshot.
+pub const BEGIN_SNAPSHOT: &[&str] = &[];
+
+// AUTO_INCREMENT moves past explicitly inserted ids by itself.
+pub const RESET_SEQUENCES: &[&str] = &[];
+
+// Microseconds, the refill of a bucket is computed from 'updated_at'.
+pub const CREATE_RATE_LIMITS_TABLE: &str = "\
+    create table if not exists rate_limits(bucket_key VARCHAR(255) NOT NULL, \
+    tokens DOUBLE NOT NULL, updated_at DATETIME(6) NOT NULL, \
+    full_at DATETIME NOT NULL, version INT NOT NULL, PRIMARY KEY(bucket_key))";
+
+pub const CREATE_RATE_LIMITS_FULL_AT_INDEX: &str = "create index \
+    rate_limits_full_at on rate_limits(full_at)";
+
+pub const DROP_RATE_LIMITS_TABLE: &str = "drop table rate_limits";
+
+pub const SELECT_RATE_LIMIT: &str = "select * from rate_limits \
+    where bucket_key = ?";
+
+// Inserts nothing if another request created the bucket first.
+pub const INSERT_RATE_LIMIT: &str = "insert ignore into rate_limits(\
+    bucket_key, tokens, updated_at, full_at, version) values (?, ?, ?, ?, 0)";
+
+// Only matches the version that was read, so of two racing updates one
+// updates nothing.
+pub const UPDATE_RATE_LIMIT: &str = "update rate_limits set tokens = ?, \
+    updated_at = ?, full_at = ?, version = version + 1 \
+    where bucket_key = ? and version = ?";
+
+pub const DELETE_FULL_RATE_LIMITS: &str = "delete from rate_limits \
+    where full_at <= ?";
+
+pub const ADD_USERS_EMAIL_VERIFIED_AT: &str = "alter table users \
+    add email_verified_at DATETIME";
+
+pub const DROP_USERS_EMAIL_VERIFIED_AT: &str = "alter table users \
+    drop column email_verified_at";
+
+pub const CREATE_EMAIL_TOKENS_TABLE: &str = "\
+    create table if not exists email_tokens(id INT AUTO_INCREMENT, \
+    user_id INT NOT NULL, purpose VARCHAR(32) NOT NULL, \
+    token_id CHAR(32) NOT NULL UNIQUE, created_at DATETIME NOT NULL, \
+    expires_at DATETIME NOT NULL, used_at DATETIME, PRIMARY KEY(id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_EMAIL_TOKENS_USER_INDEX: &str = "create index \
+    email_tokens_user on email_tokens(user_id, purpose)";
+
+pub const DROP_EMAIL_TOKENS_TABLE: &str = "drop table email_tokens";
+
+pub const INSERT_EMAIL_TOKEN: &str = "insert into email_tokens(user_id, \
+    purpose, token_id, created_at, expires_at) values (?, ?, ?, ?, ?)";
+
+// Only the latest email of a kind works, earlier links stop working.
+pub const REVOKE_EMAIL_TOKENS: &str = "update email_tokens set used_at = ? \
+    where user_id = ? and purpose = ? and used_at is null";
+
+// Only matches a usable token, so of two racing requests one updates nothing.
+pub const USE_EMAIL_TOKEN: &str = "update email_tokens set used_at = ? \
+    where token_id = ? and purpose = ? and used_at is null and expires_at > ?";
+
+pub const SELECT_EMAIL_TOKEN_USER: &str = "select user_id from email_tokens \
+    where token_id = ?";
+
+// Matches the email the link was sent to, a changed one isn't verified.
+pub const UPDATE_USER_EMAIL_VERIFIED: &str = "update users set \
+    email_verified_at = ? where id = ? and email = ?";
+
+pub const CREATE_API_KEYS_TABLE: &str = "\
+    create table if not exists api_keys(id INT AUTO_INCREMENT, \
+    name VARCHAR(100) NOT NULL, prefix CHAR(12) NOT NULL UNIQUE, \
+    secret_hash CHAR(64) NOT NULL, scopes VARCHAR(255) NOT NULL, \
+    created_by INT, created_at DATETIME NOT NULL, expires_at DATETIME, \
+    last_used_at DATETIME, revoked_at DATETIME, PRIMARY KEY(id), \
+    FOREIGN KEY(created_by) REFERENCES users(id) ON DELETE SET NULL)";
+
+pub const DROP_API_KEYS_TABLE: &str = "drop table api_keys";
+
+pub const INSERT_API_KEY: &str = "insert into api_keys(name, prefix, \
+    secret_hash, scopes, created_by, created_at, expires_at) \
+    values (?, ?, ?, ?, ?, ?, ?)";
+
+pub const SELECT_API_KEY: &str = "select * from api_keys where prefix = ?";
+
+pub const SELECT_ALL_API_KEYS: &str = "select * from api_keys order by id";
+
+pub const REVOKE_API_KEY: &str = "update api_keys set revoked_at = ? \
+    where id = ? and revoked_at is null";
+
+pub const TOUCH_API_KEY: &str = "update api_keys set last_used_at = ? \
+    where id = ?";
+
+pub const CREATE_LOGIN_EVENTS_TABLE: &str = "\
+    create table if not exists login_events(id INT AUTO_INCREMENT, \
+    user_id INT, username VARCHAR(255) NOT NULL, ip VARCHAR(45) NOT NULL, \
+    user_agent VARCHAR(255), success BOOLEAN NOT NULL, reason VARCHAR(32), \
+    created_at DATETIME NOT NULL, PRIMARY KEY(id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE SET NULL)";
+
+pub const CREATE_LOGIN_EVENTS_USER_INDEX: &str = "create index \
+    login_events_user on login_events(user_id, id)";
+
+pub const CREATE_LOGIN_LOCKOUTS_TABLE: &str = "\
+    create table if not exists login_lockouts(user_id INT NOT NULL, \
+    failed_logins INT NOT NULL, locked_until DATETIME, PRIMARY KEY(user_id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const DROP_LOGIN_EVENTS_TABLE: &str = "drop table login_events";
+
+pub const DROP_LOGIN_LOCKOUTS_TABLE: &str = "drop table login_lockouts";
+
+pub const INSERT_LOGIN_EVENT: &str = "insert into login_events(user_id, \
+    username, ip, user_agent, success, reason, created_at) \
+    values (?, ?, ?, ?, ?, ?, ?)";
+
+pub const SELECT_LOGIN_EVENTS: &str = "select * from login_events \
+    where user_id = ? order by id desc limit ?";
+
+pub const SELECT_LOGIN_LOCKOUT: &str = "select * from login_lockouts \
+    where user_id = ?";
+
+// Counts in the statement, so concurrent failures don't overwrite each other.
+pub const INCREMENT_LOGIN_FAILURES: &str = "insert into login_lockouts(\
+    user_id, failed_logins) values (?, 1) \
+    on duplicate key update failed_logins = failed_logins + 1";
+
+pub const UPDATE_LOGIN_LOCKED_UNTIL: &str = "update login_lockouts \
+    set locked_until = ? where user_id = ?";
+
+pub const DELETE_LOGIN_LOCKOUT: &str = "delete from login_lockouts \
+    where user_id = ?";
+
+pub const CREATE_TOTP_TABLE: &str = "\
+    create table if not exists totp(user_id INT NOT NULL, \
+    secret VARCHAR(64) NOT NULL, confirmed_at DATETIME, last_used_step BIGINT, \
+    created_at DATETIME NOT NULL, PRIMARY KEY(user_id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_RECOVERY_CODES_TABLE: &str = "\
+    create table if not exists recovery_codes(id INT AUTO_INCREMENT, \
+    user_id INT NOT NULL, code_hash CHAR(64) NOT NULL, used_at DATETIME, \
+    PRIMARY KEY(id), \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_RECOVERY_CODES_USER_INDEX: &str = "create index \
+    recovery_codes_user on recovery_codes(user_id)";
+
+pub const DROP_TOTP_TABLE: &str = "drop table totp";
+
+pub const DROP_RECOVERY_CODES_TABLE: &str = "drop table recovery_codes";
+
+pub const SELECT_TOTP: &str = "select * from totp where user_id = ?";
+
+pub const DELETE_UNCONFIRMED_TOTP: &str = "delete from totp \
+    where user_id = ? and confirmed_at is null";
+
+pub const INSERT_TOTP: &str = "insert into totp(user_id, secret, created_at) \
+    values (?, ?, ?)";
+
+pub const CONFIRM_TOTP: &str = "update totp set confirmed_at = ?, \
+    last_used_step = ? where user_id = ? and confirmed_at is null";
+
+// Only matches a step after the last one used, so a code works once.
+pub const USE_TOTP_STEP: &str = "update totp set last_used_step = ? \
+    where user_id = ? and confirmed_at is not null \
+    and (last_used_step is null or last_used_step < ?)";
+
+pub const DELETE_TOTP: &str = "delete from totp where user_id = ?";
+
+pub const INSERT_RECOVERY_CODE: &str = "insert into recovery_codes(\
+    user_id, code_hash) values (?, ?)";
+
+pub const USE_RECOVERY_CODE: &str = "update recovery_codes set used_at = ? \
+    where user_id = ? and code_hash = ? and used_at is null";
+
+pub const DELETE_RECOVERY_CODES: &str = "delete from recovery_codes \
+    where user_id = ?";
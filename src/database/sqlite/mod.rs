@@ -0,0 +1,186 @@
+use async_trait::async_trait;
+use sqlx::{Result, SqlitePool};
+
+use crate::database::backup::backup_target;
+use crate::database::error::DbResult;
+use crate::database::loader::bulk_loader;
+use crate::database::migrations::*;
+use crate::database::repository::sql_repository;
+use crate::database::search::*;
+
+pub mod sql_strs;
+
+use sql_strs::*;
+
+sql_repository!(SqliteRepository, sqlx::Sqlite, sqlx::SqlitePool,
+    crate::database::sqlite::sql_strs);
+
+// SQLite takes one writer at a time, so batches run one after the other.
+bulk_loader!(
+    sqlx::SqlitePool, sqlx::Sqlite, sqlx::SqliteConnection,
+    crate::database::sqlite::sql_strs,
+    max_placeholders: 32766,
+    max_concurrency: 1,
+    found_rows: false
+);
+
+backup_target!(
+    sqlx::SqlitePool, sqlx::Sqlite, crate::database::sqlite::sql_strs,
+    max_placeholders: 32766
+);
+
+/**
+ * SQLite migrations. Versions line up with the MySQL ones, but tables are
+ * created in their final shape since SQLite can't alter column types.
+ */
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        up: &[CREATE_USERS_TABLE],
+        down: &[DROP_USERS_TABLE],
+    },
+    Migration {
+        version: 2,
+        name: "create_hotels",
+        up: &[CREATE_HOTELS_TABLE],
+        down: &[DROP_HOTELS_TABLE],
+    },
+    Migration {
+        version: 3,
+        name: "create_reviews",
+        up: &[CREATE_REVIEWS_TABLE],
+        down: &[DROP_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 4,
+        name: "create_user_reviews",
+        up: &[CREATE_USER_REVIEWS_TABLE],
+        down: &[DROP_USER_REVIEWS_TABLE],
+    },
+    Migration {
+        version: 5,
+        name: "reviews_time_datetime",
+        up: &[CREATE_REVIEWS_TIME_INDEX],
+        down: &[DROP_REVIEWS_TIME_INDEX],
+    },
+    // No FULLTEXT indexes in SQLite, searches rank the filtered rows instead.
+    Migration {
+        version: 6,
+        name: "fulltext_search",
+        up: &[],
+        down: &[],
+    },
+    Migration {
+        version: 7,
+        name: "hotel_rating_summary",
+        up: &[
+            ADD_USER_REVIEWS_RATING, ADD_USER_REVIEWS_CREATED_AT,
+            CREATE_RATING_SUMMARY_TABLE, FILL_RATING_SUMMARY,
+        ],
+        down: &[
+            DROP_RATING_SUMMARY_TABLE, DROP_USER_REVIEWS_CREATED_AT,
+            DROP_USER_REVIEWS_RATING,
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "user_passwords",
+        up: &[ADD_USERS_PASSWORD_HASH],
+        down: &[DROP_USERS_PASSWORD_HASH],
+    },
+    Migration {
+        version: 9,
+        name: "refresh_tokens",
+        up: &[CREATE_REFRESH_TOKENS_TABLE, CREATE_REFRESH_TOKENS_FAMILY_INDEX],
+        down: &[DROP_REFRESH_TOKENS_TABLE],
+    },
+    Migration {
+        version: 10,
+        name: "user_roles",
+        up: &[ADD_USERS_ROLE],
+        down: &[DROP_USERS_ROLE],
+    },
+    Migration {
+        version: 11,
+        name: "rate_limits",
+        up: &[CREATE_RATE_LIMITS_TABLE, CREATE_RATE_LIMITS_FULL_AT_INDEX],
+        down: &[DROP_RATE_LIMITS_TABLE],
+    },
+    Migration {
+        version: 12,
+        name: "email_verification",
+        up: &[
+            ADD_USERS_EMAIL_VERIFIED_AT, CREATE_EMAIL_TOKENS_TABLE,
+            CREATE_EMAIL_TOKENS_USER_INDEX,
+        ],
+        down: &[DROP_EMAIL_TOKENS_TABLE, DROP_USERS_EMAIL_VERIFIED_AT],
+    },
+    Migration {
+        version: 13,
+        name: "api_keys",
+        up: &[CREATE_API_KEYS_TABLE],
+        down: &[DROP_API_KEYS_TABLE],
+    },
+    Migration {
+        version: 14,
+        name: "login_events",
+        up: &[
+            CREATE_LOGIN_EVENTS_TABLE, CREATE_LOGIN_EVENTS_USER_INDEX,
+            CREATE_LOGIN_LOCKOUTS_TABLE,
+        ],
+        down: &[DROP_LOGIN_LOCKOUTS_TABLE, DROP_LOGIN_EVENTS_TABLE],
+    },
+    Migration {
+        version: 15,
+        name: "totp",
+        up: &[
+            CREATE_TOTP_TABLE, CREATE_RECOVERY_CODES_TABLE,
+            CREATE_RECOVERY_CODES_USER_INDEX,
+        ],
+        down: &[DROP_RECOVERY_CODES_TABLE, DROP_TOTP_TABLE],
+    },
+];
+
+#[async_trait]
+impl MigrationTarget for SqlitePool {
+    fn migrations(&self) -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    async fn execute(&self, stmt: &str) -> Result<()> {
+        sqlx::query(stmt).execute(self).await.map(|_| ())
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        sqlx::query(CREATE_SCHEMA_MIGRATIONS_TABLE).execute(self).await?;
+        sqlx::query_as(SELECT_SCHEMA_MIGRATIONS).fetch_all(self).await
+    }
+
+    async fn record_migration(&self, migration: &Migration) -> Result<()> {
+        sqlx::query(INSERT_SCHEMA_MIGRATION)
+            .bind(migration.version).bind(migration.name)
+            .execute(self).await.map(|_| ())
+    }
+
+    async fn forget_migration(&self, version: i64) -> Result<()> {
+        sqlx::query(DELETE_SCHEMA_MIGRATION).bind(version)
+            .execute(self).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl SearchRepository for SqliteRepository {
+    async fn search(&self, query: &SearchQuery) -> DbResult<SearchResults> {
+        let mut select = search_candidates::<sqlx::Sqlite>(query);
+        let candidates = match query.target {
+            SearchTarget::Reviews => select.build_query_as()
+                .fetch_all(self.pool()).await?
+                .into_iter().map(SearchItem::Review).collect(),
+            SearchTarget::Hotels => select.build_query_as()
+                .fetch_all(self.pool()).await?
+                .into_iter().map(SearchItem::Hotel).collect(),
+        };
+        Ok(SearchResults::rank(query, candidates))
+    }
+}
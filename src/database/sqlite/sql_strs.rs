@@ -0,0 +1,111 @@
+// Most statements are plain SQL that SQLite accepts as-is, only the DDL and
+// the upserts need their own dialect.
+pub use crate::database::sql_strs::*;
+
+pub const CREATE_USERS_TABLE: &str = "\
+    create table if not exists users(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    username VARCHAR(15) NOT NULL UNIQUE, email VARCHAR(255) NOT NULL UNIQUE);";
+
+pub const CREATE_REVIEWS_TABLE: &str = "\
+    create table if not exists reviews(review_id VARCHAR(100) NOT NULL, \
+    hotel_id INT NOT NULL, rating INT NOT NULL, \
+    author VARCHAR(100), title VARCHAR(200), text VARCHAR(5000), \
+    time DATETIME NOT NULL, PRIMARY KEY(review_id), \
+    FOREIGN KEY(hotel_id) REFERENCES hotels(hotel_id))";
+
+pub const CREATE_USER_REVIEWS_TABLE: &str = "\
+    create table if not exists user_reviews(\
+    review_id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    user_id INT NOT NULL, hotel_id INT NOT NULL, title VARCHAR(200), \
+    text VARCHAR(5000), FOREIGN KEY(user_id) REFERENCES users(id), \
+    FOREIGN KEY(hotel_id) REFERENCES hotels(hotel_id))";
+
+pub const CREATE_SCHEMA_MIGRATIONS_TABLE: &str = "\
+    create table if not exists schema_migrations(\
+    version INTEGER PRIMARY KEY, name VARCHAR(200) NOT NULL, \
+    applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP)";
+
+pub const CREATE_REFRESH_TOKENS_TABLE: &str = "\
+    create table if not exists refresh_tokens(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    user_id INT NOT NULL, family CHAR(32) NOT NULL, \
+    token_hash CHAR(64) NOT NULL UNIQUE, created_at DATETIME NOT NULL, \
+    expires_at DATETIME NOT NULL, used_at DATETIME, revoked_at DATETIME, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const UPSERT_HOTELS: &str = " on conflict(hotel_id) do update set \
+    name = excluded.name, address = excluded.address, city = excluded.city, \
+    province = excluded.province, country = excluded.country \
+    where name is not excluded.name or address is not excluded.address \
+    or city is not excluded.city or province is not excluded.province \
+    or country is not excluded.country";
+
+pub const UPSERT_REVIEWS: &str = " on conflict(review_id) do update set \
+    hotel_id = excluded.hotel_id, rating = excluded.rating, \
+    author = excluded.author, title = excluded.title, text = excluded.text, \
+    time = excluded.time \
+    where hotel_id is not excluded.hotel_id or rating is not excluded.rating \
+    or author is not excluded.author or title is not excluded.title \
+    or text is not excluded.text or time is not excluded.time";
+
+pub const DROP_REVIEWS_TIME_INDEX: &str = "drop index reviews_hotel_time";
+
+pub const CREATE_RATE_LIMITS_TABLE: &str = "\
+    create table if not exists rate_limits(bucket_key VARCHAR(255) NOT NULL \
+    PRIMARY KEY, tokens REAL NOT NULL, updated_at DATETIME NOT NULL, \
+    full_at DATETIME NOT NULL, version INT NOT NULL)";
+
+pub const INSERT_RATE_LIMIT: &str = "insert into rate_limits(\
+    bucket_key, tokens, updated_at, full_at, version) values (?, ?, ?, ?, 0) \
+    on conflict do nothing";
+
+pub const CREATE_EMAIL_TOKENS_TABLE: &str = "\
+    create table if not exists email_tokens(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    user_id INT NOT NULL, purpose VARCHAR(32) NOT NULL, \
+    token_id CHAR(32) NOT NULL UNIQUE, created_at DATETIME NOT NULL, \
+    expires_at DATETIME NOT NULL, used_at DATETIME, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_API_KEYS_TABLE: &str = "\
+    create table if not exists api_keys(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    name VARCHAR(100) NOT NULL, prefix CHAR(12) NOT NULL UNIQUE, \
+    secret_hash CHAR(64) NOT NULL, scopes VARCHAR(255) NOT NULL, \
+    created_by INT, created_at DATETIME NOT NULL, expires_at DATETIME, \
+    last_used_at DATETIME, revoked_at DATETIME, \
+    FOREIGN KEY(created_by) REFERENCES users(id) ON DELETE SET NULL)";
+
+pub const CREATE_LOGIN_EVENTS_TABLE: &str = "\
+    create table if not exists login_events(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    user_id INT, username VARCHAR(255) NOT NULL, ip VARCHAR(45) NOT NULL, \
+    user_agent VARCHAR(255), success BOOLEAN NOT NULL, reason VARCHAR(32), \
+    created_at DATETIME NOT NULL, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE SET NULL)";
+
+pub const CREATE_LOGIN_LOCKOUTS_TABLE: &str = "\
+    create table if not exists login_lockouts(user_id INT NOT NULL \
+    PRIMARY KEY, failed_logins INT NOT NULL, locked_until DATETIME, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const INCREMENT_LOGIN_FAILURES: &str = "insert into login_lockouts(\
+    user_id, failed_logins) values (?, 1) on conflict(user_id) \
+    do update set failed_logins = login_lockouts.failed_logins + 1";
+
+pub const CREATE_TOTP_TABLE: &str = "\
+    create table if not exists totp(user_id INT NOT NULL PRIMARY KEY, \
+    secret VARCHAR(64) NOT NULL, confirmed_at DATETIME, last_used_step BIGINT, \
+    created_at DATETIME NOT NULL, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+pub const CREATE_RECOVERY_CODES_TABLE: &str = "\
+    create table if not exists recovery_codes(\
+    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+    user_id INT NOT NULL, code_hash CHAR(64) NOT NULL, used_at DATETIME, \
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE)";
+
+/// SQLite has no row locks, writing takes the database's write lock before
+/// the transaction reads anything.
+pub const LOCK_HOTEL: &str = "update hotels set hotel_id = hotel_id where hotel_id = ?";
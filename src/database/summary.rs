@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+use crate::database::error::DbResult;
+use crate::database::sql_strs::{DELETE_RATING_SUMMARIES, SUMMARIZE_RATINGS};
+
+/**
+ * A row of 'hotel_rating_summary': the ratings of a hotel's reviews and
+ * rated user reviews, kept up to date on every write.
+ *
+ * # Fields:
+ * - 'review_count', 'rating_sum': What the average is worked out from.
+ * - 'rating_1' to 'rating_5': How many reviews gave that many stars.
+ * - 'last_review_time': When the latest rated review was submitted.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct RatingSummary {
+    pub hotel_id: i32,
+    pub review_count: i32,
+    pub rating_sum: i32,
+    pub rating_1: i32,
+    pub rating_2: i32,
+    pub rating_3: i32,
+    pub rating_4: i32,
+    pub rating_5: i32,
+    pub last_review_time: Option<DateTime<Utc>>,
+}
+
+impl RatingSummary {
+    pub fn new(hotel_id: i32) -> RatingSummary {
+        RatingSummary {
+            hotel_id, review_count: 0, rating_sum: 0, rating_1: 0, rating_2: 0,
+            rating_3: 0, rating_4: 0, rating_5: 0, last_review_time: None,
+        }
+    }
+
+    /// Counts one more review, like 'SUMMARIZE_RATINGS' does in SQL.
+    pub fn add(&mut self, rating: i32, time: Option<DateTime<Utc>>) {
+        self.review_count += 1;
+        self.rating_sum += rating;
+        match rating {
+            1 => self.rating_1 += 1,
+            2 => self.rating_2 += 1,
+            3 => self.rating_3 += 1,
+            4 => self.rating_4 += 1,
+            5 => self.rating_5 += 1,
+            _ => {},
+        }
+        self.last_review_time = self.last_review_time.max(time);
+    }
+
+    pub fn average(&self) -> Option<f64> {
+        match self.review_count {
+            0 => None,
+            count => Some(self.rating_sum as f64 / count as f64),
+        }
+    }
+}
+
+impl fmt::Display for RatingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} reviews, sum {}, [{} {} {} {} {}], last {}",
+            self.review_count, self.rating_sum, self.rating_1, self.rating_2,
+            self.rating_3, self.rating_4, self.rating_5,
+            self.last_review_time.map_or("never".to_string(), |t| t.to_rfc3339()))
+    }
+}
+
+/// A hotel whose stored summary didn't match the one rebuilt from scratch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryDrift {
+    pub hotel_id: i32,
+    pub stored: Option<RatingSummary>,
+    pub rebuilt: Option<RatingSummary>,
+}
+
+impl fmt::Display for SummaryDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |s: &Option<RatingSummary>| match s {
+            Some(s) => s.to_string(),
+            None => "missing".to_string(),
+        };
+        write!(f, "hotel {}: stored {}, rebuilt {}",
+            self.hotel_id, show(&self.stored), show(&self.rebuilt))
+    }
+}
+
+/**
+ * Rating summaries of the hotels.
+ *
+ * SQL backends read them from 'hotel_rating_summary', which user review
+ * writes refresh in the same transaction as the rows they touch, and bulk
+ * loads once all their batches are in.
+ */
+#[async_trait]
+pub trait RatingSummaryRepository: Send + Sync {
+    async fn get_rating_summary(&self, hotel_id: i32
+    ) -> DbResult<Option<RatingSummary>>;
+
+    /**
+     * Recomputes every summary from the reviews and user reviews.
+     *
+     * # Returns:
+     * - The hotels whose stored summary was wrong or missing, as they were
+     *   before and after the rebuild.
+     */
+    async fn rebuild_rating_summaries(&self) -> DbResult<Vec<SummaryDrift>>;
+}
+
+/**
+ * Builds the statements recomputing the summaries of 'hotel_ids' from
+ * scratch: one deleting their rows, one inserting them again. Hotels left
+ * without rated reviews end up without a row.
+ */
+pub(crate) fn refresh_statements<'a, DB: Database>(hotel_ids: &[i32]
+) -> (QueryBuilder<'a, DB>, QueryBuilder<'a, DB>)
+where
+    i32: 'a + Encode<'a, DB> + Type<DB> + Send,
+{
+    let mut hotel_ids = hotel_ids.to_vec();
+    hotel_ids.sort_unstable();
+    hotel_ids.dedup();
+
+    let mut delete = QueryBuilder::new(DELETE_RATING_SUMMARIES);
+    delete.push(" where hotel_id in (");
+    push_ids(&mut delete, &hotel_ids);
+
+    let mut insert = QueryBuilder::new(SUMMARIZE_RATINGS);
+    insert.push("where hotel_id in (");
+    push_ids(&mut insert, &hotel_ids);
+    insert.push(" group by hotel_id");
+
+    (delete, insert)
+}
+
+fn push_ids<'a, DB: Database>(qb: &mut QueryBuilder<'a, DB>, ids: &[i32])
+where
+    i32: 'a + Encode<'a, DB> + Type<DB> + Send,
+{
+    let mut separated = qb.separated(", ");
+    for &id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(")");
+}
+
+/// Pairs up the summaries before and after a rebuild, keeping the differences.
+pub(crate) fn drift(stored: Vec<RatingSummary>, rebuilt: Vec<RatingSummary>
+) -> Vec<SummaryDrift> {
+    let mut pairs: BTreeMap<i32, (Option<RatingSummary>, Option<RatingSummary>)> =
+        BTreeMap::new();
+    for s in stored {
+        let hotel_id = s.hotel_id;
+        pairs.entry(hotel_id).or_default().0 = Some(s);
+    }
+    for s in rebuilt {
+        let hotel_id = s.hotel_id;
+        pairs.entry(hotel_id).or_default().1 = Some(s);
+    }
+
+    pairs.into_iter()
+        .filter(|(_, (stored, rebuilt))| stored != rebuilt)
+        .map(|(hotel_id, (stored, rebuilt))| SummaryDrift { hotel_id, stored, rebuilt })
+        .collect()
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::error::DbResult;
+
+/**
+ * A row of 'totp', the two-factor authentication of a user.
+ *
+ * # Fields:
+ * - 'secret': Base32, as authenticator apps take it.
+ * - 'confirmed_at': 'None' while enrolling, logins only ask for codes once
+ *   a first one confirmed the app has the secret.
+ * - 'last_used_step': The time step of the latest code used, codes of it
+ *   and earlier steps are refused so a code works once.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Totp {
+    pub user_id: i32,
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Totp {
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+/**
+ * TOTP secrets and the one-time recovery codes that stand in for a code,
+ * stored SHA-256 hashed.
+ *
+ * SQL backends use codes with guarded UPDATEs, like email tokens, so of two
+ * requests racing with the same code only one gets through.
+ */
+#[async_trait]
+pub trait TotpRepository: Send + Sync {
+    async fn get_totp(&self, user_id: i32) -> DbResult<Option<Totp>>;
+
+    /// Starts enrolling 'user_id', replacing an unconfirmed secret. Fails if
+    /// the user's TOTP is confirmed.
+    async fn set_totp_secret(&self, user_id: i32, secret: &str, now: DateTime<Utc>
+    ) -> DbResult<()>;
+
+    /**
+     * Turns on the user's TOTP, with the step of the code that confirmed it
+     * and new recovery codes replacing any old ones.
+     *
+     * # Returns:
+     * - false if the user isn't enrolling.
+     */
+    async fn confirm_totp(
+        &self, user_id: i32, step: i64, recovery_code_hashes: &[String], now: DateTime<Utc>
+    ) -> DbResult<bool>;
+
+    /// Uses a code of 'step', false if one of it or a later step was used.
+    async fn use_totp_step(&self, user_id: i32, step: i64) -> DbResult<bool>;
+
+    /// Uses a recovery code, false if it's unknown or used.
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str, now: DateTime<Utc>
+    ) -> DbResult<bool>;
+
+    /// Turns off the user's TOTP and deletes their recovery codes.
+    async fn delete_totp(&self, user_id: i32) -> DbResult<bool>;
+}
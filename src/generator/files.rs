@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use chrono::SecondsFormat;
+use serde_json::{json, Value};
+
+use crate::hotels_info::{Hotel, Review};
+
+/**
+ * Writes 'hotels' as JSON files in the '{"sr": [...]}' layout the parser
+ * reads, 'per_file' hotels to a file.
+ *
+ * # Returns:
+ * - The number of files written to 'dir', which is created if needed.
+ */
+pub fn write_hotels(dir: &Path, hotels: &[Hotel], per_file: usize
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut files = 0;
+
+    for (i, chunk) in hotels.chunks(per_file.max(1)).enumerate() {
+        let sr: Vec<Value> = chunk.iter().map(|h| json!({
+            "id": h.hotel_id.to_string(),
+            "f": h.name,
+            "ad": h.address,
+            "ci": h.city,
+            "pr": h.province,
+            "c": h.country,
+        })).collect();
+        write_json(&dir.join(format!("hotels_{i}.json")), &json!({ "sr": sr }))?;
+        files += 1;
+    }
+
+    Ok(files)
+}
+
+/**
+ * Writes 'reviews' as JSON files in the 'reviewDetails' layout the parser
+ * reads, one page of at most 'per_page' reviews of a single hotel per file.
+ *
+ * "ANONYMOUS" authors and "NO TITLE" titles are written as empty strings,
+ * so parsing the files gives back the same reviews.
+ *
+ * # Returns:
+ * - The number of files written to 'dir', which is created if needed.
+ */
+pub fn write_reviews(
+    dir: &Path, reviews: &HashMap<i32, Vec<Review>>, per_page: usize
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let mut files = 0;
+
+    for (hotel_id, hotel_reviews) in reviews {
+        for (page, chunk) in hotel_reviews.chunks(per_page.max(1)).enumerate() {
+            let review: Vec<Value> = chunk.iter().map(|r| json!({
+                "hotelId": r.hotel_id.to_string(),
+                "reviewId": r.review_id,
+                "ratingOverall": r.rating,
+                "title": match r.title.as_str() {
+                    "NO TITLE" => "",
+                    title => title,
+                },
+                "reviewText": r.text,
+                "userNickname": match r.author.as_str() {
+                    "ANONYMOUS" => "",
+                    author => author,
+                },
+                "reviewSubmissionTime":
+                    r.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            })).collect();
+            let details = json!({
+                "reviewDetails": {
+                    "numberOfReviewsInThisPage": review.len(),
+                    "reviewCollection": { "review": review },
+                }
+            });
+            write_json(
+                &dir.join(format!("review_{hotel_id}_{page}.json")), &details
+            )?;
+            files += 1;
+        }
+    }
+
+    Ok(files)
+}
+
+fn write_json(path: &Path, value: &Value) -> io::Result<()> {
+    let file = io::BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer(file, value).map_err(io::Error::from)
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+pub mod files;
+pub mod seed;
+mod words;
+
+pub use files::*;
+pub use seed::*;
+
+use crate::database::{NewUser, NewUserReview};
+use crate::hotels_info::{Hotel, Review};
+use words::*;
+
+/// How many reviews each generated hotel gets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReviewCount {
+    Fixed(usize),
+    /// Uniformly picked from 'min..=max'.
+    Uniform { min: usize, max: usize },
+    /// Exponentially distributed around 'mean', so a few hotels get most of
+    /// the reviews, like in the real data.
+    Exponential { mean: f64 },
+}
+
+/**
+ * Parameters of a generated dataset.
+ *
+ * # Fields:
+ * - 'seed': Seed of the RNG, the same options always generate the same data.
+ * - 'hotels': Number of hotels, with ids '1..=hotels'.
+ * - 'reviews_per_hotel': Distribution of reviews per hotel.
+ * - 'text_words': Inclusive range of words in a review's text.
+ * - 'from', 'to': Review submission times are picked from 'from..to'.
+ * - 'users': Number of users to seed.
+ * - 'user_reviews_per_user': Inclusive range of user reviews per user.
+ */
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    pub seed: u64,
+    pub hotels: usize,
+    pub reviews_per_hotel: ReviewCount,
+    pub text_words: (usize, usize),
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub users: usize,
+    pub user_reviews_per_user: (usize, usize),
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            seed: 0,
+            hotels: 100,
+            reviews_per_hotel: ReviewCount::Uniform { min: 0, max: 50 },
+            text_words: (5, 80),
+            from: Utc.with_ymd_and_hms(2010, 1, 1, 0, 0, 0).unwrap(),
+            to: Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
+            users: 0,
+            user_reviews_per_user: (0, 5),
+        }
+    }
+}
+
+/**
+ * Deterministic generator of hotels, reviews, users and user reviews.
+ *
+ * Everything is drawn from one ChaCha RNG, which is portable across
+ * platforms and 'rand' versions, so calls made in the same order with the
+ * same options produce the same data.
+ */
+pub struct Generator {
+    opts: GeneratorOptions,
+    rng: ChaCha8Rng,
+}
+
+impl Generator {
+    pub fn new(opts: GeneratorOptions) -> Generator {
+        let rng = ChaCha8Rng::seed_from_u64(opts.seed);
+        Generator { opts, rng }
+    }
+
+    pub fn options(&self) -> &GeneratorOptions {
+        &self.opts
+    }
+
+    pub fn hotels(&mut self) -> Vec<Hotel> {
+        (1..=self.opts.hotels as i32).map(|hotel_id| {
+            let (city, province, country) = *self.pick(CITIES);
+            Hotel {
+                hotel_id,
+                name: format!("{} {} {}",
+                    self.pick(HOTEL_PREFIXES), city, self.pick(HOTEL_KINDS)),
+                address: format!("{} {} {}", self.rng.gen_range(1..2000),
+                    self.pick(STREETS), self.pick(STREET_KINDS)),
+                city: city.to_string(),
+                province: province.to_string(),
+                country: country.to_string(),
+            }
+        }).collect()
+    }
+
+    /// Reviews for each of 'hotels', keyed by hotel id.
+    pub fn reviews(&mut self, hotels: &[Hotel]) -> HashMap<i32, Vec<Review>> {
+        let mut reviews = HashMap::new();
+        for hotel in hotels {
+            let count = self.review_count();
+            let hotel_reviews: Vec<Review> = (0..count)
+                .map(|i| self.review(hotel.hotel_id, i)).collect();
+            if !hotel_reviews.is_empty() {
+                reviews.insert(hotel.hotel_id, hotel_reviews);
+            }
+        }
+        reviews
+    }
+
+    pub fn users(&mut self) -> Vec<NewUser> {
+        // Usernames are at most 15 characters long
+        (1..=self.opts.users).map(|i| {
+            let username = format!("{}{i}", self.pick(FIRST_NAMES));
+            let username: String = username.chars().take(15).collect();
+            NewUser {
+                email: format!("{username}@example.com"), username,
+                password_hash: None,
+            }
+        }).collect()
+    }
+
+    /// Reviews by the users with ids 'user_ids' of the hotels 'hotel_ids'.
+    pub fn user_reviews(&mut self, user_ids: &[i32], hotel_ids: &[i32]
+    ) -> Vec<NewUserReview> {
+        if hotel_ids.is_empty() {
+            return vec![];
+        }
+        let (min, max) = self.opts.user_reviews_per_user;
+        let mut user_reviews = vec![];
+        for &user_id in user_ids {
+            for _ in 0..self.rng.gen_range(min..=max.max(min)) {
+                user_reviews.push(NewUserReview {
+                    user_id,
+                    hotel_id: *self.pick(hotel_ids),
+                    title: self.sentence(2, 6),
+                    text: self.text(),
+                    rating: Some(self.rating()),
+                });
+            }
+        }
+        user_reviews
+    }
+
+    fn review(&mut self, hotel_id: i32, index: usize) -> Review {
+        let rating = self.rating();
+        let author = match self.rng.gen_bool(0.1) {
+            true => "ANONYMOUS".to_string(),
+            false => format!("{}{}", self.pick(FIRST_NAMES),
+                self.rng.gen_range(1..100)),
+        };
+        let title = match self.rng.gen_bool(0.05) {
+            true => "NO TITLE".to_string(),
+            false => self.sentence(2, 6),
+        };
+        Review {
+            hotel_id,
+            review_id: format!("{hotel_id:08x}{index:08x}{:08x}",
+                self.rng.gen::<u32>()),
+            rating,
+            author,
+            title,
+            text: self.text(),
+            time: self.time(),
+        }
+    }
+
+    // Mostly good reviews, like on most booking sites
+    fn rating(&mut self) -> i32 {
+        match self.rng.gen_range(0..100) {
+            0..=4 => 1,
+            5..=14 => 2,
+            15..=34 => 3,
+            35..=69 => 4,
+            _ => 5,
+        }
+    }
+
+    fn review_count(&mut self) -> usize {
+        match self.opts.reviews_per_hotel {
+            ReviewCount::Fixed(n) => n,
+            ReviewCount::Uniform { min, max } => self.rng.gen_range(min..=max.max(min)),
+            ReviewCount::Exponential { mean } => {
+                let u: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+                (-mean * u.ln()).round() as usize
+            },
+        }
+    }
+
+    fn time(&mut self) -> DateTime<Utc> {
+        let span = (self.opts.to - self.opts.from).num_seconds().max(1);
+        self.opts.from + Duration::seconds(self.rng.gen_range(0..span))
+    }
+
+    fn text(&mut self) -> String {
+        let (min, max) = self.opts.text_words;
+        self.sentence(min, max)
+    }
+
+    fn sentence(&mut self, min: usize, max: usize) -> String {
+        let len = self.rng.gen_range(min..=max.max(min));
+        let words: Vec<&str> = (0..len).map(|_| *self.pick(WORDS)).collect();
+        let mut sentence = words.join(" ");
+        if let Some(first) = sentence.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        sentence
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.rng.gen_range(0..items.len())]
+    }
+}
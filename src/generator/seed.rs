@@ -0,0 +1,57 @@
+use std::fmt;
+
+use crate::database::{AppState, DbError, DbResult};
+use crate::generator::Generator;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub users_added: usize,
+    /// Generated users that were already in the database.
+    pub users_existing: usize,
+    pub user_reviews_added: usize,
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "users: {} added, {} already there; user_reviews: {} added",
+            self.users_added, self.users_existing, self.user_reviews_added)
+    }
+}
+
+/**
+ * Seeds generated users, and reviews by them, through the repositories of
+ * 'app_state', so it works the same on every backend.
+ *
+ * # Parameters:
+ * - 'hotel_ids': Hotels the user reviews may be about, they must exist in
+ *   the database already.
+ *
+ * # Returns:
+ * - What was added. Users that already exist are reused, so seeding twice
+ *   only adds more user reviews.
+ */
+pub async fn seed_users(
+    generator: &mut Generator, app_state: &AppState, hotel_ids: &[i32]
+) -> DbResult<SeedReport> {
+    let mut report = SeedReport::default();
+    let mut user_ids = vec![];
+
+    for user in generator.users() {
+        match app_state.users.add_user(&user).await {
+            Ok(()) => report.users_added += 1,
+            Err(DbError::Constraint(_)) => report.users_existing += 1,
+            Err(e) => return Err(e),
+        }
+        if let Some(u) = app_state.users
+            .get_user_by_creds(&user.username, &user.email).await? {
+            user_ids.push(u.id);
+        }
+    }
+
+    for review in generator.user_reviews(&user_ids, hotel_ids) {
+        app_state.user_reviews.add_user_review(&review).await?;
+        report.user_reviews_added += 1;
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,41 @@
+pub const HOTEL_PREFIXES: &[&str] = &[
+    "Grand", "Royal", "Comfort", "Budget", "Harbor", "Central", "Park",
+    "Sunset", "Garden", "Plaza", "Riverside", "Historic",
+];
+
+pub const HOTEL_KINDS: &[&str] = &[
+    "Hotel", "Inn", "Suites", "Lodge", "Resort", "Motel", "Hostel",
+];
+
+pub const STREETS: &[&str] = &[
+    "Main", "Market", "Mission", "Oak", "Pine", "Lake", "Hill", "Bay",
+    "Union", "Broadway", "Washington", "Lincoln",
+];
+
+pub const STREET_KINDS: &[&str] = &["St", "Ave", "Blvd", "Rd", "Way"];
+
+/// City, province and country of a generated hotel.
+pub const CITIES: &[(&str, &str, &str)] = &[
+    ("San Francisco", "CA", "USA"), ("Los Angeles", "CA", "USA"),
+    ("New York", "NY", "USA"), ("Chicago", "IL", "USA"),
+    ("Seattle", "WA", "USA"), ("Boston", "MA", "USA"),
+    ("Austin", "TX", "USA"), ("Miami", "FL", "USA"),
+    ("Toronto", "ON", "Canada"), ("Vancouver", "BC", "Canada"),
+    ("Montreal", "QC", "Canada"), ("Guadalajara", "JA", "Mexico"),
+];
+
+pub const FIRST_NAMES: &[&str] = &[
+    "ana", "ben", "carla", "dan", "elena", "frank", "gina", "hugo", "iris",
+    "jon", "kate", "luis", "maria", "nick", "olga", "pedro", "rosa", "sam",
+];
+
+pub const WORDS: &[&str] = &[
+    "the", "room", "was", "clean", "and", "staff", "very", "friendly",
+    "location", "great", "breakfast", "good", "bed", "comfortable", "noisy",
+    "at", "night", "small", "bathroom", "but", "price", "fair", "would",
+    "stay", "again", "view", "from", "window", "parking", "expensive", "pool",
+    "nice", "walk", "to", "downtown", "check-in", "quick", "wifi", "slow",
+    "lobby", "beautiful", "service", "excellent", "old", "carpet", "close",
+    "airport", "shuttle", "helpful", "desk", "quiet", "spacious", "dirty",
+    "towels", "coffee", "restaurant", "recommend", "family", "trip", "city",
+];
@@ -12,7 +12,8 @@ pub struct Hotel {
     pub country: String,
 }
 
-#[derive(Debug, Clone)]
+/// A hotel review, as parsed from the data files and as served by the API.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
 pub struct Review {
     pub hotel_id: i32,
     pub review_id: String,
@@ -23,7 +24,7 @@ pub struct Review {
     pub time: DateTime<Utc>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct HotelsInfo {
     hotels_map: HashMap<i32, Hotel>,
     reviews_map: HashMap<i32, Vec<Review>>,
@@ -33,7 +34,13 @@ impl HotelsInfo {
     pub fn new() -> HotelsInfo {
         let hotels: HashMap<i32, Hotel> = HashMap::new();
         let reviews: HashMap<i32, Vec<Review>> = HashMap::new();
-        return HotelsInfo { hotels_map: hotels, reviews_map: reviews };
+        HotelsInfo { hotels_map: hotels, reviews_map: reviews }
+    }
+
+    pub fn from_data(
+        hotels: HashMap<i32, Hotel>, reviews: HashMap<i32, Vec<Review>>
+    ) -> HotelsInfo {
+        HotelsInfo { hotels_map: hotels, reviews_map: reviews }
     }
 
     pub fn get_hotels(&self) -> HashMap<i32, Hotel> {
@@ -61,14 +68,14 @@ impl HotelsInfo {
     }
 
     pub fn search_hotels(&self, hotel_id: i32) -> Option<Hotel> {
-        return match self.hotels_map.contains_key(&hotel_id) {
+        match self.hotels_map.contains_key(&hotel_id) {
             true => Some(self.hotels_map.get(&hotel_id).unwrap().clone()),
             false => None,
         }
     }
 
     pub fn search_reviews(&self, hotel_id: i32) -> Option<Vec<Review>> {
-        return match self.reviews_map.contains_key(&hotel_id) {
+        match self.reviews_map.contains_key(&hotel_id) {
             true => {
                 let mut reviews: Vec<Review> = vec![];
                 for r in self.reviews_map.get(&hotel_id).unwrap() {
@@ -0,0 +1,474 @@
+use std::fs;
+use std::path::Path;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation
+};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType
+};
+use rand::{rngs::OsRng, RngCore};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use simple_asn1::{from_der, oid, ASN1Block};
+
+pub const DEFAULT_ISSUER: &str = "hotels";
+pub const DEFAULT_AUDIENCE: &str = "hotels";
+
+/// The algorithms tokens can be signed with.
+pub const ALGORITHMS: &[Algorithm] = &[Algorithm::HS256, Algorithm::RS256, Algorithm::EdDSA];
+
+/// The public half of an asymmetric key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    Ed25519 { x: Vec<u8> },
+}
+
+impl PublicKey {
+    /// Reads a 'PUBLIC KEY' (SubjectPublicKeyInfo) or 'RSA PUBLIC KEY' PEM.
+    fn from_pem(pem: &pem::Pem) -> Result<PublicKey, String> {
+        match pem.tag() {
+            "RSA PUBLIC KEY" => PublicKey::from_rsa_der(pem.contents()),
+            "PUBLIC KEY" => match from_der(pem.contents()).as_deref() {
+                Ok([ASN1Block::Sequence(_, info)]) => match info.as_slice() {
+                    [ASN1Block::Sequence(_, alg), ASN1Block::BitString(_, _, key)] =>
+                        match alg.first() {
+                            Some(ASN1Block::ObjectIdentifier(_, id))
+                                if *id == oid!(1, 2, 840, 113549, 1, 1, 1) =>
+                                PublicKey::from_rsa_der(key),
+                            Some(ASN1Block::ObjectIdentifier(_, id))
+                                if *id == oid!(1, 3, 101, 112) && key.len() == 32 =>
+                                Ok(PublicKey::Ed25519 { x: key.clone() }),
+                            _ => Err("Only RSA and Ed25519 keys are supported".to_string()),
+                        },
+                    _ => Err("Malformed public key".to_string()),
+                },
+                _ => Err("Malformed public key".to_string()),
+            },
+            tag => Err(format!("Expected a public key, got '{tag}'")),
+        }
+    }
+
+    /// Reads a PKCS#1 'RSAPublicKey', the sequence of the modulus and exponent.
+    fn from_rsa_der(der: &[u8]) -> Result<PublicKey, String> {
+        match from_der(der).as_deref() {
+            Ok([ASN1Block::Sequence(_, ints)]) => match ints.as_slice() {
+                [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => Ok(PublicKey::Rsa {
+                    n: n.to_bytes_be().1, e: e.to_bytes_be().1
+                }),
+                _ => Err("Malformed RSA public key".to_string()),
+            },
+            _ => Err("Malformed RSA public key".to_string()),
+        }
+    }
+
+    /// The public half of a private key PEM, as 'algorithm' reads it.
+    fn of_private_pem(pem: &pem::Pem, algorithm: Algorithm) -> Result<PublicKey, String> {
+        match (algorithm, pem.tag()) {
+            (Algorithm::RS256, "RSA PRIVATE KEY") => RsaKeyPair::from_der(pem.contents())
+                .map(|pair| PublicKey::of_rsa_pair(&pair))
+                .map_err(|e| format!("Invalid RSA private key: {e}")),
+            (Algorithm::RS256, "PRIVATE KEY") => RsaKeyPair::from_pkcs8(pem.contents())
+                .map(|pair| PublicKey::of_rsa_pair(&pair))
+                .map_err(|e| format!("Invalid RSA private key: {e}")),
+            (Algorithm::EdDSA, "PRIVATE KEY") =>
+                Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+                    .map(|pair| PublicKey::Ed25519 { x: pair.public_key().as_ref().to_vec() })
+                    .map_err(|e| format!("Invalid Ed25519 private key: {e}")),
+            (_, tag) => Err(format!("Expected a {algorithm:?} private key, got '{tag}'")),
+        }
+    }
+
+    fn of_rsa_pair(pair: &RsaKeyPair) -> PublicKey {
+        let RsaPublicKeyComponents { n, e } = pair.public().into();
+        PublicKey::Rsa { n, e }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            PublicKey::Rsa { .. } => Algorithm::RS256,
+            PublicKey::Ed25519 { .. } => Algorithm::EdDSA,
+        }
+    }
+
+    /// The RFC 7638 thumbprint, used as the key id: the SHA-256 of the
+    /// required members of the JWK, in lexicographic order.
+    fn thumbprint(&self) -> String {
+        let members = match self {
+            PublicKey::Rsa { n, e } => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+                URL_SAFE_NO_PAD.encode(e), URL_SAFE_NO_PAD.encode(n)),
+            PublicKey::Ed25519 { x } => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#,
+                URL_SAFE_NO_PAD.encode(x)),
+        };
+        URL_SAFE_NO_PAD.encode(Sha256::digest(members))
+    }
+
+    fn jwk(&self, kid: &str) -> Jwk {
+        let (key_algorithm, algorithm) = match self {
+            PublicKey::Rsa { n, e } => (KeyAlgorithm::RS256, AlgorithmParameters::RSA(
+                RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(n),
+                    e: URL_SAFE_NO_PAD.encode(e),
+                }
+            )),
+            PublicKey::Ed25519 { x } => (KeyAlgorithm::EdDSA, AlgorithmParameters::OctetKeyPair(
+                OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(x),
+                }
+            )),
+        };
+        Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.to_string()),
+                ..Default::default()
+            },
+            algorithm,
+        }
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        match self {
+            PublicKey::Rsa { n, e } => DecodingKey::from_rsa_raw_components(n, e),
+            PublicKey::Ed25519 { x } => DecodingKey::from_ed_der(x),
+        }
+    }
+}
+
+/// A key tokens are accepted from, by the 'kid' of their header.
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/**
+ * Signs and verifies access tokens (JWTs).
+ *
+ * With HS256 tokens are signed and verified with a shared secret. With
+ * RS256 or EdDSA they're signed with a private key and verified with its
+ * public key, which other services can get from '/.well-known/jwks.json'.
+ * Asymmetric keys are identified by their RFC 7638 thumbprint, set as the
+ * 'kid' of the tokens they sign.
+ *
+ * To rotate keys, sign with the new private key and keep the public key of
+ * the old one among the verification keys until the last tokens it signed
+ * expire.
+ *
+ * # Fields:
+ * - 'verification': The signing key's public half first, then the keys
+ *   still accepted.
+ * - 'jwks': The public keys, as served.
+ * - 'issuer', 'audience': Set as the 'iss' and 'aud' claims and required
+ *   when verifying.
+ */
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing: EncodingKey,
+    kid: Option<String>,
+    verification: Vec<VerificationKey>,
+    jwks: JwkSet,
+    issuer: String,
+    audience: String,
+}
+
+impl Default for JwtKeys {
+    /// HS256 with a random secret, so tokens don't outlive the process.
+    fn default() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        JwtKeys::hmac(&secret)
+    }
+}
+
+impl JwtKeys {
+    pub fn hmac(secret: &[u8]) -> JwtKeys {
+        JwtKeys {
+            algorithm: Algorithm::HS256,
+            signing: EncodingKey::from_secret(secret),
+            kid: None,
+            verification: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret),
+            }],
+            jwks: JwkSet { keys: Vec::new() },
+            issuer: DEFAULT_ISSUER.to_string(),
+            audience: DEFAULT_AUDIENCE.to_string(),
+        }
+    }
+
+    /**
+     * # Parameters:
+     * - 'algorithm': RS256 or EdDSA, the kind of 'private_key'.
+     * - 'private_key': The PEM file tokens are signed with, PKCS#8 or for
+     *   RSA also PKCS#1.
+     * - 'public_keys': PEM files of the public keys still accepted besides
+     *   the signing key's own, RSA or Ed25519.
+     */
+    pub fn from_pem_files<P: AsRef<Path>, Q: AsRef<Path>>(
+        algorithm: Algorithm, private_key: P, public_keys: &[Q]
+    ) -> Result<JwtKeys, String> {
+        let read = |path: &Path| fs::read(path)
+            .map_err(|e| format!("Error reading '{}': {e}", path.display()));
+        let parse = |path: &Path, bytes: &[u8]| pem::parse(bytes)
+            .map_err(|e| format!("Invalid PEM in '{}': {e}", path.display()));
+
+        let private_key = private_key.as_ref();
+        let bytes = read(private_key)?;
+        let signing = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(&bytes),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&bytes),
+            _ => return Err(format!("Can't sign with {algorithm:?} keys")),
+        }.map_err(|e| format!("Invalid private key '{}': {e}", private_key.display()))?;
+        let mut public = vec![
+            PublicKey::of_private_pem(&parse(private_key, &bytes)?, algorithm)
+                .map_err(|e| format!("{e} in '{}'", private_key.display()))?
+        ];
+        for path in public_keys.iter().map(AsRef::as_ref) {
+            let key = PublicKey::from_pem(&parse(path, &read(path)?)?)
+                .map_err(|e| format!("{e} in '{}'", path.display()))?;
+            if !public.contains(&key) {
+                public.push(key);
+            }
+        }
+
+        let kids: Vec<String> = public.iter().map(PublicKey::thumbprint).collect();
+        Ok(JwtKeys {
+            algorithm,
+            signing,
+            kid: Some(kids[0].clone()),
+            verification: public.iter().zip(&kids).map(|(key, kid)| VerificationKey {
+                kid: Some(kid.clone()), algorithm: key.algorithm(), key: key.decoding_key()
+            }).collect(),
+            jwks: JwkSet {
+                keys: public.iter().zip(&kids).map(|(key, kid)| key.jwk(kid)).collect()
+            },
+            issuer: DEFAULT_ISSUER.to_string(),
+            audience: DEFAULT_AUDIENCE.to_string(),
+        })
+    }
+
+    /// Replaces the default 'iss' and 'aud' claims.
+    pub fn with_claims(self, issuer: String, audience: String) -> JwtKeys {
+        JwtKeys { issuer, audience, ..self }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    /// The public keys tokens are verified with, empty for HS256.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, String> {
+        let header = Header { kid: self.kid.clone(), ..Header::new(self.algorithm) };
+        encode(&header, claims, &self.signing).map_err(|e| e.to_string())
+    }
+
+    /**
+     * Verifies a token's signature, with the key named by its 'kid', and
+     * its expiry, issuer and audience.
+     */
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, String> {
+        self.decode_for(token, &self.audience)
+    }
+
+    /// Like 'decode', for tokens issued to another audience than access
+    /// tokens, e.g. emailed links.
+    pub fn decode_for<T: DeserializeOwned>(&self, token: &str, audience: &str
+    ) -> Result<T, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let key = self.verification.iter().find(|key| key.kid == header.kid)
+            .ok_or_else(|| match &header.kid {
+                Some(kid) => format!("Unknown key id '{kid}'"),
+                None => "Missing key id".to_string(),
+            })?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        decode::<T>(token, &key.key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use chrono::{Duration, Utc};
+    use ring::rand::SystemRandom;
+    use serde::Deserialize;
+    use tempfile::NamedTempFile;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+        iss: String,
+        aud: String,
+    }
+
+    fn claims(iss: &str, aud: &str) -> TestClaims {
+        TestClaims {
+            sub: "ann".to_string(),
+            exp: (Utc::now() + Duration::minutes(5)).timestamp() as usize,
+            iss: iss.to_string(),
+            aud: aud.to_string(),
+        }
+    }
+
+    fn pem_file(tag: &str, der: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(pem::encode(&pem::Pem::new(tag, der)).as_bytes()).unwrap();
+        file
+    }
+
+    /// A new Ed25519 key, as PKCS#8 and SubjectPublicKeyInfo PEM files.
+    fn ed25519_pems() -> (NamedTempFile, NamedTempFile) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        // The DER of the sequence of the Ed25519 OID and the key's bit string
+        let mut spki = vec![
+            0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00
+        ];
+        spki.extend_from_slice(pair.public_key().as_ref());
+        (pem_file("PRIVATE KEY", pkcs8.as_ref()), pem_file("PUBLIC KEY", &spki))
+    }
+
+    fn ed25519_keys(public_keys: &[&NamedTempFile]) -> (JwtKeys, NamedTempFile) {
+        let (private, public) = ed25519_pems();
+        let paths: Vec<&Path> = public_keys.iter().map(|f| f.path()).collect();
+        (JwtKeys::from_pem_files(Algorithm::EdDSA, private.path(), &paths).unwrap(), public)
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc_7638() {
+        let key = PublicKey::Rsa {
+            n: URL_SAFE_NO_PAD.decode("0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4\
+                cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5Js\
+                GY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZg\
+                nYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lF\
+                d2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw").unwrap(),
+            e: URL_SAFE_NO_PAD.decode("AQAB").unwrap(),
+        };
+        assert_eq!(key.thumbprint(), "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs");
+    }
+
+    #[test]
+    fn hmac_tokens_have_no_kid_or_jwks() {
+        let keys = JwtKeys::hmac(b"secret");
+        let token = keys.encode(&claims(DEFAULT_ISSUER, DEFAULT_AUDIENCE)).unwrap();
+
+        assert_eq!(decode_header(&token).unwrap().kid, None);
+        assert!(keys.jwks().keys.is_empty());
+        assert_eq!(keys.decode::<TestClaims>(&token).unwrap().sub, "ann");
+        assert!(JwtKeys::hmac(b"other").decode::<TestClaims>(&token).is_err());
+    }
+
+    #[test]
+    fn eddsa_tokens_name_their_key_in_the_jwks() {
+        let (keys, _) = ed25519_keys(&[]);
+        let token = keys.encode(&claims(DEFAULT_ISSUER, DEFAULT_AUDIENCE)).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::EdDSA);
+        let [jwk] = keys.jwks().keys.as_slice() else {
+            panic!("expected one key, got {:?}", keys.jwks());
+        };
+        assert_eq!(jwk.common.key_id, header.kid);
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::EdDSA));
+        assert_eq!(jwk.common.public_key_use, Some(PublicKeyUse::Signature));
+        assert!(matches!(&jwk.algorithm, AlgorithmParameters::OctetKeyPair(p)
+            if p.curve == EllipticCurve::Ed25519));
+        assert_eq!(keys.decode::<TestClaims>(&token).unwrap().sub, "ann");
+    }
+
+    #[test]
+    fn rs256_tokens_name_their_key_in_the_jwks() {
+        // A PKCS#1 key made for these tests only
+        let private = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/jwt_rs256_test_key.pem");
+        let keys = JwtKeys::from_pem_files::<_, &Path>(Algorithm::RS256, private, &[]).unwrap();
+        let token = keys.encode(&claims(DEFAULT_ISSUER, DEFAULT_AUDIENCE)).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        let [jwk] = keys.jwks().keys.as_slice() else {
+            panic!("expected one key, got {:?}", keys.jwks());
+        };
+        let AlgorithmParameters::RSA(params) = &jwk.algorithm else {
+            panic!("expected an RSA key, got {:?}", jwk.algorithm);
+        };
+        let public = PublicKey::Rsa {
+            n: URL_SAFE_NO_PAD.decode(&params.n).unwrap(),
+            e: URL_SAFE_NO_PAD.decode(&params.e).unwrap(),
+        };
+        assert_eq!(header.kid, Some(public.thumbprint()));
+        assert_eq!(jwk.common.key_id, header.kid);
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert_eq!(keys.decode::<TestClaims>(&token).unwrap().sub, "ann");
+    }
+
+    #[test]
+    fn rotated_keys_keep_verifying_old_tokens() {
+        let (old, old_public) = ed25519_keys(&[]);
+        let token = old.encode(&claims(DEFAULT_ISSUER, DEFAULT_AUDIENCE)).unwrap();
+
+        let (new, _) = ed25519_keys(&[&old_public]);
+        assert_eq!(new.jwks().keys.len(), 2);
+        assert_eq!(new.jwks().keys[1].common.key_id, old.jwks().keys[0].common.key_id);
+        assert_ne!(decode_header(&new.encode(&claims(DEFAULT_ISSUER, DEFAULT_AUDIENCE))
+            .unwrap()).unwrap().kid, decode_header(&token).unwrap().kid);
+        assert!(new.decode::<TestClaims>(&token).is_ok());
+
+        // Once dropped, its tokens name an unknown key
+        let (newer, _) = ed25519_keys(&[]);
+        let e = newer.decode::<TestClaims>(&token).unwrap_err();
+        assert!(e.starts_with("Unknown key id"), "{e}");
+    }
+
+    #[test]
+    fn decode_for_checks_issuer_audience_and_kid() {
+        let (keys, _) = ed25519_keys(&[]);
+        let keys = keys.with_claims("hotels-test".to_string(), "api".to_string());
+        let decode = |claims: &TestClaims, audience: &str| {
+            keys.decode_for::<TestClaims>(&keys.encode(claims).unwrap(), audience)
+        };
+
+        assert!(decode(&claims("hotels-test", "api"), "api").is_ok());
+        assert!(decode(&claims("hotels-test", "api:totp"), "api:totp").is_ok());
+        // An access token isn't accepted for another audience, nor the reverse
+        assert!(decode(&claims("hotels-test", "api"), "api:totp").is_err());
+        assert!(decode(&claims("hotels-test", "api:totp"), "api").is_err());
+        assert!(decode(&claims("someone-else", "api"), "api").is_err());
+
+        let expired = TestClaims {
+            exp: (Utc::now() - Duration::minutes(5)).timestamp() as usize,
+            ..claims("hotels-test", "api")
+        };
+        assert!(decode(&expired, "api").is_err());
+
+        let hmac = JwtKeys::hmac(b"secret")
+            .with_claims("hotels-test".to_string(), "api".to_string());
+        let e = keys.decode::<TestClaims>(&hmac.encode(&claims("hotels-test", "api")).unwrap())
+            .unwrap_err();
+        assert_eq!(e, "Missing key id");
+    }
+}
@@ -1,5 +1,12 @@
 pub mod config;
+pub mod passwords;
+pub mod tokens;
+pub mod totp;
+pub mod jwt;
+pub mod rate_limit;
+pub mod mailer;
 pub mod hotels_info;
 pub mod parser;
 pub mod database;
-pub mod routes;
\ No newline at end of file
+pub mod routes;
+pub mod generator;
\ No newline at end of file
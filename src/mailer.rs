@@ -0,0 +1,265 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /**
+     * The email as an RFC 5322 message from 'from', with CRLF line endings.
+     *
+     * Addresses and the subject can't hold line breaks, they'd let whoever
+     * picked them add headers.
+     */
+    pub fn message(&self, from: &str) -> Result<String, String> {
+        if [from, &self.to, &self.subject].iter().any(|h| h.contains(['\r', '\n'])) {
+            return Err("Email headers can't contain line breaks".to_string());
+        }
+        let body: Vec<&str> = self.body.lines().collect();
+        Ok(format!(
+            "From: {from}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\
+            MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+            self.to, self.subject, Utc::now().to_rfc2822(), body.join("\r\n")
+        ))
+    }
+}
+
+/**
+ * Sends the emails of the server, e.g. verification and password reset
+ * links.
+ */
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), String>;
+}
+
+/**
+ * Writes emails to a file, or to stdout, instead of sending them. For
+ * development and tests, links can be read back from the output.
+ *
+ * # Fields:
+ * - 'path': The file messages are appended to, stdout when 'None'.
+ */
+pub struct FileMailer {
+    from: String,
+    path: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl FileMailer {
+    pub fn stdout(from: &str) -> FileMailer {
+        FileMailer { from: from.to_string(), path: None, lock: Mutex::new(()) }
+    }
+
+    pub fn file(from: &str, path: PathBuf) -> FileMailer {
+        FileMailer { from: from.to_string(), path: Some(path), lock: Mutex::new(()) }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        let message = format!("{}\n", email.message(&self.from)?.replace("\r\n", "\n"));
+        // One message at a time, so concurrent ones don't interleave
+        let _lock = self.lock.lock().unwrap();
+        match &self.path {
+            Some(path) => OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| file.write_all(message.as_bytes()))
+                .map_err(|e| format!("Error writing email to '{}': {e}", path.display())),
+            None => {
+                println!("{message}");
+                Ok(())
+            },
+        }
+    }
+}
+
+/**
+ * Sends emails to an SMTP server, without TLS: a local relay or a test
+ * server such as MailHog or Mailpit.
+ *
+ * # Fields:
+ * - 'credentials': Username and password for 'AUTH PLAIN', if the server
+ *   wants them.
+ * - 'timeout': For the whole exchange with the server.
+ */
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+    from: String,
+    credentials: Option<(String, String)>,
+    timeout: Duration,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, port: u16, from: &str) -> SmtpMailer {
+        SmtpMailer {
+            host: host.to_string(),
+            port,
+            from: from.to_string(),
+            credentials: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn with_credentials(self, username: &str, password: &str) -> SmtpMailer {
+        SmtpMailer {
+            credentials: Some((username.to_string(), password.to_string())),
+            ..self
+        }
+    }
+
+    async fn session(&self, message: &str, to: &str) -> Result<(), String> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await
+            .map_err(|e| format!("Error connecting to {}:{}: {e}", self.host, self.port))?;
+        let mut smtp = Smtp { stream: BufReader::new(stream) };
+
+        smtp.reply("greeting", 220).await?;
+        smtp.command("EHLO localhost", 250).await?;
+        if let Some((username, password)) = &self.credentials {
+            let plain = STANDARD.encode(format!("\0{username}\0{password}"));
+            smtp.command(&format!("AUTH PLAIN {plain}"), 235).await?;
+        }
+        smtp.command(&format!("MAIL FROM:<{}>", address(&self.from)), 250).await?;
+        smtp.command(&format!("RCPT TO:<{}>", address(to)), 250).await?;
+        smtp.command("DATA", 354).await?;
+        // Lines starting with a dot get another one, a lone dot ends the data
+        let data: Vec<String> = message.split("\r\n")
+            .map(|line| match line.starts_with('.') {
+                true => format!(".{line}"),
+                false => line.to_string(),
+            })
+            .collect();
+        smtp.write(&format!("{}.", data.join("\r\n"))).await?;
+        smtp.reply("the message", 250).await?;
+        smtp.command("QUIT", 221).await
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), String> {
+        let message = email.message(&self.from)?;
+        tokio::time::timeout(self.timeout, self.session(&message, &email.to)).await
+            .map_err(|_| format!("Timed out sending email via {}:{}", self.host, self.port))?
+    }
+}
+
+/// 'Hotels <noreply@hotels.test>' is 'noreply@hotels.test'.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+struct Smtp {
+    stream: BufReader<TcpStream>,
+}
+
+impl Smtp {
+    /// Sends 'line' and reads the reply, which must have the code 'expected'.
+    async fn command(&mut self, line: &str, expected: u16) -> Result<(), String> {
+        self.write(line).await?;
+        self.reply(line.split(' ').next().unwrap_or_default(), expected).await
+    }
+
+    async fn write(&mut self, line: &str) -> Result<(), String> {
+        self.stream.get_mut().write_all(format!("{line}\r\n").as_bytes()).await
+            .map_err(|e| format!("Error talking to the SMTP server: {e}"))
+    }
+
+    /// Reads a reply, of one or more 'XYZ-' lines and a final 'XYZ ' one.
+    async fn reply(&mut self, after: &str, expected: u16) -> Result<(), String> {
+        loop {
+            let mut line = String::new();
+            let read = self.stream.read_line(&mut line).await
+                .map_err(|e| format!("Error talking to the SMTP server: {e}"))?;
+            if read == 0 {
+                return Err("The SMTP server closed the connection".to_string());
+            }
+            let line = line.trim_end();
+            let code: Option<u16> = line.get(..3).and_then(|code| code.parse().ok());
+            if code != Some(expected) {
+                return Err(format!("The SMTP server replied '{line}' to {after}"));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/**
+ * The emails the routes send, and how.
+ *
+ * # Fields:
+ * - 'link_url': Where links in emails point, the frontend's pages read the
+ *   token from the query string and confirm it with the API.
+ */
+#[derive(Clone)]
+pub struct Mail {
+    pub mailer: Arc<dyn Mailer>,
+    pub link_url: String,
+}
+
+pub const DEFAULT_FROM: &str = "Hotels <noreply@localhost>";
+
+pub const DEFAULT_LINK_URL: &str = "http://localhost:5173";
+
+impl Default for Mail {
+    /// Printed to stdout.
+    fn default() -> Self {
+        Mail {
+            mailer: Arc::new(FileMailer::stdout(DEFAULT_FROM)),
+            link_url: DEFAULT_LINK_URL.to_string(),
+        }
+    }
+}
+
+impl Mail {
+    fn link(&self, page: &str, token: &str) -> String {
+        format!("{}/{page}?token={token}", self.link_url.trim_end_matches('/'))
+    }
+
+    pub fn verification(&self, to: &str, username: &str, token: &str, ttl: chrono::Duration
+    ) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Hi {username},\n\nConfirm this is your email address by opening \
+                this link within {} hour(s):\n\n{}\n\nIf you didn't sign up, you \
+                can ignore this email.\n",
+                ttl.num_hours().max(1), self.link("verify_email", token)
+            ),
+        }
+    }
+
+    pub fn password_reset(&self, to: &str, username: &str, token: &str, ttl: chrono::Duration
+    ) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi {username},\n\nSet a new password by opening this link within \
+                {} minute(s):\n\n{}\n\nThe link works once. If you didn't ask for \
+                it, you can ignore this email and your password stays the same.\n",
+                ttl.num_minutes().max(1), self.link("reset_password", token)
+            ),
+        }
+    }
+}
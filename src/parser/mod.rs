@@ -11,14 +11,14 @@ use recursive::*;
 use utils::*;
 
 pub fn mt_processing(
-    r_dir_path: &String, h_dir_path: &String
+    r_dir_path: &str, h_dir_path: &str
 ) -> (HashMap<i32, Hotel>, HashMap<i32, Vec<Review>>) {
     // HotelsInfo Struct
     let info: Arc<Mutex<HotelsInfo>> = 
         Arc::new(Mutex::new(HotelsInfo::new()));
 
     // Reviews Files Parsing
-    let rev_dir_path = r_dir_path.clone();
+    let rev_dir_path = r_dir_path.to_string();
     let hotels_info = info.clone();
     let handle = thread::spawn(move || {
         mt_traverse_dir(rev_dir_path, hotels_info, Data::Reviews)
@@ -26,7 +26,7 @@ pub fn mt_processing(
     handle.join().unwrap();
 
     // Hotels Files Parsing
-    let hot_dir_path = h_dir_path.clone();
+    let hot_dir_path = h_dir_path.to_string();
     let hotels_info = info.clone();
     let handle = thread::spawn(move || {
         mt_traverse_dir(hot_dir_path, hotels_info, Data::Hotels)
@@ -36,18 +36,18 @@ pub fn mt_processing(
     let hotels = info.lock().unwrap().get_hotels();
     let reviews = info.lock().unwrap().get_reviews();
 
-    return (hotels, reviews);
+    (hotels, reviews)
 }
 
-pub fn r_processing(r_dir_path: &String, h_dir_path: &String) {
+pub fn r_processing(r_dir_path: &str, h_dir_path: &str) {
     let mut info = HotelsInfo::new();
 
     r_traverse_dir(
-        r_dir_path.clone(), &mut info, &Data::Reviews
+        r_dir_path.to_string(), &mut info, &Data::Reviews
     );
 
     r_traverse_dir(
-        h_dir_path.clone(), &mut info, &Data::Hotels
+        h_dir_path.to_string(), &mut info, &Data::Hotels
     );
 
     println!("\nRecursive Approach: {:#?}", 
@@ -51,8 +51,8 @@ pub fn mt_traverse_dir(
  * # Parameters:
  * - 'file_path': A string containing the file path to be processed.
  * - 'reviews_set': Hashmap to be populated with hotel ids (keys) and their
- *      corresponding reviews (values). Hashmap held in Mutex to prevent
- *      race conditions, and Arc used to allow multiple thread-safe references.
+ *   corresponding reviews (values). Hashmap held in Mutex to prevent
+ *   race conditions, and Arc used to allow multiple thread-safe references.
  */
 pub fn mt_process_reviews(file_path: String, hotels_info: Arc<Mutex<HotelsInfo>>) {
     let file = fs::File::open(file_path).unwrap();
@@ -102,8 +102,8 @@ pub fn mt_process_reviews(file_path: String, hotels_info: Arc<Mutex<HotelsInfo>>
  * # Parameters:
  * - 'file_path': A string containing the file path to be processed.
  * - 'hotels_set': Hashmap to be populated with hotel ids (key) and their
- *      corresponding hotel (value). Hashmap held in Mutex to prevent
- *      race conditions, and Arc used to allow multiple thread-safe references.
+ *   corresponding hotel (value). Hashmap held in Mutex to prevent
+ *   race conditions, and Arc used to allow multiple thread-safe references.
  */
 pub fn mt_process_hotels(file_path: String, hotels_info: Arc<Mutex<HotelsInfo>>) {
     let file = fs::File::open(file_path).unwrap();
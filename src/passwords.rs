@@ -0,0 +1,76 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString
+};
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+/**
+ * Hashes and verifies passwords with Argon2id.
+ *
+ * Hashes are PHC strings ('$argon2id$v=19$m=...,t=...,p=...$salt$hash'),
+ * they carry their own salt and parameters, so changing the parameters only
+ * affects new hashes and old ones keep verifying.
+ */
+#[derive(Debug, Clone)]
+pub struct Passwords {
+    params: Params,
+}
+
+impl Default for Passwords {
+    /// The argon2 crate's defaults: 19 MiB of memory, 2 passes, 1 lane.
+    fn default() -> Self {
+        Passwords { params: Params::default() }
+    }
+}
+
+impl Passwords {
+    /**
+     * # Parameters:
+     * - 'memory_kib': Memory used per hash, in KiB.
+     * - 'iterations': Passes over the memory.
+     * - 'parallelism': Lanes, hashed in parallel.
+     */
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32
+    ) -> Result<Passwords, String> {
+        let params = Params::new(memory_kib, iterations, parallelism, None)
+            .map_err(|e| format!("Invalid argon2 parameters: {e}"))?;
+        Ok(Passwords { params })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hashes 'password' with a fresh random salt.
+    pub fn hash(&self, password: &str) -> Result<String, String> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2().hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| format!("Error hashing password: {e}"))
+    }
+
+    /// Whether 'password' matches 'hash', false for malformed hashes too.
+    pub fn verify(&self, password: &str, hash: &str) -> bool {
+        match PasswordHash::new(hash) {
+            Ok(hash) => self.argon2()
+                .verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(e) => {
+                eprintln!("Malformed password hash: {e}");
+                false
+            },
+        }
+    }
+}
+
+/// Checks a new password's length, in characters.
+pub fn check_password(password: &str) -> Result<(), String> {
+    match password.chars().count() {
+        n if n < MIN_PASSWORD_LEN => Err(format!(
+            "Passwords need at least {MIN_PASSWORD_LEN} characters")),
+        n if n > MAX_PASSWORD_LEN => Err(format!(
+            "Passwords can't be longer than {MAX_PASSWORD_LEN} characters")),
+        _ => Ok(()),
+    }
+}
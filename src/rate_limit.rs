@@ -0,0 +1,153 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::{DbResult, InMemoryRateLimits, Policy, RateLimitRepository, Take};
+
+/// How often full buckets are deleted from the store.
+const PRUNE_EVERY_SECS: i64 = 60;
+
+/// Routes sharing a rate limit, each client has a bucket per group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitGroup {
+    /// Logging in and checking users, which can be used to guess passwords
+    /// or find out who has an account.
+    Auth,
+    /// Routes that add, change or delete records.
+    Writes,
+}
+
+impl LimitGroup {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitGroup::Auth => "auth",
+            LimitGroup::Writes => "writes",
+        }
+    }
+}
+
+/// What a group's buckets are keyed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKey {
+    Ip,
+    /// The user id of a valid access token, the IP for requests without one.
+    User,
+}
+
+impl FromStr for LimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(LimitKey::Ip),
+            "user" => Ok(LimitKey::User),
+            _ => Err(format!("Unknown rate limit key '{s}', expected ip or user")),
+        }
+    }
+}
+
+/// The client of a request, as far as rate limits are concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Client {
+    pub ip: String,
+    pub user_id: Option<usize>,
+}
+
+impl Client {
+    fn key(&self, group: LimitGroup, key: LimitKey) -> String {
+        match (key, self.user_id) {
+            (LimitKey::User, Some(user_id)) => format!("{}:user:{user_id}", group.as_str()),
+            _ => format!("{}:ip:{}", group.as_str(), self.ip),
+        }
+    }
+}
+
+/**
+ * The outcome of a rate limited request, for its 'X-RateLimit-*' headers.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub policy: Policy,
+    pub take: Take,
+}
+
+impl Limit {
+    pub fn remaining(&self) -> u32 {
+        self.take.tokens.floor() as u32
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Too many requests, retry in {} second(s)",
+            self.policy.retry_after(self.take.tokens))
+    }
+}
+
+/**
+ * Token bucket rate limits per route group.
+ *
+ * # Fields:
+ * - 'store': Where the buckets are kept, in memory by default. A database
+ *   store shares them between servers and keeps them across restarts.
+ * - 'groups': The policy of each limited group, groups without one aren't
+ *   limited.
+ */
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitRepository>,
+    groups: Vec<(LimitGroup, Policy, LimitKey)>,
+    pruned_at: Mutex<DateTime<Utc>>,
+}
+
+impl Default for RateLimiter {
+    /// No limits, in memory.
+    fn default() -> Self {
+        RateLimiter::new(Arc::new(InMemoryRateLimits::new()))
+    }
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitRepository>) -> RateLimiter {
+        RateLimiter { store, groups: Vec::new(), pruned_at: Mutex::new(Utc::now()) }
+    }
+
+    /// Limits 'group' to 'policy', replacing its previous one.
+    pub fn with_policy(mut self, group: LimitGroup, policy: Policy, key: LimitKey
+    ) -> RateLimiter {
+        self.groups.retain(|(g, _, _)| *g != group);
+        self.groups.push((group, policy, key));
+        self
+    }
+
+    /**
+     * Takes a token from the client's bucket of 'group'.
+     *
+     * # Returns:
+     * - 'None' if 'group' isn't limited.
+     */
+    pub async fn check(&self, group: LimitGroup, client: &Client
+    ) -> DbResult<Option<Limit>> {
+        let Some(&(_, policy, key)) = self.groups.iter().find(|(g, _, _)| *g == group) else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        self.prune(now).await;
+        let take = self.store.take_token(&client.key(group, key), policy, now).await?;
+        Ok(Some(Limit { policy, take }))
+    }
+
+    /// Deletes full buckets, at most once every 'PRUNE_EVERY_SECS'.
+    async fn prune(&self, now: DateTime<Utc>) {
+        {
+            let mut pruned_at = self.pruned_at.lock().unwrap();
+            if now - *pruned_at < Duration::seconds(PRUNE_EVERY_SECS) {
+                return;
+            }
+            *pruned_at = now;
+        }
+        if let Err(e) = self.store.prune_buckets(now).await {
+            eprintln!("Error pruning rate limit buckets: {e}");
+        }
+    }
+}
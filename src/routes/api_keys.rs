@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+
+use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{Admins, Authorized};
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+use crate::tokens::{hash_api_key_secret, new_api_key};
+
+/**
+ * # Fields:
+ * - 'expires_at': When the key stops working, never if it's missing.
+ */
+#[derive(Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A new key and its row, the only time the key is shown.
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+#[post("/api_keys", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn add_api_key(
+    auth: Authorized<Admins>, request: web::Json<ApiKeyRequest>,
+    app_state: web::Data<AppState>
+) -> HttpResponse {
+    let ApiKeyRequest { name, mut scopes, expires_at } = request.into_inner();
+    let now = Utc::now();
+    if name.trim().is_empty() || name.chars().count() > 100 {
+        return HttpResponse::BadRequest().body("The name must have 1 to 100 characters");
+    }
+    if scopes.is_empty() {
+        return HttpResponse::BadRequest().body("An API key needs at least one scope");
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return HttpResponse::BadRequest().body("The expiry must be in the future");
+    }
+    scopes.sort_by_key(|scope| scope.as_str());
+    scopes.dedup();
+
+    let (prefix, secret) = new_api_key();
+    let added = app_state.api_keys.add_api_key(&NewApiKey {
+        name, prefix: prefix.clone(), secret_hash: hash_api_key_secret(&secret),
+        scopes: Scopes(scopes), created_by: auth.token.id as i32,
+        created_at: now, expires_at,
+    }).await;
+
+    let api_key = match added {
+        Ok(_) => app_state.api_keys.get_api_key(&prefix).await,
+        Err(e) => Err(e),
+    };
+    match api_key {
+        Ok(Some(api_key)) => HttpResponse::Ok().json(CreatedApiKey {
+            key: format!("hk_{prefix}_{secret}"), api_key,
+        }),
+        Ok(None) => {
+            eprintln!("Error adding API key: the new key '{prefix}' wasn't found");
+            HttpResponse::InternalServerError().into()
+        },
+        Err(e) => {
+            eprintln!("Error adding API key: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+/// All keys, revoked and expired ones too, without their secrets.
+#[get("/api_keys")]
+pub async fn get_all_api_keys(_: Authorized<Admins>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    match app_state.api_keys.get_all_api_keys().await {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(e) => {
+            eprintln!("Error getting all API keys: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
+
+/// Keys are revoked rather than deleted, so they stay listed with their
+/// last use.
+#[post("/api_keys/{key_id}/revoke", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn revoke_api_key(
+    _: Authorized<Admins>, path: web::Path<i32>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let key_id = path.into_inner();
+
+    match app_state.api_keys.revoke_api_key(key_id, Utc::now()).await {
+        Ok(true) => HttpResponse::Ok().into(),
+        Ok(false) => HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error revoking API key: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
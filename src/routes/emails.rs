@@ -0,0 +1,238 @@
+use chrono::Utc;
+
+use crate::routes::utils::*;
+use crate::routes::jwt_extractor::AuthorizationToken;
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+use crate::jwt::JwtKeys;
+use crate::tokens::new_email_token_id;
+
+/**
+ * The JWT in an emailed link. Its 'aud' is the access tokens' audience and
+ * the purpose, e.g. 'hotels:reset_password', so neither kind of token is
+ * accepted as the other.
+ *
+ * # Fields:
+ * - 'email': Where the link was sent, verifying it doesn't verify another
+ *   email the user changed to since.
+ * - 'jti': The id of the token's 'email_tokens' row.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailClaims {
+    pub user_id: usize,
+    pub email: String,
+    pub jti: String,
+    pub exp: usize,
+    pub iss: String,
+    pub aud: String,
+}
+
+fn email_audience(keys: &JwtKeys, purpose: EmailPurpose) -> String {
+    format!("{}:{purpose}", keys.audience())
+}
+
+/// Stores a new token for 'purpose' and emails 'user' a link with it.
+pub(crate) async fn send_email_token(
+    app_state: &AppState, user: &User, purpose: EmailPurpose
+) -> Result<(), String> {
+    let now = Utc::now();
+    let ttl = match purpose {
+        EmailPurpose::VerifyEmail => app_state.tokens.email_verification,
+        EmailPurpose::ResetPassword => app_state.tokens.password_reset,
+    };
+    let token_id = new_email_token_id();
+    app_state.email_tokens.add_email_token(&NewEmailToken {
+        user_id: user.id, purpose, token_id: token_id.clone(),
+        created_at: now, expires_at: now + ttl,
+    }).await.map_err(|e| e.to_string())?;
+
+    let token = app_state.keys.encode(&EmailClaims {
+        user_id: user.id as usize,
+        email: user.email.clone(),
+        jti: token_id,
+        exp: (now + ttl).timestamp() as usize,
+        iss: app_state.keys.issuer().to_string(),
+        aud: email_audience(&app_state.keys, purpose),
+    })?;
+    let email = match purpose {
+        EmailPurpose::VerifyEmail => app_state.mail
+            .verification(&user.email, &user.username, &token, ttl),
+        EmailPurpose::ResetPassword => app_state.mail
+            .password_reset(&user.email, &user.username, &token, ttl),
+    };
+    app_state.mail.mailer.send(&email).await
+}
+
+/**
+ * Checks an emailed token and uses it up.
+ *
+ * # Returns:
+ * - 'None' if the token is invalid, expired, for another purpose or used
+ *   already.
+ */
+async fn use_email_token(app_state: &AppState, token: &str, purpose: EmailPurpose
+) -> DbResult<Option<EmailClaims>> {
+    let audience = email_audience(&app_state.keys, purpose);
+    let Ok(claims) = app_state.keys.decode_for::<EmailClaims>(token, &audience) else {
+        return Ok(None);
+    };
+    let user_id = app_state.email_tokens
+        .use_email_token(&claims.jti, purpose, Utc::now()).await?;
+    Ok(Some(claims).filter(|claims| user_id == Some(claims.user_id as i32)))
+}
+
+#[post("/verify_email/request", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn request_email_verification(
+    auth: AuthorizationToken, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let account = match app_state.users.get_account(auth.id as i32).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+    if account.email_verified_at.is_some() {
+        return HttpResponse::Conflict().body("This email address is verified already");
+    }
+
+    let user = User {
+        id: account.id, username: account.username, email: account.email,
+        role: account.role,
+    };
+    match send_email_token(&app_state, &user, EmailPurpose::VerifyEmail).await {
+        Ok(_) => HttpResponse::Ok().into(),
+        Err(e) => {
+            eprintln!("Error sending verification email: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EmailToken {
+    pub token: String,
+}
+
+#[post("/verify_email/confirm", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn confirm_email_verification(
+    request: web::Json<EmailToken>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let claims = match use_email_token(
+        &app_state, &request.token, EmailPurpose::VerifyEmail
+    ).await {
+        Ok(Some(claims)) => claims,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired token"),
+        Err(e) => {
+            eprintln!("Error using email token: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+
+    let verified = app_state.users
+        .set_email_verified(claims.user_id as i32, &claims.email, Utc::now()).await;
+
+    match verified {
+        Ok(true) => HttpResponse::Ok().into(),
+        Ok(false) => HttpResponse::Conflict()
+            .body("The email address changed since the link was sent"),
+        Err(e) => {
+            eprintln!("Error verifying email: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+/// Answers the same whether or not the email belongs to a user, so it can't
+/// be used to find out who has an account.
+#[post("/password_reset/request", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn request_password_reset(
+    request: web::Json<PasswordResetRequest>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let email = request.into_inner().email;
+
+    let user = match app_state.users.get_user_by_creds(&email, &email).await {
+        Ok(user) => user.filter(|u| u.email == email),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+
+    // Sent in the background, the response time doesn't tell either
+    if let Some(user) = user {
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send_email_token(
+                &app_state, &user, EmailPurpose::ResetPassword
+            ).await {
+                eprintln!("Error sending password reset email: {e}");
+            }
+        });
+    }
+    HttpResponse::Ok().into()
+}
+
+#[derive(Deserialize)]
+pub struct PasswordReset {
+    pub token: String,
+    pub new_password: String,
+}
+
+/**
+ * Sets a new password with an emailed token, and logs the user out
+ * everywhere: whoever reset it may not be the only one who knew the old one.
+ * It also unlocks the account, a lockout shouldn't outlast the old password.
+ */
+#[post("/password_reset/confirm", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn confirm_password_reset(
+    reset: web::Json<PasswordReset>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let PasswordReset { token, new_password } = reset.into_inner();
+    // Checked first, so a weak password doesn't use up the token
+    if let Err(e) = check_password(&new_password) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let claims = match use_email_token(&app_state, &token, EmailPurpose::ResetPassword).await {
+        Ok(Some(claims)) => claims,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired token"),
+        Err(e) => {
+            eprintln!("Error using email token: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+    let user_id = claims.user_id as i32;
+
+    let reset = match hash_password(&app_state.passwords, new_password).await {
+        Ok(hash) => app_state.users.set_password_hash(user_id, &hash).await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    match reset {
+        Ok(true) => {},
+        Ok(false) => return HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error resetting password: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    }
+
+    let now = Utc::now();
+    if let Err(e) = app_state.sessions.revoke_user_tokens(user_id, now).await {
+        eprintln!("Error revoking sessions after a password reset: {e}");
+    }
+    if let Err(e) = app_state.logins.clear_login_failures(user_id).await {
+        eprintln!("Error unlocking the account after a password reset: {e}");
+    }
+    // Following the link proved the inbox is the user's, as verifying does
+    if let Err(e) = app_state.users.set_email_verified(user_id, &claims.email, now).await {
+        eprintln!("Error verifying email: {e}");
+    }
+    HttpResponse::Ok().into()
+}
@@ -1,17 +1,17 @@
 use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{ReadHotels, Reader};
 
 #[get("/get_hotel/{hotel_id}")]
-pub async fn get_hotel(path: web::Path<usize>, app_state: web::Data<AppState>
+pub async fn get_hotel(
+    _: Reader<ReadHotels>, path: web::Path<usize>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let hotel_id: usize = path.into_inner();
 
-    let hotel: Result<Option<Hotel>> = sqlx::query_as(
-        SELECT_HOTEL
-    ).bind(hotel_id as u64)
-    .fetch_optional(&app_state.pool).await;
+    let hotel = app_state.hotels.get_hotel(hotel_id as i32).await;
 
     match hotel {
-        Ok(h) => HttpResponse::Ok().json(h.unwrap()),
+        Ok(Some(h)) => HttpResponse::Ok().json(h),
+        Ok(None) => HttpResponse::NotFound().into(),
         Err(e) => {
             eprintln!("Error getting hotel by id: {e}"); 
             HttpResponse::BadRequest().into()
@@ -20,10 +20,9 @@ pub async fn get_hotel(path: web::Path<usize>, app_state: web::Data<AppState>
 }
 
 #[get("/get_all_hotels")]
-pub async fn get_all_hotels(app_state: web::Data<AppState>) -> HttpResponse {
-    let hotels: Result<Vec<Hotel>> = sqlx::query_as(
-        SELECT_ALL_HOTELS
-    ).fetch_all(&app_state.pool).await;
+pub async fn get_all_hotels(_: Reader<ReadHotels>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let hotels = app_state.hotels.get_all_hotels().await;
 
     match hotels {
         Ok(hs) => HttpResponse::Ok().json(hs),
@@ -36,15 +35,11 @@ pub async fn get_all_hotels(app_state: web::Data<AppState>) -> HttpResponse {
 
 #[get("/get_like_hotels/{hotel_name}")]
 pub async fn get_like_hotels(
-    path: web::Path<String>, app_state: web::Data<AppState>
+    _: Reader<ReadHotels>, path: web::Path<String>, app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let mut hotel_name =  path.into_inner();
-    hotel_name = "%".to_string() + &hotel_name + "%";
+    let hotel_name =  path.into_inner();
 
-    let hotels: Result<Vec<Hotel>> = sqlx::query_as(
-        SELECT_LIKE_HOTELS
-    ).bind(hotel_name)
-    .fetch_all(&app_state.pool).await;
+    let hotels = app_state.hotels.get_like_hotels(&hotel_name).await;
 
     match hotels {
         Ok(hs) => HttpResponse::Ok().json(hs),
@@ -53,4 +48,32 @@ pub async fn get_like_hotels(
             HttpResponse::BadRequest().into()
         }
     }
-}
\ No newline at end of file
+}
+
+/// A hotel's rating summary along with the average it gives.
+#[derive(Serialize)]
+pub struct HotelRating {
+    #[serde(flatten)]
+    pub summary: RatingSummary,
+    pub average: Option<f64>,
+}
+
+#[get("/get_hotel_rating/{hotel_id}")]
+pub async fn get_hotel_rating(
+    _: Reader<ReadHotels>, path: web::Path<usize>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let hotel_id: usize = path.into_inner();
+
+    let summary = app_state.ratings.get_rating_summary(hotel_id as i32).await;
+
+    match summary {
+        Ok(Some(summary)) => HttpResponse::Ok().json(HotelRating {
+            average: summary.average(), summary
+        }),
+        Ok(None) => HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error getting hotel rating: {e}");
+            HttpResponse::BadRequest().into()
+        }
+    }
+}
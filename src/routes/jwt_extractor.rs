@@ -1,17 +1,39 @@
-use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Serialize, Deserialize};
+use std::fmt;
 use std::future::{Ready, ready};
+use std::marker::PhantomData;
 use actix_web::{
-    FromRequest, web, error::ErrorUnauthorized, HttpRequest, 
-    dev::Payload, http::header::AUTHORIZATION
+    FromRequest, web, error::{ErrorInternalServerError, ErrorUnauthorized},
+    HttpRequest, HttpResponse, ResponseError, dev::Payload,
+    http::{StatusCode, header::AUTHORIZATION}
 };
+use chrono::Utc;
+use futures::future::LocalBoxFuture;
 
 use crate::routes::utils::Claims;
-use crate::database::AppState;
+use crate::database::{ApiKey, AppState, Role, Scope, Scopes};
+use crate::tokens::hash_api_key_secret;
+
+/// The header machine clients send their API key in.
+pub const API_KEY_HEADER: &str = "x-api-key";
 
 #[derive(Serialize, Deserialize)]
 pub struct AuthorizationToken {
     pub id: usize,
+    pub role: Role,
+}
+
+impl AuthorizationToken {
+    /**
+     * Checks that the caller may change a record of user 'owner_id': their
+     * own records, or anyone's with one of 'G::ROLES'.
+     */
+    pub fn check_owner<G: RoleGuard>(&self, owner_id: i32) -> Result<(), AccessDenied> {
+        match self.id == owner_id as usize || G::ROLES.contains(&self.role) {
+            true => Ok(()),
+            false => Err(AccessDenied::not_owner(self.role, G::ROLES)),
+        }
+    }
 }
 
 impl FromRequest for AuthorizationToken {
@@ -28,27 +50,344 @@ impl FromRequest for AuthorizationToken {
             None => "".to_string(),
         };
 
+        if auth_token.is_empty() && req.headers().contains_key(API_KEY_HEADER) {
+            return ready(Err(ErrorUnauthorized(
+                "Unauthorized: This route needs a user's token, not an API key!"
+            )));
+        }
         if auth_token.is_empty() {
             return ready(Err(ErrorUnauthorized("Unauthorized: No token found!")));
         }
 
-        let secret = req.app_data::<web::Data<AppState>>().unwrap()
-            .secret.to_string();
+        let keys = &req.app_data::<web::Data<AppState>>().unwrap().keys;
 
-        let decoded_token = decode::<Claims>(
-            &auth_token, &DecodingKey::from_secret(secret.as_ref()),
-            &Validation::default()
-        );
+        let decoded_token = keys.decode::<Claims>(&auth_token);
 
         match decoded_token {
-            Ok(token) => {
-                println!("Authorized!: {:#?}", token.claims);
-                ready(Ok(AuthorizationToken {id: token.claims.user_id}))
+            Ok(claims) => ready(Ok(AuthorizationToken { id: claims.user_id, role: claims.role })),
+            Err(_) => ready(Err(ErrorUnauthorized("Unauthorized: Invalid token!"))),
+        }
+    }
+}
+
+/// The roles a route accepts, checked by 'Authorized'.
+pub trait RoleGuard {
+    const ROLES: &'static [Role];
+}
+
+/// Moderators and admins.
+pub struct Moderators;
+
+impl RoleGuard for Moderators {
+    const ROLES: &'static [Role] = &[Role::Moderator, Role::Admin];
+}
+
+pub struct Admins;
+
+impl RoleGuard for Admins {
+    const ROLES: &'static [Role] = &[Role::Admin];
+}
+
+/**
+ * An 'AuthorizationToken' whose role is one of 'G::ROLES'.
+ *
+ * Routes declare the roles they need by taking it as an argument, e.g.
+ * 'auth: Authorized<Admins>'. A missing or invalid token is a 401, a token
+ * with another role an 'AccessDenied' 403.
+ */
+pub struct Authorized<G: RoleGuard> {
+    pub token: AuthorizationToken,
+    guard: PhantomData<G>,
+}
+
+impl<G: RoleGuard> FromRequest for Authorized<G> {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let token = match AuthorizationToken::from_request(req, payload).into_inner() {
+            Ok(token) => token,
+            Err(e) => return ready(Err(e)),
+        };
+
+        match G::ROLES.contains(&token.role) {
+            true => ready(Ok(Authorized { token, guard: PhantomData })),
+            false => ready(Err(AccessDenied::new(token.role, G::ROLES).into())),
+        }
+    }
+}
+
+/// The scope a route needs from API keys, checked by 'Permitted'.
+pub trait ScopeGuard {
+    const SCOPE: Scope;
+}
+
+pub struct ReadHotels;
+
+impl ScopeGuard for ReadHotels {
+    const SCOPE: Scope = Scope::HotelsRead;
+}
+
+pub struct ReadReviews;
+
+impl ScopeGuard for ReadReviews {
+    const SCOPE: Scope = Scope::ReviewsRead;
+}
+
+pub struct ReadUsers;
+
+impl ScopeGuard for ReadUsers {
+    const SCOPE: Scope = Scope::UsersRead;
+}
+
+pub struct WriteUsers;
+
+impl ScopeGuard for WriteUsers {
+    const SCOPE: Scope = Scope::UsersWrite;
+}
+
+pub struct WriteReviews;
+
+impl ScopeGuard for WriteReviews {
+    const SCOPE: Scope = Scope::ReviewsWrite;
+}
+
+/**
+ * Whoever calls a route that machine clients may call too: a user with an
+ * access token in AUTHORIZATION, or an API key in 'X-Api-Key'. The API key
+ * wins if a request has both.
+ *
+ * API keys act as the admin who created them where a route needs a user,
+ * e.g. adding a review, see 'Caller::author'. Other routes that act as a
+ * user, e.g. changing a password, keep taking an 'AuthorizationToken'.
+ */
+pub enum Caller {
+    User(AuthorizationToken),
+    ApiKey(ApiKey),
+}
+
+impl Caller {
+    /// Checks that the caller has one of 'G::ROLES', or 'S::SCOPE' for keys.
+    pub fn check<G: RoleGuard, S: ScopeGuard>(&self) -> Result<(), AccessDenied> {
+        match self {
+            Caller::User(token) if G::ROLES.contains(&token.role) => Ok(()),
+            Caller::User(token) => Err(AccessDenied::new(token.role, G::ROLES)),
+            Caller::ApiKey(key) if key.scopes.contains(S::SCOPE) => Ok(()),
+            Caller::ApiKey(key) => Err(AccessDenied::missing_scope(&key.scopes, S::SCOPE)),
+        }
+    }
+
+    /// Like 'AuthorizationToken::check_owner', API keys own no records and
+    /// need 'S::SCOPE' for any.
+    pub fn check_owner<G: RoleGuard, S: ScopeGuard>(&self, owner_id: i32
+    ) -> Result<(), AccessDenied> {
+        match self {
+            Caller::User(token) => token.check_owner::<G>(owner_id),
+            Caller::ApiKey(_) => self.check::<G, S>(),
+        }
+    }
+
+    /**
+     * The user that records made by the caller belong to: the user of a
+     * token, or the creator of a key with 'S::SCOPE'.
+     */
+    pub fn author<S: ScopeGuard>(&self) -> Result<i32, AccessDenied> {
+        match self {
+            Caller::User(token) => Ok(token.id as i32),
+            Caller::ApiKey(key) if !key.scopes.contains(S::SCOPE) => {
+                Err(AccessDenied::missing_scope(&key.scopes, S::SCOPE))
             },
-            Err(e) => {
-                println!("Unauthorized: {e}");
-                ready(Err(ErrorUnauthorized("Unauthorized: Invalid token!")))
+            Caller::ApiKey(key) => key.created_by
+                .ok_or_else(|| AccessDenied::ownerless_key(&key.scopes, S::SCOPE)),
+        }
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Some(api_key) = req.headers().get(API_KEY_HEADER) else {
+            let token = AuthorizationToken::from_request(req, payload).into_inner();
+            return Box::pin(ready(token.map(Caller::User)));
+        };
+        let api_key = api_key.to_str().unwrap_or("").to_string();
+        let app_state = req.app_data::<web::Data<AppState>>().unwrap().clone();
+
+        Box::pin(async move {
+            authenticate_api_key(&app_state, &api_key).await.map(Caller::ApiKey)
+        })
+    }
+}
+
+/**
+ * Finds the key 'hk_<prefix>_<secret>' and checks it's active and its secret
+ * matches.
+ *
+ * Also records the use, a failure to do so only gets logged.
+ */
+async fn authenticate_api_key(app_state: &AppState, api_key: &str
+) -> Result<ApiKey, actix_web::Error> {
+    let invalid = || ErrorUnauthorized("Unauthorized: Invalid API key!");
+    let Some((prefix, secret)) = api_key.strip_prefix("hk_")
+        .and_then(|key| key.split_once('_')) else {
+        return Err(invalid());
+    };
+
+    let key = match app_state.api_keys.get_api_key(prefix).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return Err(invalid()),
+        Err(e) => {
+            eprintln!("Error getting API key: {e}");
+            return Err(ErrorInternalServerError("Error checking API key"));
+        },
+    };
+    let now = Utc::now();
+    if !key.is_active(now) || hash_api_key_secret(secret) != key.secret_hash {
+        return Err(invalid());
+    }
+
+    if key.needs_touch(now) {
+        if let Err(e) = app_state.api_keys.touch_api_key(key.id, now).await {
+            eprintln!("Error recording use of API key '{prefix}': {e}");
+        }
+    }
+    Ok(key)
+}
+
+/**
+ * A 'Caller' that's a user with one of 'G::ROLES' or an API key with
+ * 'S::SCOPE', e.g. '_: Permitted<Admins, ReadUsers>'. Errors like
+ * 'Authorized'.
+ */
+pub struct Permitted<G: RoleGuard, S: ScopeGuard> {
+    pub caller: Caller,
+    guard: PhantomData<(G, S)>,
+}
+
+impl<G: RoleGuard + 'static, S: ScopeGuard + 'static> FromRequest for Permitted<G, S> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let caller = Caller::from_request(req, payload);
+
+        Box::pin(async move {
+            let caller = caller.await?;
+            caller.check::<G, S>()?;
+            Ok(Permitted { caller, guard: PhantomData })
+        })
+    }
+}
+
+/**
+ * Anyone calling a route that reads public data: users don't need a token,
+ * but an API key in 'X-Api-Key' must be valid and have 'S::SCOPE', e.g.
+ * '_: Reader<ReadHotels>'.
+ */
+pub struct Reader<S: ScopeGuard> {
+    pub api_key: Option<ApiKey>,
+    guard: PhantomData<S>,
+}
+
+impl<S: ScopeGuard + 'static> FromRequest for Reader<S> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(api_key) = req.headers().get(API_KEY_HEADER) else {
+            return Box::pin(ready(Ok(Reader { api_key: None, guard: PhantomData })));
+        };
+        let api_key = api_key.to_str().unwrap_or("").to_string();
+        let app_state = req.app_data::<web::Data<AppState>>().unwrap().clone();
+
+        Box::pin(async move {
+            let key = authenticate_api_key(&app_state, &api_key).await?;
+            if !key.scopes.contains(S::SCOPE) {
+                return Err(AccessDenied::missing_scope(&key.scopes, S::SCOPE).into());
             }
+            Ok(Reader { api_key: Some(key), guard: PhantomData })
+        })
+    }
+}
+
+/**
+ * The JSON body of a 403, e.g.
+ * '{"error": "forbidden", "message": "...", "role": "user",
+ * "required_roles": ["admin"]}', or for API keys
+ * '{..., "scopes": ["users:read"], "required_scope": "users:write"}'.
+ */
+#[derive(Debug, Serialize)]
+pub struct AccessDenied {
+    error: &'static str,
+    message: String,
+    #[serde(flatten)]
+    caller: DeniedCaller,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum DeniedCaller {
+    User { role: Role, required_roles: &'static [Role] },
+    ApiKey { scopes: Scopes, required_scope: Scope },
+}
+
+impl AccessDenied {
+    pub fn new(role: Role, required_roles: &'static [Role]) -> AccessDenied {
+        let names: Vec<&str> = required_roles.iter().map(Role::as_str).collect();
+        AccessDenied {
+            error: "forbidden",
+            message: format!("Requires the role {}", names.join(" or ")),
+            caller: DeniedCaller::User { role, required_roles },
+        }
+    }
+
+    /// For records of other users, 'required_roles' may change them anyway.
+    pub fn not_owner(role: Role, required_roles: &'static [Role]) -> AccessDenied {
+        let names: Vec<&str> = required_roles.iter().map(Role::as_str).collect();
+        AccessDenied {
+            message: format!("Belongs to another user, changing it requires \
+                the role {}", names.join(" or ")),
+            ..AccessDenied::new(role, required_roles)
+        }
+    }
+
+    pub fn missing_scope(scopes: &Scopes, required_scope: Scope) -> AccessDenied {
+        AccessDenied {
+            error: "forbidden",
+            message: format!("Requires an API key with the scope {required_scope}"),
+            caller: DeniedCaller::ApiKey { scopes: scopes.clone(), required_scope },
         }
     }
-}
\ No newline at end of file
+
+    /// For keys whose creator was deleted, they can't act as a user.
+    pub fn ownerless_key(scopes: &Scopes, required_scope: Scope) -> AccessDenied {
+        AccessDenied {
+            message: "The API key's creator was deleted, it can't act as a user"
+                .to_string(),
+            ..AccessDenied::missing_scope(scopes, required_scope)
+        }
+    }
+}
+
+impl fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.caller {
+            DeniedCaller::User { role, .. } => write!(f, "{}, not {role}", self.message),
+            DeniedCaller::ApiKey { scopes, .. } => {
+                write!(f, "{}, not '{scopes}'", self.message)
+            },
+        }
+    }
+}
+
+impl ResponseError for AccessDenied {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Forbidden().json(self)
+    }
+}
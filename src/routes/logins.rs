@@ -0,0 +1,206 @@
+use actix_web::{HttpRequest, http::header::{RETRY_AFTER, USER_AGENT}};
+use chrono::{DateTime, Utc};
+
+use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{Admins, Permitted, ReadUsers, WriteUsers};
+use crate::routes::sessions::start_session;
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+
+/// Longer usernames and user agents are cut to fit their columns.
+const MAX_TEXT_CHARS: usize = 255;
+
+fn truncated(text: &str) -> String {
+    text.chars().take(MAX_TEXT_CHARS).collect()
+}
+
+/// Who tried to log in, and from where, for 'login_events'.
+pub(crate) struct LoginAttempt {
+    username: String,
+    ip: String,
+    user_agent: Option<String>,
+}
+
+impl LoginAttempt {
+    pub(crate) fn new(req: &HttpRequest, username: &str) -> LoginAttempt {
+        LoginAttempt {
+            username: truncated(username),
+            ip: req.peer_addr().map_or("unknown".to_string(), |addr| addr.ip().to_string()),
+            user_agent: req.headers().get(USER_AGENT)
+                .and_then(|agent| agent.to_str().ok())
+                .map(truncated),
+        }
+    }
+
+    /// Stores the attempt, 'failure' being 'None' for a successful one. A
+    /// failure to do so only gets logged, it doesn't stop logins.
+    async fn record(
+        &self, app_state: &AppState, user_id: Option<i32>, failure: Option<LoginFailure>
+    ) {
+        let recorded = app_state.logins.add_login_event(&NewLoginEvent {
+            user_id,
+            username: self.username.clone(),
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            failure,
+            created_at: Utc::now(),
+        }).await;
+        if let Err(e) = recorded {
+            eprintln!("Error recording login of '{}': {e}", self.username);
+        }
+    }
+
+    /**
+     * Checks the account isn't locked, before its password or code is.
+     *
+     * # Returns:
+     * - The user's failed logins, 'None' if there are none.
+     * - The response to answer with instead if it's locked.
+     */
+    pub(crate) async fn check_lockout(&self, app_state: &AppState, user_id: i32
+    ) -> Result<Option<LoginLockout>, HttpResponse> {
+        let lockout = match app_state.logins.get_login_lockout(user_id).await {
+            Ok(lockout) => lockout,
+            Err(e) => {
+                eprintln!("Error getting login lockout: {e}");
+                return Err(HttpResponse::InternalServerError().into());
+            },
+        };
+        let now = Utc::now();
+        if let Some(locked_until) = lockout.as_ref()
+            .filter(|l| l.is_locked(now)).and_then(|l| l.locked_until) {
+            self.record(app_state, Some(user_id), Some(LoginFailure::Locked)).await;
+            return Err(account_locked(locked_until, now));
+        }
+        Ok(lockout)
+    }
+
+    /// Records a failure, counting it towards a lockout if it's the user's.
+    pub(crate) async fn fail(
+        &self, app_state: &AppState, user_id: Option<i32>, failure: LoginFailure
+    ) {
+        let counted = matches!(failure, LoginFailure::WrongPassword | LoginFailure::WrongCode);
+        if let Some(user_id) = user_id.filter(|_| counted) {
+            let added = app_state.logins
+                .add_login_failure(user_id, &app_state.lockout, Utc::now()).await;
+            if let Err(e) = added {
+                eprintln!("Error counting failed login: {e}");
+            }
+        }
+        self.record(app_state, user_id, Some(failure)).await;
+    }
+
+    /**
+     * Logs 'user' in: resets their failed logins, records the login and
+     * starts a session.
+     *
+     * # Parameters:
+     * - 'lockout': From 'check_lockout', nothing to reset if 'None'.
+     */
+    pub(crate) async fn succeed(
+        &self, app_state: &AppState, user: User, lockout: Option<LoginLockout>
+    ) -> HttpResponse {
+        if lockout.is_some() {
+            if let Err(e) = app_state.logins.clear_login_failures(user.id).await {
+                eprintln!("Error resetting failed logins: {e}");
+            }
+        }
+        self.record(app_state, Some(user.id), None).await;
+
+        match start_session(app_state, user).await {
+            Ok(tokens) => HttpResponse::Ok().json(tokens),
+            Err(e) => {
+                eprintln!("Error starting session: {e}");
+                HttpResponse::InternalServerError().into()
+            },
+        }
+    }
+}
+
+/**
+ * The JSON body of a 423, e.g.
+ * '{"error": "account_locked", "message": "...", "retry_after": 120}'.
+ */
+#[derive(Debug, Serialize)]
+struct AccountLocked {
+    error: &'static str,
+    message: &'static str,
+    retry_after: i64,
+}
+
+/// The answer to logins while the account is locked.
+fn account_locked(locked_until: DateTime<Utc>, now: DateTime<Utc>) -> HttpResponse {
+    // Rounded up, retrying after it never hits the lock again
+    let retry_after = ((locked_until - now).num_milliseconds() + 999) / 1000;
+    HttpResponse::Locked()
+        .insert_header((RETRY_AFTER, retry_after))
+        .json(AccountLocked {
+            error: "account_locked",
+            message: "Too many failed logins, the account is locked for a while",
+            retry_after,
+        })
+}
+
+#[derive(Deserialize)]
+pub struct LoginEventsQuery {
+    pub limit: Option<i64>,
+}
+
+/**
+ * # Fields:
+ * - 'failed_logins', 'locked_until': Since the user's last successful login,
+ *   0 and 'None' if it was successful.
+ * - 'events': Newest first.
+ */
+#[derive(Serialize)]
+pub struct LoginActivity {
+    pub user_id: i32,
+    pub failed_logins: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub events: Vec<LoginEvent>,
+}
+
+/// The latest logins of a user, 50 by default and at most 500.
+#[get("/login_events/{user_id}")]
+pub async fn get_login_events(
+    _: Permitted<Admins, ReadUsers>, path: web::Path<i32>,
+    query: web::Query<LoginEventsQuery>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let activity = match app_state.logins.get_login_lockout(user_id).await {
+        Ok(lockout) => app_state.logins.get_login_events(user_id, limit).await
+            .map(|events| LoginActivity {
+                user_id,
+                failed_logins: lockout.as_ref().map_or(0, |l| l.failed_logins),
+                locked_until: lockout.and_then(|l| l.locked_until),
+                events,
+            }),
+        Err(e) => Err(e),
+    };
+
+    match activity {
+        Ok(activity) => HttpResponse::Ok().json(activity),
+        Err(e) => {
+            eprintln!("Error getting login events: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
+
+/// Unlocks an account and resets its failed logins.
+#[post("/unlock/{user_id}", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn unlock_user(
+    _: Permitted<Admins, WriteUsers>, path: web::Path<i32>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id = path.into_inner();
+
+    match app_state.logins.clear_login_failures(user_id).await {
+        Ok(_) => HttpResponse::Ok().into(),
+        Err(e) => {
+            eprintln!("Error unlocking user: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
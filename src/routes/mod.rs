@@ -1,19 +1,77 @@
+use actix_web::web;
+
 pub mod hotels;
 pub mod reviews;
 pub mod users;
 pub mod user_reviews;
+pub mod search;
+pub mod sessions;
+pub mod emails;
+pub mod api_keys;
+pub mod logins;
+pub mod totp;
 mod utils;
 mod jwt_extractor;
+mod rate_limit;
 
 pub use hotels::*;
 pub use reviews::*;
 pub use users::*;
 pub use user_reviews::*;
+pub use search::*;
+pub use sessions::*;
+pub use emails::*;
+pub use api_keys::*;
+pub use logins::*;
+pub use totp::*;
+pub use jwt_extractor::*;
+pub use rate_limit::*;
 
 pub async fn root() -> String {
     "Server is up and running".to_string()
 }
 
+/// Registers every route, on the server's 'App' and on those of handler tests.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(root))
+        .service(get_user)
+        .service(check_user)
+        .service(add_user)
+        .service(delete_user)
+        .service(update_user)
+        .service(get_all_users)
+        .service(get_hotel)
+        .service(get_all_hotels)
+        .service(get_like_hotels)
+        .service(get_hotel_rating)
+        .service(get_hotel_reviews)
+        .service(add_user_review)
+        .service(get_all_user_reviews)
+        .service(get_user_reviews_by_username)
+        .service(get_user_reviews_by_hotel_id)
+        .service(delete_user_review)
+        .service(login)
+        .service(change_password)
+        .service(refresh_session)
+        .service(logout)
+        .service(request_email_verification)
+        .service(confirm_email_verification)
+        .service(request_password_reset)
+        .service(confirm_password_reset)
+        .service(add_api_key)
+        .service(get_all_api_keys)
+        .service(revoke_api_key)
+        .service(get_login_events)
+        .service(unlock_user)
+        .service(enroll_totp)
+        .service(confirm_totp_enrollment)
+        .service(disable_totp)
+        .service(login_totp)
+        .service(jwks)
+        .service(set_role)
+        .service(search);
+}
+
 // user: add, update, delete
 // hotels: get, get like
 // reviews: get by hotel
@@ -0,0 +1,139 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use actix_web::{
+    body::EitherBody, dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER},
+    web, Error, HttpResponse
+};
+use futures::future::LocalBoxFuture;
+use serde::Serialize;
+
+use crate::database::AppState;
+use crate::rate_limit::{Client, Limit, LimitGroup};
+use crate::routes::utils::Claims;
+
+/**
+ * Middleware limiting a route to the policy of its group in
+ * 'AppState::rate_limits', e.g.
+ * '#[post("/login", wrap = "RateLimit(LimitGroup::Auth)")]'.
+ *
+ * Responses get 'X-RateLimit-Limit', 'X-RateLimit-Remaining' and
+ * 'X-RateLimit-Reset' headers, limited requests a 429 with 'Retry-After'.
+ * When the store fails requests go through, an unreachable database
+ * shouldn't take the routes down with it.
+ */
+pub struct RateLimit(pub LimitGroup);
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), group: self.0 }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    group: LimitGroup,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let group = self.group;
+
+        Box::pin(async move {
+            let limit = match req.app_data::<web::Data<AppState>>() {
+                Some(app_state) => app_state.rate_limits
+                    .check(group, &client(&req, app_state)).await
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error checking rate limit: {e}");
+                        None
+                    }),
+                None => None,
+            };
+
+            match limit {
+                Some(limit) if !limit.take.allowed => {
+                    let mut response = HttpResponse::TooManyRequests()
+                        .json(TooManyRequests::new(&limit));
+                    set_headers(response.headers_mut(), &limit);
+                    Ok(req.into_response(response).map_into_right_body())
+                },
+                _ => {
+                    let mut response = service.call(req).await?;
+                    if let Some(limit) = limit {
+                        set_headers(response.headers_mut(), &limit);
+                    }
+                    Ok(response.map_into_left_body())
+                },
+            }
+        })
+    }
+}
+
+/// The peer's IP, and the user of the access token if it's valid.
+fn client(req: &ServiceRequest, app_state: &AppState) -> Client {
+    let user_id = req.headers().get(AUTHORIZATION)
+        .and_then(|token| token.to_str().ok())
+        .and_then(|token| app_state.keys.decode::<Claims>(token).ok())
+        .map(|claims| claims.user_id);
+    Client {
+        ip: req.peer_addr().map_or("unknown".to_string(), |addr| addr.ip().to_string()),
+        user_id,
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, limit: &Limit) {
+    let reset = limit.policy.reset_after(limit.take.tokens);
+    for (name, value) in [
+        ("x-ratelimit-limit", i64::from(limit.policy.burst)),
+        ("x-ratelimit-remaining", i64::from(limit.remaining())),
+        ("x-ratelimit-reset", reset),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+    if !limit.take.allowed {
+        headers.insert(RETRY_AFTER,
+            HeaderValue::from(limit.policy.retry_after(limit.take.tokens)));
+    }
+}
+
+/**
+ * The JSON body of a 429, e.g.
+ * '{"error": "too_many_requests", "message": "...", "retry_after": 12}'.
+ */
+#[derive(Debug, Serialize)]
+struct TooManyRequests {
+    error: &'static str,
+    message: String,
+    retry_after: i64,
+}
+
+impl TooManyRequests {
+    fn new(limit: &Limit) -> TooManyRequests {
+        TooManyRequests {
+            error: "too_many_requests",
+            message: limit.to_string(),
+            retry_after: limit.policy.retry_after(limit.take.tokens),
+        }
+    }
+}
@@ -1,26 +1,15 @@
 use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{ReadHotels, Reader};
 
 #[get("/get_hotel_reviews/{hotel_id}")]
 pub async fn get_hotel_reviews(
-    path: web::Path<usize>, app_state: web::Data<AppState>
+    _: Reader<ReadHotels>, path: web::Path<usize>, filter: web::Query<ReviewFilter>,
+    app_state: web::Data<AppState>
 ) -> HttpResponse {
     let hotel_id: usize = path.into_inner();
 
-    #[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
-    pub struct Review {
-        pub hotel_id: i32,
-        pub review_id: String,
-        pub rating: i32,
-        pub author: String,
-        pub title: String,
-        pub text: String,
-        pub time: String,
-    }
-
-    let hotel: Result<Vec<Review>> = sqlx::query_as(
-        SELECT_HOTEL_REVIEWS
-    ).bind(hotel_id as u64)
-    .fetch_all(&app_state.pool).await;
+    let hotel = app_state.reviews
+        .get_hotel_reviews(hotel_id as i32, &filter).await;
 
     match hotel {
         Ok(h) => HttpResponse::Ok().json(h),
@@ -29,4 +18,4 @@ pub async fn get_hotel_reviews(
             HttpResponse::BadRequest().into()
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,21 @@
+use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{ReadHotels, Reader};
+
+#[get("/search")]
+pub async fn search(
+    _: Reader<ReadHotels>, query: web::Query<SearchQuery>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Missing search words 'q'");
+    }
+
+    let results = app_state.search.search(&query).await;
+
+    match results {
+        Ok(rs) => HttpResponse::Ok().json(rs),
+        Err(e) => {
+            eprintln!("Error searching: {e}");
+            HttpResponse::BadRequest().into()
+        }
+    }
+}
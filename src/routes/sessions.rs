@@ -0,0 +1,155 @@
+use chrono::Utc;
+
+use crate::routes::utils::*;
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+use crate::tokens::*;
+
+/**
+ * What logging in and refreshing return.
+ *
+ * # Fields:
+ * - 'token': The access token, sent in the 'Authorization' header.
+ * - 'refresh_token': Exchanged at '/token/refresh' for the next pair, it
+ *   only works once.
+ * - 'expires_in': Seconds until 'token' expires.
+ */
+#[derive(Serialize)]
+pub(crate) struct TokenPair {
+    token: String,
+    refresh_token: String,
+    expires_in: i64,
+}
+
+impl TokenPair {
+    /// Fails if the access token can't be signed.
+    fn new(app_state: &AppState, user: User, refresh_token: String
+    ) -> Result<TokenPair, String> {
+        let token = create_jwt(
+            user.id as usize, user.username, user.email, user.role,
+            &app_state.keys, app_state.tokens.access
+        )?;
+        Ok(TokenPair {
+            token, refresh_token, expires_in: app_state.tokens.access.num_seconds()
+        })
+    }
+}
+
+/// Issues an access token and the first refresh token of a new session.
+pub(crate) async fn start_session(app_state: &AppState, user: User
+) -> Result<TokenPair, String> {
+    let now = Utc::now();
+    let refresh_token = new_refresh_token();
+    app_state.sessions.add_refresh_token(&NewRefreshToken {
+        user_id: user.id,
+        family: new_token_family(),
+        token_hash: hash_refresh_token(&refresh_token),
+        created_at: now,
+        expires_at: now + app_state.tokens.refresh,
+    }).await.map_err(|e| e.to_string())?;
+    TokenPair::new(app_state, user, refresh_token)
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[post("/token/refresh", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn refresh_session(
+    request: web::Json<RefreshRequest>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let now = Utc::now();
+    let next = new_refresh_token();
+
+    let rotation = app_state.sessions.rotate_refresh_token(
+        &hash_refresh_token(&request.refresh_token), &hash_refresh_token(&next),
+        now, now + app_state.tokens.refresh
+    ).await;
+
+    let user_id = match rotation {
+        Ok(Rotation::Rotated { user_id }) => user_id,
+        Ok(Rotation::Reused { user_id, family }) => {
+            eprintln!("Refresh token of user {user_id} was reused, \
+                revoked session {family}");
+            return HttpResponse::Unauthorized().into();
+        },
+        Ok(Rotation::Expired | Rotation::Unknown) => {
+            return HttpResponse::Unauthorized().into();
+        },
+        Err(e) => {
+            eprintln!("Error rotating refresh token: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+
+    let user = match app_state.users.get_user(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().into(),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+
+    match TokenPair::new(&app_state, user, next) {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) => {
+            eprintln!("Error issuing access token: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+/**
+ * # Fields:
+ * - 'all': Log out every session of the user instead of just this one.
+ */
+#[derive(Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Serialize)]
+struct LogoutResponse {
+    revoked: u64,
+}
+
+#[post("/logout", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn logout(
+    request: web::Json<LogoutRequest>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let now = Utc::now();
+
+    let token = app_state.sessions
+        .get_refresh_token(&hash_refresh_token(&request.refresh_token)).await;
+    let token = match token {
+        Ok(Some(token)) if token.is_active(now) => token,
+        Ok(_) => return HttpResponse::Unauthorized().into(),
+        Err(e) => {
+            eprintln!("Error getting refresh token: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+
+    let revoked = match request.all {
+        true => app_state.sessions.revoke_user_tokens(token.user_id, now).await,
+        false => app_state.sessions.revoke_token_family(&token.family, now).await,
+    };
+
+    match revoked {
+        Ok(revoked) => HttpResponse::Ok().json(LogoutResponse { revoked }),
+        Err(e) => {
+            eprintln!("Error revoking refresh tokens: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
+
+/// The public keys of access tokens, for other services to verify them.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(app_state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(app_state.keys.jwks())
+}
@@ -0,0 +1,263 @@
+use actix_web::HttpRequest;
+use chrono::{Duration, Utc};
+
+use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{AuthorizationToken, Authorized, Moderators};
+use crate::routes::logins::LoginAttempt;
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+use crate::jwt::JwtKeys;
+use crate::tokens::{hash_recovery_code, new_recovery_code};
+use crate::totp;
+
+/// How long the second step of a login may take.
+const TOTP_LOGIN_TTL_SECS: i64 = 5 * 60;
+
+const RECOVERY_CODES: usize = 10;
+
+/**
+ * The JWT of a login waiting for its TOTP code. Its 'aud' is the access
+ * tokens' audience and ':totp', so it isn't accepted as an access token.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpClaims {
+    pub user_id: usize,
+    pub exp: usize,
+    pub iss: String,
+    pub aud: String,
+}
+
+fn totp_audience(keys: &JwtKeys) -> String {
+    format!("{}:totp", keys.audience())
+}
+
+/// The answer to a login with the right password, when a code is needed too.
+#[derive(Serialize)]
+pub struct TotpRequired {
+    pub totp_required: bool,
+    pub totp_token: String,
+    pub expires_in: i64,
+}
+
+/// Answers a login whose password was right, but whose user needs a code.
+pub(crate) fn totp_required(app_state: &AppState, user_id: i32) -> HttpResponse {
+    let token = app_state.keys.encode(&TotpClaims {
+        user_id: user_id as usize,
+        exp: (Utc::now() + Duration::seconds(TOTP_LOGIN_TTL_SECS)).timestamp() as usize,
+        iss: app_state.keys.issuer().to_string(),
+        aud: totp_audience(&app_state.keys),
+    });
+
+    match token {
+        Ok(totp_token) => HttpResponse::Ok().json(TotpRequired {
+            totp_required: true, totp_token, expires_in: TOTP_LOGIN_TTL_SECS,
+        }),
+        Err(e) => {
+            eprintln!("Error issuing TOTP login token: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+/**
+ * Checks a code of an app, or a recovery code, against a confirmed 'totp'
+ * and uses it up.
+ *
+ * # Returns:
+ * - false if it's wrong or was used already.
+ */
+async fn use_code(app_state: &AppState, totp: &Totp, code: &str) -> DbResult<bool> {
+    let now = Utc::now();
+    let digits: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    // Recovery codes are longer, and have letters and a dash
+    if digits.len() == totp::DIGITS as usize && digits.chars().all(|c| c.is_ascii_digit()) {
+        return match totp::matching_step(&totp.secret, &digits, now) {
+            Some(step) => app_state.totp.use_totp_step(totp.user_id, step).await,
+            None => Ok(false),
+        };
+    }
+    app_state.totp.use_recovery_code(totp.user_id, &hash_recovery_code(code), now).await
+}
+
+/**
+ * # Fields:
+ * - 'secret': Base32, for apps the URI can't be scanned into.
+ * - 'otpauth_uri': For a QR code.
+ */
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Starts turning on two-factor authentication, for moderators and admins.
+/// Enrolling again before confirming replaces the secret.
+#[post("/totp/enroll", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn enroll_totp(auth: Authorized<Moderators>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id = auth.token.id as i32;
+    let account = match app_state.users.get_account(user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+    match app_state.totp.get_totp(user_id).await {
+        Ok(Some(totp)) if totp.is_confirmed() => {
+            return HttpResponse::Conflict().body("Two-factor authentication is on already");
+        },
+        Ok(_) => {},
+        Err(e) => {
+            eprintln!("Error getting TOTP: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    }
+
+    let secret = totp::new_secret();
+    match app_state.totp.set_totp_secret(user_id, &secret, Utc::now()).await {
+        Ok(_) => HttpResponse::Ok().json(TotpEnrollment {
+            otpauth_uri: totp::otpauth_uri(app_state.keys.issuer(), &account.username, &secret),
+            secret,
+        }),
+        Err(e) => {
+            eprintln!("Error enrolling TOTP: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// The recovery codes, the only time they're shown.
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Turns on two-factor authentication with a first code from the app.
+#[post("/totp/confirm", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn confirm_totp_enrollment(
+    auth: Authorized<Moderators>, request: web::Json<TotpCode>,
+    app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id = auth.token.id as i32;
+    let totp = match app_state.totp.get_totp(user_id).await {
+        Ok(Some(totp)) if !totp.is_confirmed() => totp,
+        Ok(_) => return HttpResponse::Conflict().body("Not enrolling, enroll first"),
+        Err(e) => {
+            eprintln!("Error getting TOTP: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+    let now = Utc::now();
+    let Some(step) = totp::matching_step(&totp.secret, &request.code, now) else {
+        return HttpResponse::BadRequest().body("Invalid code");
+    };
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES).map(|_| new_recovery_code()).collect();
+    let hashes: Vec<String> = recovery_codes.iter().map(|c| hash_recovery_code(c)).collect();
+    match app_state.totp.confirm_totp(user_id, step, &hashes, now).await {
+        Ok(true) => HttpResponse::Ok().json(RecoveryCodes { recovery_codes }),
+        Ok(false) => HttpResponse::Conflict().body("Not enrolling, enroll first"),
+        Err(e) => {
+            eprintln!("Error confirming TOTP: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+/// Turns off two-factor authentication with a code, or a recovery code.
+#[post("/totp/disable", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn disable_totp(
+    auth: AuthorizationToken, request: web::Json<TotpCode>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id = auth.id as i32;
+    let used = match app_state.totp.get_totp(user_id).await {
+        Ok(Some(totp)) if totp.is_confirmed() => use_code(&app_state, &totp, &request.code).await,
+        Ok(_) => return HttpResponse::NotFound().body("Two-factor authentication is off"),
+        Err(e) => Err(e),
+    };
+    match used {
+        Ok(true) => {},
+        Ok(false) => return HttpResponse::BadRequest().body("Invalid code"),
+        Err(e) => {
+            eprintln!("Error checking TOTP code: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    }
+
+    match app_state.totp.delete_totp(user_id).await {
+        Ok(_) => HttpResponse::Ok().into(),
+        Err(e) => {
+            eprintln!("Error disabling TOTP: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+/**
+ * # Fields:
+ * - 'totp_token': From 'login'.
+ * - 'code': From the app, or a recovery code.
+ */
+#[derive(Deserialize)]
+pub struct TotpLogin {
+    pub totp_token: String,
+    pub code: String,
+}
+
+/// The second step of a login with two-factor authentication. Wrong codes
+/// count towards a lockout like wrong passwords.
+#[post("/login/totp", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn login_totp(
+    req: HttpRequest, request: web::Json<TotpLogin>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let audience = totp_audience(&app_state.keys);
+    let Ok(claims) = app_state.keys.decode_for::<TotpClaims>(&request.totp_token, &audience)
+    else {
+        return HttpResponse::Unauthorized().body("Invalid or expired token");
+    };
+    let user_id = claims.user_id as i32;
+
+    let (account, totp) = match app_state.users.get_account(user_id).await {
+        Ok(Some(account)) => match app_state.totp.get_totp(user_id).await {
+            Ok(Some(totp)) if totp.is_confirmed() => (account, totp),
+            // Turned off since the password was checked
+            Ok(_) => return HttpResponse::Unauthorized().body("Invalid or expired token"),
+            Err(e) => {
+                eprintln!("Error getting TOTP: {e}");
+                return HttpResponse::InternalServerError().into();
+            },
+        },
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid or expired token"),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+    let attempt = LoginAttempt::new(&req, &account.username);
+
+    let lockout = match attempt.check_lockout(&app_state, user_id).await {
+        Ok(lockout) => lockout,
+        Err(locked) => return locked,
+    };
+    match use_code(&app_state, &totp, &request.code).await {
+        Ok(true) => {},
+        Ok(false) => {
+            attempt.fail(&app_state, Some(user_id), LoginFailure::WrongCode).await;
+            return HttpResponse::Unauthorized().into();
+        },
+        Err(e) => {
+            eprintln!("Error checking TOTP code: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    }
+
+    let UserAccount { id, username, email, role, .. } = account;
+    attempt.succeed(&app_state, User { id, username, email, role }, lockout).await
+}
@@ -1,14 +1,24 @@
 use crate::routes::utils::*;
+use crate::routes::jwt_extractor::{Caller, Moderators, ReadReviews, Reader, WriteReviews};
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
 
-#[post("/add_user_review")]
+#[post("/add_user_review", wrap = "RateLimit(LimitGroup::Writes)")]
 pub async fn add_user_review(
-    review: web::Json<NewUserReview>, app_state: web::Data<AppState>
+    caller: Caller, review: web::Json<NewUserReview>,
+    app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let added_user_review = sqlx::query(
-        INSERT_USER_REVIEW
-    ).bind(review.user_id.clone()).bind(review.hotel_id.clone())
-    .bind(review.title.clone()).bind(review.text.clone())
-    .execute(&app_state.pool).await;
+    // 'reviews:write' keys add reviews as the admin who created them
+    let user_id = match caller.author::<WriteReviews>() {
+        Ok(user_id) => user_id,
+        Err(denied) => return denied.error_response(),
+    };
+    if review.rating.is_some_and(|r| !(1..=5).contains(&r)) {
+        return HttpResponse::BadRequest().body("Rating must be from 1 to 5");
+    }
+    let review = NewUserReview { user_id, ..review.into_inner() };
+
+    let added_user_review = app_state.user_reviews.add_user_review(&review).await;
 
     match added_user_review {
         Ok(_) => HttpResponse::Ok().into(),
@@ -20,11 +30,9 @@ pub async fn add_user_review(
 }
 
 #[get("/get_all_user_reviews")]
-pub async fn get_all_user_reviews(app_state: web::Data<AppState>
+pub async fn get_all_user_reviews(_: Reader<ReadReviews>, app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let user_reviews: Result<Vec<UserReview>> = sqlx::query_as(
-        SELECT_ALL_USER_REVIEWS
-    ).fetch_all(&app_state.pool).await;
+    let user_reviews = app_state.user_reviews.get_all_user_reviews().await;
 
     match user_reviews {
         Ok(us) => HttpResponse::Ok().json(us),
@@ -37,12 +45,11 @@ pub async fn get_all_user_reviews(app_state: web::Data<AppState>
 
 #[get("/get_user_reviews_by_username/{username}")]
 pub async fn get_user_reviews_by_username(
-    path: web::Path<String>, app_state: web::Data<AppState>
+    _: Reader<ReadReviews>, path: web::Path<String>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let username = path.into_inner();
-    let user_reviews: Result<Vec<UserReview>> = sqlx::query_as(
-        SELECT_USER_REVIEWS_BY_USERNAME
-    ).bind(username).fetch_all(&app_state.pool).await;
+    let user_reviews = app_state.user_reviews
+        .get_user_reviews_by_username(&username).await;
 
     match user_reviews {
         Ok(us) => HttpResponse::Ok().json(us),
@@ -55,12 +62,11 @@ pub async fn get_user_reviews_by_username(
 
 #[get("/get_user_reviews_by_hotel_id/{hotel_id}")]
 pub async fn get_user_reviews_by_hotel_id(
-    path: web::Path<usize>, app_state: web::Data<AppState>
+    _: Reader<ReadReviews>, path: web::Path<usize>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let hotel_id = path.into_inner();
-    let hotel_user_reviews: Result<Vec<UserReview>> = sqlx::query_as(
-        SELECT_USER_REVIEWS_BY_HOTEL_ID
-    ).bind(hotel_id as u64).fetch_all(&app_state.pool).await;
+    let hotel_user_reviews = app_state.user_reviews
+        .get_user_reviews_by_hotel_id(hotel_id as i32).await;
 
     match hotel_user_reviews {
         Ok(us) => HttpResponse::Ok().json(us),
@@ -71,15 +77,28 @@ pub async fn get_user_reviews_by_hotel_id(
     }
 }
 
-#[get("/delete_user_review/{user_review_id}")]
+#[get("/delete_user_review/{user_review_id}", wrap = "RateLimit(LimitGroup::Writes)")]
 pub async fn delete_user_review(
-    path: web::Path<usize>, app_state: web::Data<AppState>
+    caller: Caller, path: web::Path<usize>,
+    app_state: web::Data<AppState>
 ) -> HttpResponse {
     let user_review_id: usize = path.into_inner();
 
-    let deleted: sqlx::Result<MySqlQueryResult> = sqlx::query(
-        DELETE_USER_REVIEW
-    ).bind(user_review_id as u64).execute(&app_state.pool).await;
+    // Moderators and 'reviews:write' keys may delete anyone's reviews
+    match app_state.user_reviews.get_user_review(user_review_id as i32).await {
+        Ok(Some(review)) => if let Err(denied) =
+            caller.check_owner::<Moderators, WriteReviews>(review.user_id) {
+            return denied.error_response();
+        },
+        Ok(None) => return HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error getting user review: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    }
+
+    let deleted = app_state.user_reviews
+        .delete_user_review(user_review_id as i32).await;
 
     match deleted {
         Ok(_) => HttpResponse::Ok().into(),
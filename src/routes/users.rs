@@ -1,17 +1,46 @@
+use actix_web::HttpRequest;
+
 use crate::routes::utils::*;
-use crate::routes::jwt_extractor::AuthorizationToken;
+use crate::routes::jwt_extractor::{
+    Admins, AuthorizationToken, Authorized, Caller, Permitted, ReadUsers, WriteUsers
+};
+use crate::routes::logins::LoginAttempt;
+use crate::routes::totp::totp_required;
+use crate::routes::emails::send_email_token;
+use crate::routes::rate_limit::RateLimit;
+use crate::rate_limit::LimitGroup;
+
+#[derive(Deserialize)]
+pub struct Registration {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
 
-#[post("/add_user")]
+#[post("/add_user", wrap = "RateLimit(LimitGroup::Writes)")]
 pub async fn add_user(
-    user: web::Json<NewUser>, app_state: web::Data<AppState>
+    registration: web::Json<Registration>, app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let added_user = sqlx::query(
-        INSERT_USER
-    ).bind(user.username.clone()).bind(user.email.clone())
-    .execute(&app_state.pool).await;
+    let Registration { username, email, password } = registration.into_inner();
+    if let Err(e) = check_password(&password) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let password_hash = match hash_password(&app_state.passwords, password).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            eprintln!("Error adding new user: {e}");
+            return HttpResponse::InternalServerError().into();
+        },
+    };
+
+    let user = NewUser { username, email, password_hash: Some(password_hash) };
+    let added_user = app_state.users.add_user(&user).await;
 
     match added_user {
-        Ok(_) => HttpResponse::Ok().into(),
+        Ok(_) => {
+            send_verification(&app_state, &user).await;
+            HttpResponse::Ok().into()
+        },
         Err(e) => {
             eprintln!("Error adding new user: {e}");
             HttpResponse::BadRequest().into()
@@ -19,18 +48,31 @@ pub async fn add_user(
     }
 }
 
+/// Emails a new user their verification link, a failure only gets logged:
+/// they can ask for another one.
+async fn send_verification(app_state: &AppState, user: &NewUser) {
+    let added = app_state.users.get_user_by_creds(&user.username, &user.email).await
+        .map_err(|e| e.to_string())
+        .and_then(|added| added.ok_or_else(|| "the new user wasn't found".to_string()));
+    let sent = match added {
+        Ok(added) => send_email_token(app_state, &added, EmailPurpose::VerifyEmail).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        eprintln!("Error sending verification email to '{}': {e}", user.email);
+    }
+}
+
 #[get("/get_user/{user_id}")]
 pub async fn get_user(path: web::Path<usize>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let user_id: usize = path.into_inner();
 
-    let user: Result<Option<User>> = sqlx::query_as(
-        SELECT_USER
-    ).bind(user_id as u64)
-    .fetch_optional(&app_state.pool).await;
+    let user = app_state.users.get_user(user_id as i32).await;
 
     match user {
-        Ok(u) => HttpResponse::Ok().json(u.unwrap()),
+        Ok(Some(u)) => HttpResponse::Ok().json(u),
+        Ok(None) => HttpResponse::NotFound().into(),
         Err(e) => {
             eprintln!("Error getting user: {e}"); 
             HttpResponse::BadRequest().into()
@@ -38,40 +80,62 @@ pub async fn get_user(path: web::Path<usize>, app_state: web::Data<AppState>
     }
 }
 
-#[derive(Serialize)]
-struct Response {
-    found_user: bool,
-    token: String,
+/**
+ * # Fields:
+ * - 'username': The username or the email of the account.
+ */
+#[derive(Deserialize)]
+pub struct Credentials {
+    #[serde(alias = "email")]
+    pub username: String,
+    pub password: String,
 }
 
-#[get("/login/{username}&{email}")]
+#[post("/login", wrap = "RateLimit(LimitGroup::Auth)")]
 pub async fn login(
-    path: web::Path<(String, String)>, 
-    app_state: web::Data<AppState>
+    req: HttpRequest, credentials: web::Json<Credentials>, app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let (username, email) = path.into_inner();
+    let Credentials { username, password } = credentials.into_inner();
+    let attempt = LoginAttempt::new(&req, &username);
 
-    let user: Result<Option<User>> = sqlx::query_as(
-        SELECT_USER_BY_CREDS
-    ).bind(username).bind(email)
-    .fetch_optional(&app_state.pool).await;
+    let account = match app_state.users.get_user_by_creds(&username, &username).await {
+        Ok(Some(u)) => app_state.users.get_account(u.id).await,
+        other => other.map(|_| None),
+    };
 
-    match user {
-        Ok(u) => {
-            match u {
-                Some(u) => {
-                    println!("U: {:#?}", u);
-                    let token = create_jwt(
-                        u.id as usize, u.username, u.email, 
-                        "SECRET".to_string()
-                    );
-                    println!("TOKEN: {}",token);
-                    let _ = check_jwt(token.clone(), "SECRET".to_string());
-                    HttpResponse::Ok().json(Response {found_user: true, token})
+    match account {
+        Ok(Some(UserAccount { id, username, email, password_hash: Some(hash), role, .. })) => {
+            // A locked account's password isn't even checked
+            let lockout = match attempt.check_lockout(&app_state, id).await {
+                Ok(lockout) => lockout,
+                Err(locked) => return locked,
+            };
+            if !verify_password(&app_state.passwords, password, hash).await {
+                attempt.fail(&app_state, Some(id), LoginFailure::WrongPassword).await;
+                return HttpResponse::Unauthorized().into();
+            }
+
+            // With two-factor authentication the session waits for a code
+            match app_state.totp.get_totp(id).await {
+                Ok(Some(totp)) if totp.is_confirmed() => return totp_required(&app_state, id),
+                Ok(_) => {},
+                Err(e) => {
+                    eprintln!("Error getting TOTP: {e}");
+                    return HttpResponse::InternalServerError().into();
                 },
-                None => HttpResponse::Ok()
-                    .json(Response {found_user: false, token: "".to_string()})
             }
+            attempt.succeed(&app_state, User { id, username, email, role }, lockout).await
+        },
+        Ok(Some(UserAccount { id, username, password_hash: None, .. })) => {
+            eprintln!("Login by '{username}', who has no password set");
+            attempt.fail(&app_state, Some(id), LoginFailure::NoPassword).await;
+            HttpResponse::Forbidden().body("A password must be set for this account")
+        },
+        Ok(None) => {
+            // Hash anyway, so the response time doesn't tell the user exists
+            let _ = hash_password(&app_state.passwords, password).await;
+            attempt.fail(&app_state, None, LoginFailure::UnknownUser).await;
+            HttpResponse::Unauthorized().into()
         },
         Err(e) => {
             eprintln!("Error getting user: {e}"); 
@@ -80,34 +144,59 @@ pub async fn login(
     }
 }
 
-#[get("/check_user/{username}&{email}")]
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[post("/change_password", wrap = "RateLimit(LimitGroup::Auth)")]
+pub async fn change_password(
+    auth: AuthorizationToken, change: web::Json<PasswordChange>,
+    app_state: web::Data<AppState>
+) -> HttpResponse {
+    let PasswordChange { current_password, new_password } = change.into_inner();
+    if let Err(e) = check_password(&new_password) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let hash = match app_state.users.get_account(auth.id as i32).await {
+        Ok(Some(UserAccount { password_hash: Some(hash), .. })) => hash,
+        Ok(_) => return HttpResponse::Forbidden().into(),
+        Err(e) => {
+            eprintln!("Error getting user: {e}");
+            return HttpResponse::BadRequest().into();
+        },
+    };
+    if !verify_password(&app_state.passwords, current_password, hash).await {
+        return HttpResponse::Unauthorized().into();
+    }
+
+    let changed = match hash_password(&app_state.passwords, new_password).await {
+        Ok(hash) => app_state.users.set_password_hash(auth.id as i32, &hash).await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+
+    match changed {
+        Ok(_) => HttpResponse::Ok().into(),
+        Err(e) => {
+            eprintln!("Error changing password: {e}");
+            HttpResponse::InternalServerError().into()
+        },
+    }
+}
+
+#[get("/check_user/{username}&{email}", wrap = "RateLimit(LimitGroup::Auth)")]
 pub async fn check_user(
     path: web::Path<(String, String)>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let (username, email) = path.into_inner();
-    println!("URL - username: {username} - email: {email}");
 
-    let user: Result<Option<User>> = sqlx::query_as(
-        SELECT_USER_BY_CREDS
-    ).bind(username).bind(email)
-    .fetch_optional(&app_state.pool).await;
+    let user = app_state.users.get_user_by_creds(&username, &email).await;
 
     match user {
-        Ok(u) => {
-            match u {
-                Some(u) => {
-                    println!("U: {:#?}", u);
-                    let token = create_jwt(
-                        u.id as usize, u.username, u.email, 
-                        "SECRET".to_string()
-                    );
-                    println!("TOKEN: {token}");
-                    let _ = check_jwt(token, "SECRET".to_string());
-                    HttpResponse::Ok().json(true)
-                },
-                None => HttpResponse::Ok().json(false)
-            }
-        },
+        Ok(u) => HttpResponse::Ok().json(u.is_some()),
         Err(e) => {
             eprintln!("Error getting user: {e}"); 
             HttpResponse::BadRequest().into()
@@ -116,10 +205,9 @@ pub async fn check_user(
 }
 
 #[get("/get_all_users")]
-pub async fn get_all_users(app_state: web::Data<AppState>) -> HttpResponse {
-    let users: Result<Vec<User>> = sqlx::query_as(
-        SELECT_ALL_USERS
-    ).fetch_all(&app_state.pool).await;
+pub async fn get_all_users(_: Permitted<Admins, ReadUsers>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let users = app_state.users.get_all_users().await;
 
     match users {
         Ok(us) => HttpResponse::Ok().json(us),
@@ -130,14 +218,15 @@ pub async fn get_all_users(app_state: web::Data<AppState>) -> HttpResponse {
     }
 }
 
-#[post("/update")]
+#[post("/update", wrap = "RateLimit(LimitGroup::Writes)")]
 pub async fn update_user(
-    user: web::Form<User>, app_state: web::Data<AppState>
+    caller: Caller, user: web::Form<User>, app_state: web::Data<AppState>
 ) -> HttpResponse {
-    let updated: sqlx::Result<MySqlQueryResult> = sqlx::query(
-        UPDATE_USER
-    ).bind(user.username.clone()).bind(user.email.clone()).bind(user.id)
-    .execute(&app_state.pool).await;
+    if let Err(denied) = caller.check_owner::<Admins, WriteUsers>(user.id) {
+        return denied.error_response();
+    }
+
+    let updated = app_state.users.update_user(&user).await;
 
     match updated {
         Ok(_) => HttpResponse::Ok().into(),
@@ -148,14 +237,16 @@ pub async fn update_user(
     }
 }
 
-#[get("/delete/{user_id}")]
-pub async fn delete_user(path: web::Path<usize>, app_state: web::Data<AppState>
+#[get("/delete/{user_id}", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn delete_user(
+    caller: Caller, path: web::Path<usize>, app_state: web::Data<AppState>
 ) -> HttpResponse {
     let user_id: usize = path.into_inner();
+    if let Err(denied) = caller.check_owner::<Admins, WriteUsers>(user_id as i32) {
+        return denied.error_response();
+    }
 
-    let deleted: sqlx::Result<MySqlQueryResult> = sqlx::query(
-        DELETE_USER
-    ).bind(user_id as u64).execute(&app_state.pool).await;
+    let deleted = app_state.users.delete_user(user_id as i32).await;
 
     match deleted {
         Ok(_) => HttpResponse::Ok().into(),
@@ -164,4 +255,32 @@ pub async fn delete_user(path: web::Path<usize>, app_state: web::Data<AppState>
             HttpResponse::BadRequest().into()
         },
     }
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct RoleChange {
+    pub role: Role,
+}
+
+#[post("/set_role/{user_id}", wrap = "RateLimit(LimitGroup::Writes)")]
+pub async fn set_role(
+    auth: Authorized<Admins>, path: web::Path<usize>,
+    change: web::Json<RoleChange>, app_state: web::Data<AppState>
+) -> HttpResponse {
+    let user_id: usize = path.into_inner();
+    // Keeps at least one admin around
+    if user_id == auth.token.id {
+        return HttpResponse::BadRequest().body("Admins can't change their own role");
+    }
+
+    let updated = app_state.users.set_role(user_id as i32, change.role).await;
+
+    match updated {
+        Ok(true) => HttpResponse::Ok().into(),
+        Ok(false) => HttpResponse::NotFound().into(),
+        Err(e) => {
+            eprintln!("Error setting role: {e}");
+            HttpResponse::BadRequest().into()
+        },
+    }
+}
@@ -1,82 +1,49 @@
-pub use actix_web::{get, post, web, HttpResponse};
-pub use sqlx::mysql::MySqlQueryResult;
+pub use actix_web::{get, post, web, HttpResponse, ResponseError};
 pub use serde::{Serialize, Deserialize};
-pub use sqlx::Result;
-use jsonwebtoken::{
-    encode, decode, EncodingKey, Header, DecodingKey, Validation
-};
 use chrono::{Utc, Duration};
 
 pub use crate::database::*;
-
-#[derive(Deserialize, Clone)]
-pub struct NewUser {
-    pub username: String,
-    pub email: String,
-}
-
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
-pub struct User {
-    pub id: i32,
-    pub username: String,
-    pub email: String,
-}
-
-#[derive(Deserialize, Clone)]
-pub struct NewUserReview {
-    pub user_id: i32,
-    pub hotel_id: i32,
-    pub title: String,
-    pub text: String,
-}
-
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
-pub struct UserReview {
-    pub review_id: i32,
-    pub user_id: i32,
-    pub hotel_id: i32,
-    pub title: String,
-    pub text: String,
-}
+pub use crate::passwords::*;
+use crate::jwt::JwtKeys;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: usize,
     pub username: String,
     pub email: String,
+    /// Tokens issued before roles were added belong to plain users.
+    #[serde(default)]
+    pub role: Role,
     pub exp: usize,
+    pub iss: String,
+    pub aud: String,
 }
 
 pub fn create_jwt(
-    user_id: usize, username: String, email: String, secret: String
-) -> String {
+    user_id: usize, username: String, email: String, role: Role,
+    keys: &JwtKeys, ttl: Duration
+) -> Result<String, String> {
     let claims: Claims = Claims { 
-        user_id, username, email, 
-        exp: (Utc::now() + Duration::hours(1)).timestamp() as usize
+        user_id, username, email, role,
+        exp: (Utc::now() + ttl).timestamp() as usize,
+        iss: keys.issuer().to_string(),
+        aud: keys.audience().to_string(),
     };
 
-    println!("Claims: {:#?}", claims);
+    keys.encode(&claims)
+}
 
-    return encode(
-        &Header::default(), 
-        &claims, 
-        &EncodingKey::from_secret(secret.as_ref())
-    ).unwrap();
+/// Hashes off the async workers, argon2 is slow on purpose.
+pub async fn hash_password(passwords: &Passwords, password: String
+) -> Result<String, String> {
+    let passwords = passwords.clone();
+    web::block(move || passwords.hash(&password)).await
+        .map_err(|e| e.to_string())?
 }
 
-pub fn check_jwt(token: String, secret: String) -> bool {
-    let token = decode::<Claims>(
-        &token, &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default()
-    );
-    match token {
-        Ok(tok) => {
-            println!("TOKEN-CLAIMS: {:#?}", tok.claims);
-            true
-        },
-        Err(e) => {
-            println!("Error validating token: {e}");
-            false
-        }
-    }
-}
\ No newline at end of file
+pub async fn verify_password(passwords: &Passwords, password: String, hash: String
+) -> bool {
+    let passwords = passwords.clone();
+    web::block(move || passwords.verify(&password, &hash)).await
+        .unwrap_or(false)
+}
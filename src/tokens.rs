@@ -0,0 +1,95 @@
+use chrono::Duration;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/**
+ * How long issued tokens are valid.
+ *
+ * # Fields:
+ * - 'access': JWTs sent with each request, they can't be revoked so they're
+ *   kept short.
+ * - 'refresh': Refresh tokens, each one is exchanged for a new access token
+ *   and the next refresh token.
+ * - 'email_verification', 'password_reset': The links sent by email.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenLifetimes {
+    pub access: Duration,
+    pub refresh: Duration,
+    pub email_verification: Duration,
+    pub password_reset: Duration,
+}
+
+impl Default for TokenLifetimes {
+    fn default() -> Self {
+        TokenLifetimes {
+            access: Duration::minutes(15),
+            refresh: Duration::days(30),
+            email_verification: Duration::days(1),
+            password_reset: Duration::hours(1),
+        }
+    }
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    hex(&buf)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A new refresh token: 256 random bits, hex encoded.
+pub fn new_refresh_token() -> String {
+    random_hex(32)
+}
+
+/// A new session id, shared by the refresh tokens issued from one login.
+pub fn new_token_family() -> String {
+    random_hex(16)
+}
+
+/// The id of a new emailed token, its JWT is what's sent.
+pub fn new_email_token_id() -> String {
+    random_hex(16)
+}
+
+/**
+ * The SHA-256 of a refresh token, which is what gets stored.
+ *
+ * Refresh tokens are random rather than picked by users, so a fast hash is
+ * enough: a leaked table can't be turned back into usable tokens.
+ */
+pub fn hash_refresh_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+/**
+ * The prefix and the secret of a new API key, which is
+ * 'hk_<prefix>_<secret>'. The prefix is stored as is, to find the key.
+ */
+pub fn new_api_key() -> (String, String) {
+    (random_hex(6), random_hex(32))
+}
+
+/// The SHA-256 of an API key's secret, random like refresh tokens.
+pub fn hash_api_key_secret(secret: &str) -> String {
+    hex(&Sha256::digest(secret.as_bytes()))
+}
+
+/// A one-time recovery code of two-factor authentication, 'xxxxx-xxxxx'.
+pub fn new_recovery_code() -> String {
+    let code = random_hex(5);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// The SHA-256 of a recovery code, ignoring case and dashes as typed.
+pub fn hash_recovery_code(code: &str) -> String {
+    let code: String = code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex(&Sha256::digest(code.as_bytes()))
+}
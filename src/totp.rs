@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use rand::{rngs::OsRng, RngCore};
+use ring::hmac;
+
+/// Seconds a code is valid for, the default of authenticator apps.
+pub const PERIOD_SECS: i64 = 30;
+
+pub const DIGITS: u32 = 6;
+
+/// Codes of the previous and next periods are accepted too, for clock skew.
+const SKEW_STEPS: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new 160-bit secret, base32 encoded as authenticator apps take it.
+pub fn new_secret() -> String {
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// RFC 4648 base32 without padding.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = buf.iter().fold(0u64, |bits, b| bits << 8 | u64::from(*b));
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            encoded.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// Decodes base32, ignoring case, spaces and padding. 'None' if it isn't.
+pub fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+        bits = (bits << 5 | value as u32) & 0xffff;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// The RFC 4226 code of 'secret' for 'counter'.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+    let truncated = u32::from_be_bytes([
+        hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]
+    ]);
+    truncated % 10u32.pow(DIGITS)
+}
+
+/// The time step 'now' is in, the RFC 6238 counter.
+pub fn step(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(PERIOD_SECS)
+}
+
+/// The code for 'step', zero padded.
+pub fn code(secret: &[u8], step: i64) -> String {
+    format!("{:0width$}", hotp(secret, step as u64), width = DIGITS as usize)
+}
+
+/**
+ * Checks 'code' against the base32 'secret' around 'now'.
+ *
+ * # Returns:
+ * - The step the code is for, which callers store so the code can't be
+ *   used again. 'None' if it doesn't match.
+ */
+pub fn matching_step(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let secret = base32_decode(secret)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let now = step(now);
+    (now - SKEW_STEPS..=now + SKEW_STEPS).find(|step| self::code(&secret, *step) == code)
+}
+
+/**
+ * The 'otpauth://' URI authenticator apps enroll from, usually as a QR code.
+ *
+ * # Parameters:
+ * - 'issuer': The service, shown with 'account' in the app.
+ */
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = percent_encode(issuer);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}\
+        &algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECS}",
+        percent_encode(account)
+    )
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+            (b as char).to_string()
+        },
+        _ => format!("%{b:02X}"),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::database::*;
+    use crate::tokens::{hash_recovery_code, new_recovery_code};
+
+    /// The SHA-1 secret of the RFC 6238 test vectors.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        // The RFC's 8 digit codes are 94287082, 07081804 and 89005924
+        assert_eq!(code(RFC_SECRET, step(at(59))), "287082");
+        assert_eq!(code(RFC_SECRET, step(at(1111111109))), "081804");
+        assert_eq!(code(RFC_SECRET, step(at(1234567890))), "005924");
+        assert_eq!(hotp(RFC_SECRET, 1), 287082);
+    }
+
+    #[test]
+    fn codes_of_neighbouring_steps_match() {
+        let secret = base32_encode(RFC_SECRET);
+        let now = at(1111111109);
+        let now_step = step(now);
+        for skew in -1..=1 {
+            let code = code(RFC_SECRET, now_step + skew);
+            assert_eq!(matching_step(&secret, &code, now), Some(now_step + skew));
+        }
+        for skew in [-2, 2] {
+            assert_eq!(matching_step(&secret, &code(RFC_SECRET, now_step + skew), now), None);
+        }
+        assert_eq!(matching_step(&secret, "081 804", now), Some(now_step));
+        assert_eq!(matching_step(&secret, "81804", now), None);
+        assert_eq!(matching_step("not base32!", "081804", now), None);
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        assert_eq!(base32_encode(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"fooba"), "MZXW6YTB");
+        for len in 0..=21 {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            assert_eq!(base32_decode(&base32_encode(&bytes)), Some(bytes));
+        }
+        assert_eq!(base32_decode("mzxw 6ytb oi======"), Some(b"foobar".to_vec()));
+        assert_eq!(base32_decode("MZ1W"), None);
+        assert_eq!(base32_decode(&new_secret()).map(|s| s.len()), Some(20));
+    }
+
+    /// 'user_id' with TOTP confirmed at 'step', and its recovery codes.
+    async fn enrolled(repo: &InMemoryRepository, user_id: i32, step: i64) -> Vec<String> {
+        let codes: Vec<String> = (0..3).map(|_| new_recovery_code()).collect();
+        let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+        repo.set_totp_secret(user_id, &new_secret(), Utc::now()).await.unwrap();
+        assert!(repo.confirm_totp(user_id, step, &hashes, Utc::now()).await.unwrap());
+        codes
+    }
+
+    #[actix_web::test]
+    async fn codes_are_not_replayed() {
+        let repo = InMemoryRepository::new().with_seed_users();
+        let user_id = repo.get_all_users().await.unwrap()[0].id;
+        let step = step(Utc::now());
+        enrolled(&repo, user_id, step).await;
+
+        // The code that confirmed it is used up, and so are earlier ones
+        assert!(!repo.use_totp_step(user_id, step).await.unwrap());
+        assert!(!repo.use_totp_step(user_id, step - 1).await.unwrap());
+        assert!(repo.use_totp_step(user_id, step + 1).await.unwrap());
+        assert!(!repo.use_totp_step(user_id, step + 1).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn recovery_codes_work_once() {
+        let repo = InMemoryRepository::new().with_seed_users();
+        let users = repo.get_all_users().await.unwrap();
+        let (user_id, other) = (users[0].id, users[1].id);
+        let codes = enrolled(&repo, user_id, step(Utc::now())).await;
+        enrolled(&repo, other, step(Utc::now())).await;
+
+        let hash = hash_recovery_code(&codes[0]);
+        assert!(!repo.use_recovery_code(other, &hash, Utc::now()).await.unwrap());
+        // Typed back in any case, with or without the dash
+        let typed = hash_recovery_code(&codes[0].to_uppercase().replace('-', ""));
+        assert!(repo.use_recovery_code(user_id, &typed, Utc::now()).await.unwrap());
+        assert!(!repo.use_recovery_code(user_id, &hash, Utc::now()).await.unwrap());
+        let hash = hash_recovery_code(&codes[1]);
+        assert!(repo.use_recovery_code(user_id, &hash, Utc::now()).await.unwrap());
+
+        // Turning TOTP off throws the rest away
+        assert!(repo.delete_totp(user_id).await.unwrap());
+        let hash = hash_recovery_code(&codes[2]);
+        assert!(!repo.use_recovery_code(user_id, &hash, Utc::now()).await.unwrap());
+    }
+}
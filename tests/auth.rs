@@ -0,0 +1,490 @@
+//! Rate limits, lockouts, sessions, emailed tokens and API keys of the auth
+//! routes, over the in-memory repository.
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+use common::*;
+use data_parser::database::*;
+use data_parser::mailer::{FileMailer, Mail, DEFAULT_FROM};
+use data_parser::rate_limit::{LimitGroup, LimitKey, RateLimiter};
+use data_parser::tokens::{hash_api_key_secret, TokenLifetimes};
+
+fn header(resp: &ServiceResponse, name: &str) -> Option<String> {
+    resp.headers().get(name).map(|value| value.to_str().unwrap().to_string())
+}
+
+#[actix_web::test]
+async fn rate_limited_requests_get_a_429() {
+    // Two requests at once, then one a minute
+    let limits = RateLimiter::new(Arc::new(InMemoryRateLimits::new()))
+        .with_policy(LimitGroup::Auth, Policy { burst: 2, per_minute: 1 }, LimitKey::Ip);
+    let app = init_app(test_state().with_rate_limits(limits)).await;
+    let check = |ip: &str| get("/check_user/ann&ann@x.io", None)
+        .peer_addr(format!("{ip}:4000").parse().unwrap()).to_request();
+
+    for remaining in ["1", "0"] {
+        let resp = test::call_service(&app, check("10.0.0.1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(header(&resp, "x-ratelimit-limit").as_deref(), Some("2"));
+        assert_eq!(header(&resp, "x-ratelimit-remaining").as_deref(), Some(remaining));
+        assert_eq!(header(&resp, RETRY_AFTER.as_str()), None);
+    }
+    let resp = test::call_service(&app, check("10.0.0.1")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&resp, RETRY_AFTER.as_str()).as_deref(), Some("60"));
+    assert_eq!(header(&resp, "x-ratelimit-remaining").as_deref(), Some("0"));
+    assert_eq!(header(&resp, "x-ratelimit-reset").as_deref(), Some("120"));
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!((body["error"].as_str(), body["retry_after"].as_i64()),
+        (Some("too_many_requests"), Some(60)));
+
+    // Other clients have buckets of their own, other routes aren't limited
+    let resp = test::call_service(&app, check("10.0.0.2")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, get("/get_hotel/1", None).to_request()).await;
+    assert_eq!(header(&resp, "x-ratelimit-limit"), None);
+}
+
+/// Locks accounts for a minute from the second failed login on.
+fn lockout_state() -> AppState {
+    test_state().with_lockout(LockoutPolicy {
+        threshold: 2, base: Duration::minutes(1), max: Duration::hours(1),
+    })
+}
+
+#[actix_web::test]
+async fn successful_logins_reset_failures() {
+    let state = lockout_state();
+    let app = init_app(state.clone()).await;
+    let (ann, _) = user_with_role(&app, &state, "ann", Role::User).await;
+
+    let resp = login_as(&app, "ann", "wrong password").await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(state.logins.get_login_lockout(ann).await.unwrap().unwrap().failed_logins, 1);
+    login(&app, "ann").await;
+    assert_eq!(state.logins.get_login_lockout(ann).await.unwrap(), None);
+
+    // So the next failure is the first again
+    login_as(&app, "ann", "wrong password").await;
+    login(&app, "ann").await;
+}
+
+#[actix_web::test]
+async fn locked_accounts_wait_or_get_unlocked() {
+    let state = lockout_state();
+    let app = init_app(state.clone()).await;
+    let (ann, _) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (_, admin_token) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let (_, user_token) = user_with_role(&app, &state, "ben", Role::User).await;
+
+    for _ in 0..2 {
+        let resp = login_as(&app, "ann", "wrong password").await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+    // Even the right password is refused while it's locked
+    let resp = login_as(&app, "ann", PASSWORD).await;
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+    assert_eq!(header(&resp, RETRY_AFTER.as_str()).as_deref(), Some("60"));
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!((body["error"].as_str(), body["retry_after"].as_i64()),
+        (Some("account_locked"), Some(60)));
+
+    let unlock = |token: &str| post(&format!("/unlock/{ann}"), Some(token)).to_request();
+    let resp = test::call_service(&app, unlock(&user_token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(login_as(&app, "ann", PASSWORD).await.status(), StatusCode::LOCKED);
+
+    let resp = test::call_service(&app, unlock(&admin_token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(state.logins.get_login_lockout(ann).await.unwrap(), None);
+    login(&app, "ann").await;
+}
+
+async fn refresh<S>(app: &S, refresh_token: &Value) -> ServiceResponse
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let req = post("/token/refresh", None)
+        .set_json(json!({ "refresh_token": refresh_token })).to_request();
+    test::call_service(app, req).await
+}
+
+async fn logout<S>(app: &S, refresh_token: &Value, all: bool) -> ServiceResponse
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let req = post("/logout", None)
+        .set_json(json!({ "refresh_token": refresh_token, "all": all })).to_request();
+    test::call_service(app, req).await
+}
+
+#[actix_web::test]
+async fn refresh_tokens_work_once() {
+    let app = init_app(test_state()).await;
+    register(&app, "ann").await;
+    let first = login(&app, "ann").await;
+    let other = login(&app, "ann").await;
+
+    let resp = refresh(&app, &first["refresh_token"]).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let second: Value = test::read_body_json(resp).await;
+    assert_ne!(second["refresh_token"], first["refresh_token"]);
+    assert!(second["token"].is_string() && second["expires_in"].as_i64() > Some(0));
+
+    // Reusing a rotated token looks like a stolen one, its whole session goes
+    let resp = refresh(&app, &first["refresh_token"]).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = refresh(&app, &second["refresh_token"]).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Other sessions of the user carry on
+    let resp = refresh(&app, &other["refresh_token"]).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = refresh(&app, &json!("not a token")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn logging_out_ends_sessions() {
+    let app = init_app(test_state()).await;
+    register(&app, "ann").await;
+    let first = login(&app, "ann").await;
+    let second = login(&app, "ann").await;
+    let third = login(&app, "ann").await;
+
+    let resp = logout(&app, &first["refresh_token"], false).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let revoked: Value = test::read_body_json(resp).await;
+    assert_eq!(revoked["revoked"], 1);
+    assert_eq!(refresh(&app, &first["refresh_token"]).await.status(), StatusCode::UNAUTHORIZED);
+    let resp = logout(&app, &first["refresh_token"], false).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Logging out everywhere takes the other sessions with it
+    let resp = refresh(&app, &second["refresh_token"]).await;
+    let rotated: Value = test::read_body_json(resp).await;
+    let resp = logout(&app, &rotated["refresh_token"], true).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    for tokens in [&rotated, &third] {
+        let resp = refresh(&app, &tokens["refresh_token"]).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+/// 'state' writing its emails to 'mails.txt' in 'dir'.
+fn mailing_state(state: AppState, dir: &Path) -> (AppState, PathBuf) {
+    let path = dir.join("mails.txt");
+    let mail = Mail {
+        mailer: Arc::new(FileMailer::file(DEFAULT_FROM, path.clone())),
+        link_url: "http://hotels.test".to_string(),
+    };
+    (state.with_mail(mail), path)
+}
+
+/// The token of the latest link in the emails, waiting for it as password
+/// reset emails are sent in the background.
+async fn emailed_token(path: &Path, page: &str) -> String {
+    let link = format!("http://hotels.test/{page}?token=");
+    for _ in 0..100 {
+        let mails = std::fs::read_to_string(path).unwrap_or_default();
+        if let Some((_, rest)) = mails.rsplit_once(&link) {
+            return rest.split_whitespace().next().unwrap().to_string();
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("no {page} link in {}", path.display());
+}
+
+fn confirm(uri: &str, body: Value) -> Request {
+    post(uri, None).set_json(body).to_request()
+}
+
+#[actix_web::test]
+async fn emailed_links_verify_the_address_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, mails) = mailing_state(test_state(), dir.path());
+    let app = init_app(state.clone()).await;
+    let (ann, token) = user_with_role(&app, &state, "ann", Role::User).await;
+    let verified_at = || async {
+        state.users.get_account(ann).await.unwrap().unwrap().email_verified_at
+    };
+    let request = || post("/verify_email/request", Some(&token)).to_request();
+
+    assert_eq!(test::call_service(&app, request()).await.status(), StatusCode::OK);
+    let email_token = emailed_token(&mails, "verify_email").await;
+    assert!(std::fs::read_to_string(&mails).unwrap().contains("To: ann@x.io\n"));
+    assert_eq!(verified_at().await, None);
+
+    let body = json!({ "token": email_token });
+    let resp = test::call_service(&app, confirm("/verify_email/confirm", body.clone())).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(verified_at().await.is_some());
+    let resp = test::call_service(&app, confirm("/verify_email/confirm", body.clone())).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::call_service(&app, request()).await.status(), StatusCode::CONFLICT);
+    // Nor is it a password reset token
+    let resp = test::call_service(&app, confirm("/password_reset/confirm",
+        json!({ "token": email_token, "new_password": "battery staple" }))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Keeping the email keeps it verified, a new one has to be verified again
+    let update = |email: &str| post("/update", Some(&token))
+        .set_form([("id", ann.to_string()), ("username", "ann".to_string()),
+            ("email", email.to_string())])
+        .to_request();
+    assert_eq!(test::call_service(&app, update("ann@x.io")).await.status(), StatusCode::OK);
+    assert!(verified_at().await.is_some());
+    assert_eq!(test::call_service(&app, update("ann@y.io")).await.status(), StatusCode::OK);
+    assert_eq!(verified_at().await, None);
+
+    // A link sent to the old address doesn't verify the new one
+    assert_eq!(test::call_service(&app, update("ann@x.io")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, request()).await.status(), StatusCode::OK);
+    let old_token = emailed_token(&mails, "verify_email").await;
+    assert_eq!(test::call_service(&app, update("ann@z.io")).await.status(), StatusCode::OK);
+    let resp = test::call_service(&app,
+        confirm("/verify_email/confirm", json!({ "token": old_token }))).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(verified_at().await, None);
+}
+
+#[actix_web::test]
+async fn expired_email_tokens_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = TokenLifetimes {
+        email_verification: Duration::minutes(-5), ..TokenLifetimes::default()
+    };
+    let (state, mails) = mailing_state(test_state().with_tokens(tokens), dir.path());
+    let app = init_app(state.clone()).await;
+    let (ann, token) = user_with_role(&app, &state, "ann", Role::User).await;
+
+    let resp = test::call_service(&app,
+        post("/verify_email/request", Some(&token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let email_token = emailed_token(&mails, "verify_email").await;
+    let resp = test::call_service(&app,
+        confirm("/verify_email/confirm", json!({ "token": email_token }))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(state.users.get_account(ann).await.unwrap().unwrap().email_verified_at, None);
+    let resp = test::call_service(&app,
+        confirm("/verify_email/confirm", json!({ "token": "not.a.token" }))).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn password_resets_log_out_and_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let (state, mails) = mailing_state(test_state(), dir.path());
+    let app = init_app(state.clone()).await;
+    register(&app, "ann").await;
+    let session = login(&app, "ann").await;
+    let reset = |email: &str| confirm("/password_reset/request", json!({ "email": email }));
+
+    // Unknown addresses get the same answer, and no email
+    assert_eq!(test::call_service(&app, reset("nobody@x.io")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, reset("ann@x.io")).await.status(), StatusCode::OK);
+    let email_token = emailed_token(&mails, "reset_password").await;
+    assert!(!std::fs::read_to_string(&mails).unwrap().contains("nobody@x.io"));
+
+    let new_password = |password: &str| confirm("/password_reset/confirm",
+        json!({ "token": email_token, "new_password": password }));
+    // A weak password doesn't use up the token
+    let resp = test::call_service(&app, new_password("short")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, new_password("battery staple")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, new_password("another staple")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(login_as(&app, "ann", PASSWORD).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(login_as(&app, "ann", "battery staple").await.status(), StatusCode::OK);
+    let resp = refresh(&app, &session["refresh_token"]).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let ann = state.users.get_user_by_creds("ann", "").await.unwrap().unwrap().id;
+    assert!(state.users.get_account(ann).await.unwrap().unwrap().email_verified_at.is_some());
+}
+
+/// Makes key 'hk_<prefix>_secret' of 'created_by' in the repository, to set
+/// what the route doesn't take.
+async fn insert_api_key(state: &AppState, prefix: &str, scopes: &[Scope], created_by: i32,
+    expires_at: Option<chrono::DateTime<Utc>>
+) -> String {
+    state.api_keys.add_api_key(&NewApiKey {
+        name: prefix.to_string(), prefix: prefix.to_string(),
+        secret_hash: hash_api_key_secret("secret"), scopes: Scopes(scopes.to_vec()),
+        created_by, created_at: Utc::now() - Duration::days(1), expires_at,
+    }).await.unwrap();
+    format!("hk_{prefix}_secret")
+}
+
+fn with_key(req: test::TestRequest, key: &str) -> Request {
+    req.insert_header(("X-Api-Key", key)).to_request()
+}
+
+#[actix_web::test]
+async fn admins_create_list_and_revoke_api_keys() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (_, user_token) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (admin, admin_token) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let create = |token: &str, body: Value| post("/api_keys", Some(token))
+        .set_json(body).to_request();
+
+    let body = json!({ "name": "nightly", "scopes": ["users:read", "hotels:read"] });
+    let resp = test::call_service(&app, create(&user_token, body.clone())).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    for bad in [
+        json!({ "name": "nightly", "scopes": [] }),
+        json!({
+            "name": "nightly", "scopes": ["hotels:read"], "expires_at": "2001-01-01T00:00:00Z",
+        }),
+    ] {
+        let resp = test::call_service(&app, create(&admin_token, bad)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    let resp = test::call_service(&app,
+        create(&admin_token, json!({ "name": "nightly", "scopes": ["hotels:write"] }))).await;
+    assert!(resp.status().is_client_error());
+
+    let created: Value = test::call_and_read_body_json(&app, create(&admin_token, body)).await;
+    let key = created["key"].as_str().unwrap();
+    assert!(key.starts_with(&format!("hk_{}_", created["prefix"].as_str().unwrap())));
+    assert_eq!(created["scopes"], json!(["hotels:read", "users:read"]));
+    assert_eq!(created["created_by"], admin);
+    assert!(created.get("secret_hash").is_none());
+
+    let resp = test::call_service(&app, get("/api_keys", Some(&user_token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let keys: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/api_keys", Some(&admin_token)).to_request()).await;
+    assert_eq!(keys.len(), 1);
+    assert_eq!((keys[0]["name"].clone(), keys[0]["revoked_at"].clone()),
+        (json!("nightly"), Value::Null));
+    assert!(!serde_json::to_string(&keys).unwrap().contains(&key[key.len() - 16..]));
+
+    let revoke = |id: &Value, token: &str| {
+        post(&format!("/api_keys/{id}/revoke"), Some(token)).to_request()
+    };
+    let id = &created["id"];
+    assert_eq!(test::call_service(&app, revoke(id, &user_token)).await.status(),
+        StatusCode::FORBIDDEN);
+    assert_eq!(test::call_service(&app, revoke(id, &admin_token)).await.status(),
+        StatusCode::OK);
+    assert_eq!(test::call_service(&app, revoke(id, &admin_token)).await.status(),
+        StatusCode::NOT_FOUND);
+    let keys: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/api_keys", Some(&admin_token)).to_request()).await;
+    assert!(keys[0]["revoked_at"].is_string());
+    let resp = test::call_service(&app, with_key(get("/get_all_users", None), key)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn api_keys_are_checked_and_their_use_recorded() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (admin, _) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let key = insert_api_key(&state, "aaaaaa", &[Scope::UsersRead], admin, None).await;
+    let expired = insert_api_key(&state, "bbbbbb", &[Scope::UsersRead], admin,
+        Some(Utc::now() - Duration::minutes(1))).await;
+    let revoked = insert_api_key(&state, "cccccc", &[Scope::UsersRead], admin, None).await;
+    let revoked_id = state.api_keys.get_api_key("cccccc").await.unwrap().unwrap().id;
+    state.api_keys.revoke_api_key(revoked_id, Utc::now()).await.unwrap();
+
+    let users: Vec<Value> = test::call_and_read_body_json(&app,
+        with_key(get("/get_all_users", None), &key)).await;
+    assert_eq!(users.len(), 7);
+    let used = state.api_keys.get_api_key("aaaaaa").await.unwrap().unwrap();
+    assert!(used.last_used_at.is_some_and(|at| Utc::now() - at < Duration::minutes(1)));
+
+    for bad in [
+        expired.as_str(), revoked.as_str(), "hk_aaaaaa_wrong", "aaaaaa_secret", "hk_aaaaaa",
+        "hk_dddddd_secret", "",
+    ] {
+        let resp = test::call_service(&app, with_key(get("/get_all_users", None), bad)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "key '{bad}'");
+    }
+    let unused = state.api_keys.get_api_key("bbbbbb").await.unwrap().unwrap();
+    assert_eq!(unused.last_used_at, None);
+
+    // Reading is public, but not for keys without the scope
+    let resp = test::call_service(&app, get("/get_hotel/1", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, with_key(get("/get_hotel/1", None), &key)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!(denied, json!({
+        "error": "forbidden", "message": "Requires an API key with the scope hotels:read",
+        "scopes": ["users:read"], "required_scope": "hotels:read",
+    }));
+    let resp = test::call_service(&app,
+        with_key(get("/get_hotel/1", None), "hk_aaaaaa_wrong")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn keys_without_the_scope_get_a_403() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (admin, _) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let (ann, _) = user_with_role(&app, &state, "ann", Role::User).await;
+    let key = insert_api_key(&state, "aaaaaa", &[Scope::UsersRead], admin, None).await;
+
+    let resp = test::call_service(&app,
+        with_key(get(&format!("/delete/{ann}"), None), &key)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!(denied, json!({
+        "error": "forbidden", "message": "Requires an API key with the scope users:write",
+        "scopes": ["users:read"], "required_scope": "users:write",
+    }));
+    assert!(state.users.get_user(ann).await.unwrap().is_some());
+
+    let resp = test::call_service(&app, with_key(post("/add_user_review", None)
+        .set_json(json!({ "hotel_id": 1, "title": "Batch", "text": "Imported" })), &key)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!(denied["required_scope"], "reviews:write");
+
+    // Routes that act as a user don't take keys
+    let resp = test::call_service(&app, with_key(post("/change_password", None)
+        .set_json(json!({ "old_password": PASSWORD, "new_password": "battery staple" })),
+        &key)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn keys_add_reviews_as_their_creator() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (admin, _) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let (ann, _) = user_with_role(&app, &state, "ann", Role::User).await;
+    let scopes = [Scope::ReviewsRead, Scope::ReviewsWrite];
+    let key = insert_api_key(&state, "aaaaaa", &scopes, admin, None).await;
+    let add = |key: &str| with_key(post("/add_user_review", None).set_json(json!({
+        "user_id": ann, "hotel_id": 2, "title": "Batch", "text": "Imported", "rating": 3,
+    })), key);
+
+    assert_eq!(test::call_service(&app, add(&key)).await.status(), StatusCode::OK);
+    let reviews: Vec<Value> = test::call_and_read_body_json(&app,
+        with_key(get("/get_user_reviews_by_hotel_id/2", None), &key)).await;
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0]["user_id"], admin);
+
+    // Keys outlive their creator, but can't act as them anymore
+    let ann_key = insert_api_key(&state, "bbbbbb", &scopes, ann, None).await;
+    state.users.delete_user(ann).await.unwrap();
+    let resp = test::call_service(&app, add(&ann_key)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!(denied["message"], "The API key's creator was deleted, it can't act as a user");
+}
@@ -0,0 +1,120 @@
+//! What the handler tests share: an in-memory 'AppState' with a couple of
+//! hotels, the app serving it, and users to call it as.
+#![allow(dead_code)]
+
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{test, web, App};
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+use data_parser::database::*;
+use data_parser::passwords::Passwords;
+use data_parser::routes::configure;
+
+pub const PASSWORD: &str = "correct horse";
+
+pub fn hotel(hotel_id: i32, name: &str) -> Hotel {
+    Hotel {
+        hotel_id, name: name.to_string(), address: "1 Main St".to_string(),
+        city: "Toronto".to_string(), province: "ON".to_string(),
+        country: "CA".to_string(),
+    }
+}
+
+/// The 'n'-th review of a hotel, submitted on day 'n' of 2016.
+pub fn review(hotel_id: i32, n: usize, rating: i32) -> Review {
+    Review {
+        hotel_id,
+        review_id: format!("{hotel_id}-{n}"),
+        rating,
+        author: "guest".to_string(),
+        title: "Stay".to_string(),
+        text: "Nice enough".to_string(),
+        time: Utc.with_ymd_and_hms(2016, 1, 1, 0, 0, 0).unwrap()
+            + chrono::Duration::days(n as i64),
+    }
+}
+
+/**
+ * The seed users, hotel 1 with three reviews and hotel 2 with none. Hashes
+ * are cheap, as tests hash a lot of passwords in debug builds.
+ */
+pub fn test_state() -> AppState {
+    let repo = InMemoryRepository::new().with_seed_users();
+    repo.insert_hotel(hotel(1, "Harbour Inn"));
+    repo.insert_hotel(hotel(2, "Lakeview Hotel"));
+    for (n, rating) in [4, 2, 5].into_iter().enumerate() {
+        repo.insert_review(review(1, n, rating));
+    }
+    AppState::in_memory(repo).with_passwords(Passwords::new(8, 1, 1).unwrap())
+}
+
+pub async fn init_app(state: AppState
+) -> impl Service<Request, Response = ServiceResponse, Error = actix_web::Error> {
+    test::init_service(
+        App::new().app_data(web::Data::new(state)).configure(configure)
+    ).await
+}
+
+/// Registers 'username' with 'PASSWORD'.
+pub async fn register<S>(app: &S, username: &str)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let req = test::TestRequest::post().uri("/add_user").set_json(json!({
+        "username": username, "email": format!("{username}@x.io"), "password": PASSWORD,
+    })).to_request();
+    let resp = test::call_service(app, req).await;
+    assert!(resp.status().is_success(), "registering {username}: {}", resp.status());
+}
+
+pub async fn login_as<S>(app: &S, username: &str, password: &str) -> ServiceResponse
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let req = test::TestRequest::post().uri("/login")
+        .set_json(json!({ "username": username, "password": password })).to_request();
+    test::call_service(app, req).await
+}
+
+/// Logs in with 'PASSWORD', returning the token pair.
+pub async fn login<S>(app: &S, username: &str) -> Value
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let resp = login_as(app, username, PASSWORD).await;
+    assert!(resp.status().is_success(), "logging in {username}: {}", resp.status());
+    test::read_body_json(resp).await
+}
+
+/// Registers 'username' with 'role' and logs them in, returning their id and
+/// access token.
+pub async fn user_with_role<S>(app: &S, state: &AppState, username: &str, role: Role
+) -> (i32, String)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    register(app, username).await;
+    let user = state.users.get_user_by_creds(username, "").await.unwrap().unwrap();
+    state.users.set_role(user.id, role).await.unwrap();
+    let tokens = login(app, username).await;
+    (user.id, tokens["token"].as_str().unwrap().to_string())
+}
+
+pub fn get(uri: &str, token: Option<&str>) -> test::TestRequest {
+    let req = test::TestRequest::get().uri(uri);
+    match token {
+        Some(token) => req.insert_header((AUTHORIZATION, token)),
+        None => req,
+    }
+}
+
+pub fn post(uri: &str, token: Option<&str>) -> test::TestRequest {
+    let req = test::TestRequest::post().uri(uri);
+    match token {
+        Some(token) => req.insert_header((AUTHORIZATION, token)),
+        None => req,
+    }
+}
@@ -0,0 +1,101 @@
+//! The dataset generator: the same seed gives the same data and files, which
+//! the parser reads back as they were generated.
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use serde_json::{json, Value};
+
+use data_parser::generator::*;
+use data_parser::hotels_info::{Hotel, Review};
+use data_parser::parser::mt_processing;
+
+fn options(seed: u64) -> GeneratorOptions {
+    GeneratorOptions {
+        seed, hotels: 12, reviews_per_hotel: ReviewCount::Exponential { mean: 6.0 },
+        users: 5, user_reviews_per_user: (1, 3), ..GeneratorOptions::default()
+    }
+}
+
+/// Everything one generator makes, in the order the 'generate' binary asks
+/// for it, as JSON.
+fn generate(seed: u64) -> Value {
+    let mut generator = Generator::new(options(seed));
+    let hotels = generator.hotels();
+    let reviews = generator.reviews(&hotels);
+    let users: Vec<Value> = generator.users().into_iter()
+        .map(|u| json!([u.username, u.email])).collect();
+    let hotel_ids: Vec<i32> = hotels.iter().map(|h| h.hotel_id).collect();
+    let user_reviews: Vec<Value> = generator.user_reviews(&[1, 2, 3, 4, 5], &hotel_ids)
+        .into_iter().map(|r| json!([r.user_id, r.hotel_id, r.title, r.text, r.rating]))
+        .collect();
+    json!({
+        "hotels": hotels, "reviews": reviews.into_iter().collect::<BTreeMap<_, _>>(),
+        "users": users, "user_reviews": user_reviews,
+    })
+}
+
+/// The files of 'dir' with their contents, by name.
+fn read_dir(dir: &Path) -> BTreeMap<String, String> {
+    fs::read_dir(dir).unwrap().map(|entry| {
+        let path = entry.unwrap().path();
+        (path.file_name().unwrap().to_string_lossy().into_owned(),
+            fs::read_to_string(&path).unwrap())
+    }).collect()
+}
+
+#[test]
+fn the_same_seed_generates_the_same_data() {
+    let first = generate(7);
+    assert_eq!(first, generate(7));
+    assert_ne!(first, generate(8));
+
+    assert_eq!(first["hotels"].as_array().unwrap().len(), 12);
+    assert_eq!(first["users"].as_array().unwrap().len(), 5);
+    let user_reviews = first["user_reviews"].as_array().unwrap().len();
+    assert!((5..=15).contains(&user_reviews), "{user_reviews} user reviews");
+}
+
+#[test]
+fn the_same_seed_writes_the_same_files() {
+    let dir = tempfile::tempdir().unwrap();
+    for run in ["a", "b"] {
+        let mut generator = Generator::new(options(7));
+        let hotels = generator.hotels();
+        let reviews = generator.reviews(&hotels);
+        let out = dir.path().join(run);
+        write_hotels(&out.join("hotels"), &hotels, 5).unwrap();
+        write_reviews(&out.join("reviews"), &reviews, 4).unwrap();
+    }
+    for data in ["hotels", "reviews"] {
+        let a = read_dir(&dir.path().join("a").join(data));
+        assert!(!a.is_empty());
+        assert_eq!(a, read_dir(&dir.path().join("b").join(data)), "{data}");
+    }
+}
+
+#[test]
+fn generated_files_parse_back_to_the_generated_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut generator = Generator::new(options(7));
+    let hotels = generator.hotels();
+    let reviews = generator.reviews(&hotels);
+    let (hotels_dir, reviews_dir) = (dir.path().join("hotels"), dir.path().join("reviews"));
+    write_hotels(&hotels_dir, &hotels, 5).unwrap();
+    write_reviews(&reviews_dir, &reviews, 4).unwrap();
+
+    let (parsed_hotels, parsed_reviews) = mt_processing(
+        reviews_dir.to_str().unwrap(), hotels_dir.to_str().unwrap()
+    );
+    let by_id = |hotels: Vec<Hotel>| -> BTreeMap<i32, Value> {
+        hotels.into_iter().map(|h| (h.hotel_id, json!(h))).collect()
+    };
+    assert_eq!(by_id(parsed_hotels.into_values().collect()), by_id(hotels));
+    let sorted = |mut reviews: Vec<Review>| -> Value {
+        reviews.sort_by(|a, b| a.review_id.cmp(&b.review_id));
+        json!(reviews)
+    };
+    let sorted_all = |reviews: HashMap<i32, Vec<Review>>| {
+        reviews.into_iter().map(|(id, r)| (id, sorted(r))).collect::<BTreeMap<_, _>>()
+    };
+    assert_eq!(sorted_all(parsed_reviews), sorted_all(reviews));
+}
@@ -0,0 +1,229 @@
+//! Runs the Postgres backend against a live database:
+//! DATABASE_URL=postgres://postgres@localhost/hotels_test cargo t --features postgres
+//!
+//! Each test works in a schema of its own, dropped and created again on every
+//! run, so they don't get in each other's way or touch the database's tables.
+//! Without a 'postgres://' DATABASE_URL they pass without doing anything.
+#![cfg(feature = "postgres")]
+mod common;
+
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use chrono::{TimeZone, Utc};
+use futures::future::join_all;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+
+use common::*;
+use data_parser::database::postgres::PgRepository;
+use data_parser::database::*;
+
+async fn test_pool(schema: &str) -> Option<PgPool> {
+    let url = env::var("DATABASE_URL").ok()
+        .filter(|url| url.starts_with("postgres:") || url.starts_with("postgresql:"));
+    let Some(url) = url else {
+        eprintln!("DATABASE_URL isn't a Postgres URL, skipping");
+        return None;
+    };
+
+    let admin = PgPool::connect(&url).await.expect("connecting to DATABASE_URL");
+    let recreate = [
+        format!("drop schema if exists {schema} cascade"),
+        format!("create schema {schema}"),
+    ];
+    for stmt in recreate {
+        sqlx::query(&stmt).execute(&admin).await.unwrap();
+    }
+    admin.close().await;
+
+    let options = PgConnectOptions::from_str(&url).unwrap()
+        .options([("search_path", schema)]);
+    Some(PgPoolOptions::new().max_connections(8).connect_with(options).await.unwrap())
+}
+
+#[actix_web::test]
+async fn migrations_go_up_and_down() {
+    let Some(pool) = test_pool("test_migrations").await else { return };
+    let latest = latest_version(&pool);
+
+    assert!(matches!(check_schema(&pool).await.unwrap(), SchemaCheck::Pending(_)));
+    let applied = migrate_up(&pool, None).await.unwrap();
+    assert_eq!(applied, (1..=latest).collect::<Vec<_>>());
+    assert_eq!(check_schema(&pool).await.unwrap(), SchemaCheck::UpToDate);
+    assert!(migrate_up(&pool, None).await.unwrap().is_empty());
+
+    assert_eq!(migrate_down(&pool, 2).await.unwrap(), vec![latest, latest - 1]);
+    match check_schema(&pool).await.unwrap() {
+        SchemaCheck::Pending(pending) => assert_eq!(pending.len(), 2),
+        check => panic!("expected pending migrations, got {check:?}"),
+    }
+
+    // Every down has to undo its up for the whole way down and back to work
+    assert_eq!(migrate_down(&pool, usize::MAX).await.unwrap().len(), latest as usize - 2);
+    assert_eq!(migrate_up(&pool, None).await.unwrap().len(), latest as usize);
+    assert_eq!(check_schema(&pool).await.unwrap(), SchemaCheck::UpToDate);
+}
+
+#[actix_web::test]
+async fn migrations_ahead_of_the_binary_are_reported() {
+    let Some(pool) = test_pool("test_migrations_ahead").await else { return };
+    migrate_up(&pool, None).await.unwrap();
+    sqlx::query("insert into schema_migrations(version, name) values (9999, 'future')")
+        .execute(&pool).await.unwrap();
+
+    assert_eq!(check_schema(&pool).await.unwrap(),
+        SchemaCheck::Ahead(vec!["9999 (future)".to_string()]));
+    assert!(migrate_down(&pool, 1).await.is_err());
+}
+
+#[actix_web::test]
+async fn users_crud() {
+    let Some(pool) = test_pool("test_users").await else { return };
+    migrate_up(&pool, None).await.unwrap();
+    let repo = PgRepository::new(pool);
+
+    repo.add_user(&NewUser {
+        username: "ann".to_string(), email: "ann@x.io".to_string(),
+        password_hash: Some("$argon2id$hash".to_string()),
+    }).await.unwrap();
+    let user = repo.get_user_by_creds("ann", "").await.unwrap().unwrap();
+    assert_eq!(user.email, "ann@x.io");
+    assert_eq!(user.role, Role::User);
+    assert_eq!(repo.get_user_by_creds("", "ann@x.io").await.unwrap().unwrap().id, user.id);
+    assert!(repo.add_user(&NewUser {
+        username: "ann".to_string(), email: "other@x.io".to_string(), password_hash: None,
+    }).await.is_err());
+
+    let account = repo.get_account(user.id).await.unwrap().unwrap();
+    assert_eq!(account.password_hash.as_deref(), Some("$argon2id$hash"));
+    assert!(repo.set_role(user.id, Role::Admin).await.unwrap());
+    assert!(!repo.set_role(user.id + 1000, Role::Admin).await.unwrap());
+    assert!(repo.set_email_verified(user.id, "ann@x.io", Utc::now()).await.unwrap());
+    assert!(!repo.set_email_verified(user.id, "old@x.io", Utc::now()).await.unwrap());
+
+    // Keeping the email keeps it verified, a new one has to be verified again
+    repo.update_user(&User { username: "annie".to_string(), ..user.clone() }).await.unwrap();
+    assert!(repo.get_account(user.id).await.unwrap().unwrap().email_verified_at.is_some());
+    repo.update_user(&User { email: "ann@y.io".to_string(), ..user.clone() }).await.unwrap();
+    let account = repo.get_account(user.id).await.unwrap().unwrap();
+    assert_eq!((account.email.as_str(), account.role), ("ann@y.io", Role::Admin));
+    assert_eq!(account.email_verified_at, None);
+
+    assert_eq!(repo.get_all_users().await.unwrap().len(), 1);
+    repo.delete_user(user.id).await.unwrap();
+    assert!(repo.get_user(user.id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn hotels_and_reviews_crud() {
+    let Some(pool) = test_pool("test_hotels").await else { return };
+    migrate_up(&pool, None).await.unwrap();
+    pool.add_users().await.unwrap();
+    let opts = LoadOptions::default();
+    pool.add_hotels_data(HashMap::from([
+        (1, hotel(1, "Harbour Inn")), (2, hotel(2, "Lakeview Hotel")),
+    ]), &opts).await.unwrap();
+    pool.add_reviews_data(HashMap::from([
+        (1, vec![review(1, 0, 4), review(1, 1, 2), review(1, 2, 5)]),
+    ]), &opts).await.unwrap();
+    let repo = PgRepository::new(pool);
+
+    assert_eq!(repo.get_hotel(1).await.unwrap().unwrap().name, "Harbour Inn");
+    assert!(repo.get_hotel(3).await.unwrap().is_none());
+    assert_eq!(repo.get_all_hotels().await.unwrap().len(), 2);
+    let like = repo.get_like_hotels("view").await.unwrap();
+    assert_eq!(like.iter().map(|h| h.hotel_id).collect::<Vec<_>>(), vec![2]);
+
+    let newest_first = repo.get_hotel_reviews(1, &ReviewFilter {
+        from: Some(Utc.with_ymd_and_hms(2016, 1, 2, 0, 0, 0).unwrap()),
+        to: None,
+        order: Some(SortOrder::Desc),
+    }).await.unwrap();
+    assert_eq!(newest_first.iter().map(|r| r.review_id.as_str()).collect::<Vec<_>>(),
+        vec!["1-2", "1-1"]);
+
+    let user = repo.get_user_by_creds("chet", "").await.unwrap().unwrap();
+    repo.add_user_review(&NewUserReview {
+        user_id: user.id, hotel_id: 2, title: "Quiet".to_string(),
+        text: "Slept well".to_string(), rating: Some(3),
+    }).await.unwrap();
+    let added = repo.get_user_reviews_by_username("chet").await.unwrap();
+    assert_eq!(added.len(), 1);
+    assert_eq!(repo.get_user_reviews_by_hotel_id(2).await.unwrap().len(), 1);
+    assert_eq!(repo.get_all_user_reviews().await.unwrap().len(), 1);
+    let summary = repo.get_rating_summary(2).await.unwrap().unwrap();
+    assert_eq!((summary.review_count, summary.rating_sum), (1, 3));
+
+    let review_id = added[0].review_id;
+    assert_eq!(repo.get_user_review(review_id).await.unwrap().unwrap().title, "Quiet");
+    repo.delete_user_review(review_id).await.unwrap();
+    assert!(repo.get_user_review(review_id).await.unwrap().is_none());
+    assert!(repo.get_rating_summary(2).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn concurrent_review_batches_keep_summaries_right() {
+    let Some(pool) = test_pool("test_loader").await else { return };
+    migrate_up(&pool, None).await.unwrap();
+    let hotels: HashMap<i32, Hotel> = (1..=5).map(|id| (id, hotel(id, "Inn"))).collect();
+    // Small batches, so each hotel's reviews are split across several
+    let reviews: HashMap<i32, Vec<Review>> = (1..=5)
+        .map(|id| (id, (0..40).map(|n| review(id, n, n as i32 % 5 + 1)).collect()))
+        .collect();
+    let opts = LoadOptions { batch_size: 7, concurrency: 8, ..Default::default() };
+
+    pool.add_hotels_data(hotels, &opts).await.unwrap();
+    let report = pool.add_reviews_data(reviews.clone(), &opts).await.unwrap();
+    assert_eq!((report.rows.inserted, report.failed_batches), (200, 0));
+
+    let repo = PgRepository::new(pool.clone());
+    for id in 1..=5 {
+        let summary = repo.get_rating_summary(id).await.unwrap().unwrap();
+        assert_eq!((summary.review_count, summary.rating_sum), (40, 120));
+        assert_eq!(summary.rating_1, 8);
+    }
+    assert!(repo.rebuild_rating_summaries().await.unwrap().is_empty());
+
+    // Loading it all again only works as an upsert, and changes nothing
+    let report = pool.add_reviews_data(reviews.clone(), &opts).await.unwrap();
+    assert_eq!(report.failed_batches, report.batches);
+    let upsert = LoadOptions { mode: LoadMode::Upsert, ..opts };
+    let report = pool.add_reviews_data(reviews, &upsert).await.unwrap();
+    assert_eq!((report.rows.unchanged, report.failed_batches), (200, 0));
+    assert!(repo.rebuild_rating_summaries().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn concurrent_user_review_writes_keep_summaries_right() {
+    let Some(pool) = test_pool("test_user_reviews").await else { return };
+    migrate_up(&pool, None).await.unwrap();
+    pool.add_users().await.unwrap();
+    pool.add_hotels_data(HashMap::from([(1, hotel(1, "Harbour Inn"))]),
+        &LoadOptions::default()).await.unwrap();
+    let repo = PgRepository::new(pool);
+    let users = repo.get_all_users().await.unwrap();
+    let new_review = |n: usize| NewUserReview {
+        user_id: users[n % users.len()].id, hotel_id: 1, title: "Stay".to_string(),
+        text: "Nice enough".to_string(), rating: (!n.is_multiple_of(6)).then_some((n % 6) as i32),
+    };
+    for n in 0..10 {
+        repo.add_user_review(&new_review(n)).await.unwrap();
+    }
+    let existing = repo.get_user_reviews_by_hotel_id(1).await.unwrap();
+
+    // Every write refreshes the same summary row
+    let adds: Vec<NewUserReview> = (10..30).map(new_review).collect();
+    let (added, deleted) = futures::join!(
+        join_all(adds.iter().map(|review| repo.add_user_review(review))),
+        join_all(existing.iter().map(|r| repo.delete_user_review(r.review_id))),
+    );
+    for result in added.into_iter().chain(deleted) {
+        result.unwrap();
+    }
+
+    let summary = repo.get_rating_summary(1).await.unwrap().unwrap();
+    let rated = adds.iter().filter(|review| review.rating.is_some()).count() as i32;
+    assert_eq!(summary.review_count, rated);
+    assert!(repo.rebuild_rating_summaries().await.unwrap().is_empty());
+}
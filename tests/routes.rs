@@ -0,0 +1,324 @@
+//! The hotel, review and user routes, over the in-memory repository.
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::{json, Value};
+
+use common::*;
+use data_parser::database::*;
+
+#[actix_web::test]
+async fn hotels_are_found_by_id_and_name() {
+    let app = init_app(test_state()).await;
+
+    let hotel: Value =
+        test::call_and_read_body_json(&app, get("/get_hotel/1", None).to_request()).await;
+    assert_eq!(hotel["name"], "Harbour Inn");
+    let resp = test::call_service(&app, get("/get_hotel/3", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let hotels: Vec<Value> =
+        test::call_and_read_body_json(&app, get("/get_all_hotels", None).to_request()).await;
+    assert_eq!(hotels.len(), 2);
+    let like: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/get_like_hotels/view", None).to_request()).await;
+    assert_eq!(like.iter().map(|h| h["hotel_id"].clone()).collect::<Vec<_>>(), vec![json!(2)]);
+}
+
+#[actix_web::test]
+async fn hotel_rating_sums_up_its_reviews() {
+    let app = init_app(test_state()).await;
+
+    let rating: Value =
+        test::call_and_read_body_json(&app, get("/get_hotel_rating/1", None).to_request()).await;
+    assert_eq!(rating["review_count"], 3);
+    assert_eq!(rating["rating_sum"], 11);
+    assert_eq!((rating["rating_2"].clone(), rating["rating_3"].clone()), (json!(1), json!(0)));
+    assert!((rating["average"].as_f64().unwrap() - 11.0 / 3.0).abs() < 1e-9);
+
+    let resp = test::call_service(&app, get("/get_hotel_rating/2", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn hotel_reviews_are_filtered_and_sorted() {
+    let app = init_app(test_state()).await;
+    let ids = |reviews: Vec<Value>| -> Vec<String> {
+        reviews.iter().map(|r| r["review_id"].as_str().unwrap().to_string()).collect()
+    };
+
+    let reviews: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/get_hotel_reviews/1?order=asc", None).to_request()).await;
+    assert_eq!(ids(reviews), vec!["1-0", "1-1", "1-2"]);
+
+    let uri = "/get_hotel_reviews/1?from=2016-01-02T00:00:00Z&order=desc";
+    let reviews: Vec<Value> =
+        test::call_and_read_body_json(&app, get(uri, None).to_request()).await;
+    assert_eq!(ids(reviews), vec!["1-2", "1-1"]);
+
+    let reviews: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/get_hotel_reviews/1?to=2016-01-02T00:00:00Z", None).to_request()).await;
+    assert_eq!(ids(reviews), vec!["1-0"]);
+
+    let reviews: Vec<Value> =
+        test::call_and_read_body_json(&app, get("/get_hotel_reviews/2", None).to_request()).await;
+    assert!(reviews.is_empty());
+
+    let resp = test::call_service(&app,
+        get("/get_hotel_reviews/1?order=sideways", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Hotel 3 in Halifax with five reviews of its pool, one scoring higher.
+fn search_state() -> AppState {
+    let repo = InMemoryRepository::new();
+    repo.insert_hotel(hotel(1, "Harbour Inn"));
+    repo.insert_hotel(hotel(2, "Lakeview Hotel"));
+    repo.insert_hotel(Hotel { city: "Halifax".to_string(), ..hotel(3, "Seaside Lodge") });
+    for (n, (title, text, rating)) in [
+        ("Quiet", "A quiet pool by the sea", 5),
+        ("Noisy", "The pool was noisy at night", 2),
+        ("Pool", "Pool pool pool", 4),
+        ("Fine", "Nice pool", 3),
+        ("Meh", "No pool to speak of, quiet though", 1),
+    ].into_iter().enumerate() {
+        repo.insert_review(Review {
+            title: title.to_string(), text: text.to_string(), ..review(3, n, rating)
+        });
+    }
+    AppState::in_memory(repo)
+}
+
+#[actix_web::test]
+async fn search_ranks_filters_and_pages() {
+    let app = init_app(search_state()).await;
+    let search = |query: &str| get(&format!("/search?{query}"), None).to_request();
+    let ids = |results: &Value, item: &str, id: &str| -> Vec<Value> {
+        results["hits"].as_array().unwrap().iter().map(|h| h[item][id].clone()).collect()
+    };
+
+    let resp = test::call_service(&app, search("q=+")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // The best match first, ties by id
+    let mut pages = vec![];
+    for page in 1..=3 {
+        let results: Value = test::call_and_read_body_json(&app,
+            search(&format!("q=pool&per_page=2&page={page}"))).await;
+        assert_eq!((results["total"].clone(), results["page"].clone()), (json!(5), json!(page)));
+        pages.push(ids(&results, "review", "review_id"));
+    }
+    assert_eq!(pages, [
+        vec![json!("3-2"), json!("3-0")], vec![json!("3-1"), json!("3-3")], vec![json!("3-4")],
+    ]);
+    let results: Value = test::call_and_read_body_json(&app,
+        search("q=pool&per_page=0&page=0")).await;
+    assert_eq!((results["page"].clone(), results["per_page"].clone()), (json!(1), json!(1)));
+    assert_eq!(results["hits"][0]["snippet"], "<mark>Pool</mark>. <mark>Pool</mark> \
+        <mark>pool</mark> <mark>pool</mark>");
+
+    let results: Value = test::call_and_read_body_json(&app,
+        search("q=pool&min_rating=4")).await;
+    assert_eq!(ids(&results, "review", "review_id"), [json!("3-2"), json!("3-0")]);
+    let results: Value = test::call_and_read_body_json(&app, search("q=pool&hotel_id=1")).await;
+    assert_eq!(results["total"], 0);
+
+    // Boolean mode takes required, excluded and prefix words, natural mode doesn't
+    let results: Value = test::call_and_read_body_json(&app,
+        search("q=%2Bpool+-noisy&mode=boolean")).await;
+    assert_eq!(results["total"], 4);
+    assert!(!ids(&results, "review", "review_id").contains(&json!("3-1")));
+    let results: Value = test::call_and_read_body_json(&app,
+        search("q=%2Bquie*&mode=boolean")).await;
+    assert_eq!(ids(&results, "review", "review_id"), [json!("3-0"), json!("3-4")]);
+    let results: Value = test::call_and_read_body_json(&app, search("q=quie*")).await;
+    assert_eq!(results["total"], 0);
+    let results: Value = test::call_and_read_body_json(&app, search("q=pool+-noisy")).await;
+    assert_eq!(results["total"], 5);
+
+    // Hotels match by name and city
+    let results: Value = test::call_and_read_body_json(&app, search("q=toronto&in=hotels")).await;
+    assert_eq!(ids(&results, "hotel", "hotel_id"), [json!(1), json!(2)]);
+    let results: Value = test::call_and_read_body_json(&app,
+        search("q=harbour+seaside&in=hotels&city=Halifax")).await;
+    assert_eq!(ids(&results, "hotel", "hotel_id"), [json!(3)]);
+    assert_eq!(results["hits"][0]["snippet"], "<mark>Seaside</mark> Lodge, Halifax");
+    let resp = test::call_service(&app, search("q=pool&in=rooms")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn users_register_and_log_in() {
+    let app = init_app(test_state()).await;
+    register(&app, "ann").await;
+
+    let taken = post("/add_user", None).set_json(json!({
+        "username": "ann", "email": "other@x.io", "password": PASSWORD,
+    })).to_request();
+    assert_eq!(test::call_service(&app, taken).await.status(), StatusCode::BAD_REQUEST);
+    let short = post("/add_user", None).set_json(json!({
+        "username": "ben", "email": "ben@x.io", "password": "short",
+    })).to_request();
+    assert_eq!(test::call_service(&app, short).await.status(), StatusCode::BAD_REQUEST);
+
+    let exists: bool = test::call_and_read_body_json(&app,
+        get("/check_user/ann&nobody@x.io", None).to_request()).await;
+    assert!(exists);
+    let exists: bool = test::call_and_read_body_json(&app,
+        get("/check_user/ben&ben@x.io", None).to_request()).await;
+    assert!(!exists);
+
+    let tokens = login(&app, "ann").await;
+    assert!(tokens["token"].is_string() && tokens["refresh_token"].is_string());
+    // By email too
+    login(&app, "ann@x.io").await;
+    assert_eq!(login_as(&app, "ann", "wrong password").await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(login_as(&app, "nobody", PASSWORD).await.status(), StatusCode::UNAUTHORIZED);
+    // Seed users have no password
+    assert_eq!(login_as(&app, "chet", PASSWORD).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn users_change_only_their_own_records() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (ann, ann_token) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (ben, _) = user_with_role(&app, &state, "ben", Role::User).await;
+
+    let user: Value = test::call_and_read_body_json(&app,
+        get(&format!("/get_user/{ann}"), None).to_request()).await;
+    assert_eq!(user["username"], "ann");
+
+    let update = |id: i32, username: &str| post("/update", Some(&ann_token))
+        .set_form([("id", id.to_string()), ("username", username.to_string()),
+            ("email", format!("{username}@x.io"))])
+        .to_request();
+    assert_eq!(test::call_service(&app, update(ann, "annie")).await.status(), StatusCode::OK);
+    assert_eq!(state.users.get_user(ann).await.unwrap().unwrap().username, "annie");
+    let resp = test::call_service(&app, update(ben, "benny")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp = test::call_service(&app,
+        get(&format!("/delete/{ben}"), Some(&ann_token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = test::call_service(&app, get(&format!("/delete/{ann}"), None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app,
+        get(&format!("/delete/{ann}"), Some(&ann_token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(state.users.get_user(ann).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn admins_manage_users() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (_, user_token) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (admin, admin_token) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let ann = state.users.get_user_by_creds("ann", "").await.unwrap().unwrap().id;
+
+    let resp = test::call_service(&app, get("/get_all_users", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app,
+        get("/get_all_users", Some(&user_token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!((denied["role"].clone(), denied["required_roles"].clone()),
+        (json!("user"), json!(["admin"])));
+    let users: Vec<Value> = test::call_and_read_body_json(&app,
+        get("/get_all_users", Some(&admin_token)).to_request()).await;
+    assert_eq!(users.len(), 8);
+
+    let set_role = |id: i32, token: &str, role: &str| {
+        post(&format!("/set_role/{id}"), Some(token))
+            .set_json(json!({ "role": role })).to_request()
+    };
+    let resp = test::call_service(&app, set_role(ann, &user_token, "admin")).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = test::call_service(&app, set_role(ann, &admin_token, "moderator")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(state.users.get_user(ann).await.unwrap().unwrap().role, Role::Moderator);
+    let resp = test::call_service(&app, set_role(admin, &admin_token, "user")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, set_role(999, &admin_token, "user")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // Admins may change anyone's records
+    let resp = test::call_service(&app,
+        get(&format!("/delete/{ann}"), Some(&admin_token)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn reviews_belong_to_the_token_user() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (ann, ann_token) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (ben, ben_token) = user_with_role(&app, &state, "ben", Role::User).await;
+    let (_, mod_token) = user_with_role(&app, &state, "mo", Role::Moderator).await;
+    let (_, admin_token) = user_with_role(&app, &state, "root", Role::Admin).await;
+
+    // A 'user_id' in the body doesn't pick the author
+    for _ in 0..2 {
+        let resp = test::call_service(&app, post("/add_user_review", Some(&ann_token))
+            .set_json(json!({ "user_id": ben, "hotel_id": 2, "title": "Stay", "text": "Fine" }))
+            .to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let reviews = state.user_reviews.get_user_reviews_by_hotel_id(2).await.unwrap();
+    assert!(reviews.iter().all(|r| r.user_id == ann), "{reviews:?}");
+
+    let delete = |id: i32, token: &str| {
+        get(&format!("/delete_user_review/{id}"), Some(token)).to_request()
+    };
+    let resp = test::call_service(&app, delete(reviews[0].review_id, &ben_token)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let denied: Value = test::read_body_json(resp).await;
+    assert_eq!(denied, json!({
+        "error": "forbidden",
+        "message": "Belongs to another user, changing it requires the role moderator or admin",
+        "role": "user", "required_roles": ["moderator", "admin"],
+    }));
+
+    // Moderators and admins may delete anyone's reviews
+    for (review, token) in reviews.iter().zip([&mod_token, &admin_token]) {
+        let resp = test::call_service(&app, delete(review.review_id, token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    assert!(state.user_reviews.get_user_reviews_by_hotel_id(2).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn accounts_of_other_users_need_an_admin() {
+    let state = test_state();
+    let app = init_app(state.clone()).await;
+    let (ann, _) = user_with_role(&app, &state, "ann", Role::User).await;
+    let (_, ben_token) = user_with_role(&app, &state, "ben", Role::User).await;
+    let (_, mod_token) = user_with_role(&app, &state, "mo", Role::Moderator).await;
+    let (_, admin_token) = user_with_role(&app, &state, "root", Role::Admin).await;
+    let update = |token: &str| post("/update", Some(token))
+        .set_form([("id", ann.to_string()), ("username", "annie".to_string()),
+            ("email", "annie@x.io".to_string())])
+        .to_request();
+    let delete = |token: &str| get(&format!("/delete/{ann}"), Some(token)).to_request();
+
+    for (token, role) in [(&ben_token, "user"), (&mod_token, "moderator")] {
+        for req in [update(token), delete(token)] {
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            let denied: Value = test::read_body_json(resp).await;
+            assert_eq!(denied, json!({
+                "error": "forbidden",
+                "message": "Belongs to another user, changing it requires the role admin",
+                "role": role, "required_roles": ["admin"],
+            }));
+        }
+    }
+    assert_eq!(state.users.get_user(ann).await.unwrap().unwrap().username, "ann");
+
+    assert_eq!(test::call_service(&app, update(&admin_token)).await.status(), StatusCode::OK);
+    assert_eq!(state.users.get_user(ann).await.unwrap().unwrap().username, "annie");
+    assert_eq!(test::call_service(&app, delete(&admin_token)).await.status(), StatusCode::OK);
+    assert!(state.users.get_user(ann).await.unwrap().is_none());
+}
@@ -0,0 +1,231 @@
+//! Runs the SQLite backend against a database file in a temporary directory:
+//! cargo t --features sqlite
+#![cfg(feature = "sqlite")]
+mod common;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Value};
+use futures::future::join_all;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use tempfile::TempDir;
+
+use common::*;
+use data_parser::database::sqlite::SqliteRepository;
+use data_parser::database::*;
+
+/// A migrated database, deleted with the directory.
+async fn test_pool() -> (TempDir, SqlitePool) {
+    let dir = tempfile::tempdir().unwrap();
+    let options = SqliteConnectOptions::new()
+        .filename(dir.path().join("hotels.db")).create_if_missing(true);
+    let pool = SqlitePoolOptions::new().max_connections(8)
+        .connect_with(options).await.unwrap();
+    migrate_up(&pool, None).await.unwrap();
+    (dir, pool)
+}
+
+#[actix_web::test]
+async fn concurrent_user_review_writes_keep_summaries_right() {
+    let (_dir, pool) = test_pool().await;
+    pool.add_users().await.unwrap();
+    pool.add_hotels_data(HashMap::from([(1, hotel(1, "Harbour Inn"))]),
+        &LoadOptions::default()).await.unwrap();
+    let repo = SqliteRepository::new(pool);
+    let users = repo.get_all_users().await.unwrap();
+    let new_review = |n: usize| NewUserReview {
+        user_id: users[n % users.len()].id, hotel_id: 1, title: "Stay".to_string(),
+        text: "Nice enough".to_string(), rating: (!n.is_multiple_of(6)).then_some((n % 6) as i32),
+    };
+    for n in 0..10 {
+        repo.add_user_review(&new_review(n)).await.unwrap();
+    }
+    let existing = repo.get_user_reviews_by_hotel_id(1).await.unwrap();
+
+    let adds: Vec<NewUserReview> = (10..30).map(new_review).collect();
+    let (added, deleted) = futures::join!(
+        join_all(adds.iter().map(|review| repo.add_user_review(review))),
+        join_all(existing.iter().map(|r| repo.delete_user_review(r.review_id))),
+    );
+    for result in added.into_iter().chain(deleted) {
+        result.unwrap();
+    }
+
+    let summary = repo.get_rating_summary(1).await.unwrap().unwrap();
+    let rated = adds.iter().filter(|review| review.rating.is_some()).count() as i32;
+    assert_eq!(summary.review_count, rated);
+    assert!(repo.rebuild_rating_summaries().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn only_a_new_email_needs_verifying_again() {
+    let (_dir, pool) = test_pool().await;
+    let repo = SqliteRepository::new(pool);
+    repo.add_user(&NewUser {
+        username: "ann".to_string(), email: "ann@x.io".to_string(), password_hash: None,
+    }).await.unwrap();
+    let user = repo.get_user_by_creds("ann", "").await.unwrap().unwrap();
+    assert!(repo.set_email_verified(user.id, "ann@x.io", Utc::now()).await.unwrap());
+
+    repo.update_user(&User { username: "annie".to_string(), ..user.clone() }).await.unwrap();
+    let account = repo.get_account(user.id).await.unwrap().unwrap();
+    assert_eq!(account.username, "annie");
+    assert!(account.email_verified_at.is_some());
+
+    repo.update_user(&User { email: "ann@y.io".to_string(), ..user }).await.unwrap();
+    let account = repo.get_account(user.id).await.unwrap().unwrap();
+    assert_eq!((account.email.as_str(), account.email_verified_at), ("ann@y.io", None));
+}
+
+/// Two users, one an admin with a verified email, two hotels and their
+/// reviews, and a rated and an unrated user review.
+async fn fill(pool: &SqlitePool) {
+    let reviews = HashMap::from([(1, vec![review(1, 0, 4), review(1, 1, 2)]), (2, vec![])]);
+    pool.add_hotels_data(HashMap::from([(1, hotel(1, "Harbour Inn")),
+        (2, hotel(2, "Lakeview Hotel"))]), &LoadOptions::default()).await.unwrap();
+    pool.add_reviews_data(reviews, &LoadOptions::default()).await.unwrap();
+
+    let repo = SqliteRepository::new(pool.clone());
+    for name in ["ann", "ben"] {
+        repo.add_user(&NewUser {
+            username: name.to_string(), email: format!("{name}@x.io"),
+            password_hash: Some(format!("$argon2id${name}")),
+        }).await.unwrap();
+    }
+    let ann = repo.get_user_by_creds("ann", "").await.unwrap().unwrap();
+    repo.set_role(ann.id, Role::Admin).await.unwrap();
+    repo.set_email_verified(ann.id, "ann@x.io", Utc::now()).await.unwrap();
+    for rating in [Some(5), None] {
+        repo.add_user_review(&NewUserReview {
+            user_id: ann.id, hotel_id: 2, title: "Stay".to_string(),
+            text: "Nice enough".to_string(), rating,
+        }).await.unwrap();
+    }
+}
+
+/// Every row a backup covers, and the rating summaries, as JSON.
+async fn snapshot(pool: &SqlitePool) -> Value {
+    let repo = SqliteRepository::new(pool.clone());
+    // The 'get_all' queries have no order, restored rows may come back in another
+    let mut users = repo.get_all_users().await.unwrap();
+    users.sort_by_key(|u| u.id);
+    let mut accounts = vec![];
+    for user in users {
+        accounts.push(repo.get_account(user.id).await.unwrap());
+    }
+    let mut all_hotels = repo.get_all_hotels().await.unwrap();
+    all_hotels.sort_by_key(|h| h.hotel_id);
+    let mut hotels = vec![];
+    for hotel in all_hotels {
+        let id = hotel.hotel_id;
+        hotels.push(json!({
+            "hotel": hotel,
+            "reviews": repo.get_hotel_reviews(id, &ReviewFilter::default()).await.unwrap(),
+            "user_reviews": repo.get_user_reviews_by_hotel_id(id).await.unwrap(),
+            "summary": repo.get_rating_summary(id).await.unwrap(),
+        }));
+    }
+    json!({ "users": accounts, "hotels": hotels })
+}
+
+#[actix_web::test]
+async fn backups_restore_what_they_took() {
+    let (dir, source) = test_pool().await;
+    fill(&source).await;
+    let archive = dir.path().join("hotels.tar.gz");
+    let manifest = create_backup(&source, &archive, &[]).await.unwrap();
+    let rows: Vec<usize> = manifest.tables.iter().map(|t| t.rows).collect();
+    assert_eq!((manifest.version, rows), (ARCHIVE_VERSION, vec![2, 2, 2, 2]));
+    assert_eq!(read_manifest(&archive).unwrap().tables.len(), 4);
+
+    let (_copy_dir, copy) = test_pool().await;
+    let opts = RestoreOptions { batch_size: 1, ..RestoreOptions::default() };
+    let report = restore_backup(&copy, &archive, &opts).await.unwrap();
+    assert_eq!((report.tables.len(), report.batches), (4, 8));
+    assert_eq!(snapshot(&copy).await, snapshot(&source).await);
+
+    // Restores only go into empty tables
+    assert!(matches!(restore_backup(&copy, &archive, &opts).await,
+        Err(BackupError::NotEmpty(BackupTable::Users))));
+
+    // Or some of them
+    let (_part_dir, part) = test_pool().await;
+    let opts = RestoreOptions { tables: vec![BackupTable::Hotels], ..RestoreOptions::default() };
+    let report = restore_backup(&part, &archive, &opts).await.unwrap();
+    assert_eq!(report.tables, [(BackupTable::Hotels, 2)]);
+    let repo = SqliteRepository::new(part);
+    assert_eq!(repo.get_all_hotels().await.unwrap().len(), 2);
+    assert!(repo.get_all_users().await.unwrap().is_empty());
+}
+
+/// Writes an archive of 'version' holding a user and a rated review of theirs.
+fn old_archive(path: &Path, version: u32, user: Value) {
+    let files = [
+        ("users.jsonl", user.to_string()),
+        ("hotels.jsonl", serde_json::to_string(&hotel(1, "Harbour Inn")).unwrap()),
+        ("user_reviews.jsonl", json!({
+            "review_id": 7, "user_id": 1, "hotel_id": 1, "title": "Stay",
+            "text": "Nice enough", "rating": 4, "created_at": "2020-01-01T00:00:00Z",
+        }).to_string()),
+    ];
+    let manifest = json!({
+        "version": version, "created_at": "2020-01-01T00:00:00Z", "schema_version": 7,
+        "tables": [
+            { "table": "users", "file": "users.jsonl", "rows": 1 },
+            { "table": "hotels", "file": "hotels.jsonl", "rows": 1 },
+            { "table": "user_reviews", "file": "user_reviews.jsonl", "rows": 1 },
+        ],
+    }).to_string();
+
+    let gz = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+    let mut archive = tar::Builder::new(gz);
+    for (name, data) in [("manifest.json", manifest)].into_iter().chain(files.map(
+        |(name, data)| (name, format!("{data}\n")))) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, name, data.as_bytes()).unwrap();
+    }
+    archive.into_inner().unwrap().finish().unwrap();
+}
+
+#[actix_web::test]
+async fn older_archives_are_upgraded() {
+    let dir = tempfile::tempdir().unwrap();
+    let user = json!({ "id": 1, "username": "ann", "email": "ann@x.io" });
+    let with = |extra: Value| {
+        let mut user = user.clone();
+        user.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        user
+    };
+    for (version, user, password_hash, role) in [
+        (1, user.clone(), None, Role::User),
+        (2, with(json!({ "password_hash": "h" })), Some("h"), Role::User),
+        (3, with(json!({ "password_hash": "h", "role": "moderator",
+            "email_verified_at": "2020-01-01T00:00:00Z" })), Some("h"), Role::Moderator),
+    ] {
+        let archive = dir.path().join(format!("v{version}.tar.gz"));
+        old_archive(&archive, version, user);
+        let (_db_dir, pool) = test_pool().await;
+        restore_backup(&pool, &archive, &RestoreOptions::default()).await.unwrap();
+
+        let repo = SqliteRepository::new(pool);
+        let account = repo.get_account(1).await.unwrap().unwrap();
+        assert_eq!((account.password_hash.as_deref(), account.role), (password_hash, role),
+            "version {version}");
+        assert_eq!(account.email_verified_at, None, "version {version}");
+        let summary = repo.get_rating_summary(1).await.unwrap().unwrap();
+        assert_eq!((summary.review_count, summary.rating_sum), (1, 4), "version {version}");
+    }
+
+    let archive = dir.path().join("future.tar.gz");
+    old_archive(&archive, ARCHIVE_VERSION + 1, user);
+    let (_db_dir, pool) = test_pool().await;
+    assert!(matches!(restore_backup(&pool, &archive, &RestoreOptions::default()).await,
+        Err(BackupError::Format(_))));
+}